@@ -0,0 +1,77 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+
+/// Per-frame automatic gain control: scales every frame so the largest value becomes 1.0.
+///
+/// Frames whose largest value is below `floor` are scaled as if their max was `floor`, so near
+/// silence isn't blown up to full height.
+pub struct Agc {
+    floor: VizFloat,
+}
+
+impl Agc {
+    pub fn new(floor: VizFloat) -> Self {
+        Self { floor }
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Agc {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let mut max = self.floor;
+        input.iter().for_each(|v| {
+            v.channels().for_each(|v| {
+                if *v > max {
+                    max = *v
+                }
+            })
+        });
+
+        let scale = 1.0 / max;
+        input
+            .iter_mut()
+            .for_each(move |v| v.as_mut_ref().for_each(move |v| *v *= scale));
+        Ok(Some(input))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::agc::Agc;
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+
+    #[test]
+    fn agc_scales_max_to_one() {
+        let mut agc = Agc::new(0.05);
+        let mut frame = vec![
+            Channeled::Mono(0.1),
+            Channeled::Mono(0.2),
+            Channeled::Mono(0.4),
+        ];
+        let out = agc
+            .map(&mut frame)
+            .expect("should map")
+            .expect("should have frame");
+        assert_eq!(
+            out,
+            &[
+                Channeled::Mono(0.25),
+                Channeled::Mono(0.5),
+                Channeled::Mono(1.0)
+            ]
+        );
+
+        // quieter than the floor, only scaled up to the floor
+        let mut frame = vec![Channeled::Mono(0.01), Channeled::Mono(0.02)];
+        let out = agc
+            .map(&mut frame)
+            .expect("should map")
+            .expect("should have frame");
+        assert_eq!(out, &[Channeled::Mono(0.2), Channeled::Mono(0.4)]);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct AutoDbConfig {
+    /// how much of the start of playback the dB range is learned from
+    pub seconds: VizFloat,
+    /// percentile (0..1) of the dB values which is shown at min_db
+    #[serde(default = "default_low")]
+    pub low: VizFloat,
+    /// percentile (0..1) of the dB values which is shown at max_db
+    #[serde(default = "default_high")]
+    pub high: VizFloat,
+}
+
+fn default_low() -> VizFloat {
+    0.05
+}
+
+fn default_high() -> VizFloat {
+    0.99
+}
+
+/// Learns the loudness range of the source from the dB values of the first frames (estimating
+/// two percentiles as they stream by), then maps that range onto `min_db..max_db` for every frame
+/// after it. Frames are passed through unchanged until the range is locked in.
+pub struct AutoDb {
+    low: P2Quantile,
+    high: P2Quantile,
+    learn_frames: usize,
+    seen_frames: usize,
+    min_db: VizFloat,
+    max_db: VizFloat,
+    db_floor: VizFloat,
+    locked: Option<(VizFloat, VizFloat)>,
+}
+
+impl AutoDb {
+    pub fn new(
+        config: AutoDbConfig,
+        fps: u64,
+        min_db: VizFloat,
+        max_db: VizFloat,
+        db_floor: VizFloat,
+    ) -> Self {
+        Self {
+            low: P2Quantile::new(config.low),
+            high: P2Quantile::new(config.high),
+            learn_frames: (config.seconds * (fps as VizFloat)).ceil() as usize,
+            seen_frames: 0,
+            min_db,
+            max_db,
+            db_floor,
+            locked: None,
+        }
+    }
+
+    fn learn(&mut self, input: &[Channeled<VizFloat>]) {
+        let db_floor = self.db_floor;
+        for value in input {
+            for v in value.channels() {
+                // silence would drag the low percentile down to the floor
+                if *v > db_floor && v.is_finite() {
+                    self.low.push(*v);
+                    self.high.push(*v);
+                }
+            }
+        }
+
+        self.seen_frames += 1;
+        if self.seen_frames < self.learn_frames {
+            return;
+        }
+
+        match (self.low.estimate(), self.high.estimate()) {
+            (Some(low), Some(high)) if high > low => {
+                println!("auto dB: locked range to {:.1}dB..{:.1}dB", low, high);
+                self.locked = Some((low, high));
+            }
+            _ => {
+                // nothing but silence so far, keep learning
+                self.seen_frames = 0;
+            }
+        }
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for AutoDb {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        if let Some((low, high)) = self.locked {
+            let min_db = self.min_db;
+            let scale = (self.max_db - min_db) / (high - low);
+            input.iter_mut().for_each(move |v| {
+                v.as_mut_ref()
+                    .for_each(move |v| *v = min_db + ((*v - low) * scale))
+            });
+        } else {
+            self.learn(input);
+        }
+
+        Ok(Some(input))
+    }
+}
+
+/// Streaming estimate of the `p` quantile using the P² algorithm (Jain & Chlamtac, 1985).
+///
+/// Only five markers are kept: the minimum, the maximum, the estimate itself and two markers
+/// halfway to the ends. Each new value shifts the marker positions, and markers which drift from
+/// their desired position are moved with a piecewise parabolic prediction of the height.
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    p: VizFloat,
+    count: usize,
+    // marker heights
+    q: [VizFloat; 5],
+    // actual marker positions
+    n: [isize; 5],
+    // desired marker positions, and how much they move per value
+    desired: [VizFloat; 5],
+    increments: [VizFloat; 5],
+}
+
+impl P2Quantile {
+    pub fn new(p: VizFloat) -> Self {
+        Self {
+            p,
+            count: 0,
+            q: [0.0; 5],
+            n: [0, 1, 2, 3, 4],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + (2.0 * p), 4.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn push(&mut self, x: VizFloat) {
+        // the first five values are the initial markers
+        if self.count < 5 {
+            self.q[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.q
+                    .sort_by(|a, b| a.partial_cmp(b).expect("values should be finite"));
+            }
+            return;
+        }
+
+        self.count += 1;
+        let k = if x < self.q[0] {
+            self.q[0] = x;
+            0
+        } else if x >= self.q[4] {
+            self.q[4] = x;
+            3
+        } else {
+            (1..5).find(|i| x < self.q[*i]).expect("x is below q[4]") - 1
+        };
+
+        for i in (k + 1)..5 {
+            self.n[i] += 1;
+        }
+
+        for i in 0..5 {
+            self.desired[i] += self.increments[i];
+        }
+
+        for i in 1..4 {
+            let d = self.desired[i] - (self.n[i] as VizFloat);
+            if (d >= 1.0 && self.n[i + 1] - self.n[i] > 1)
+                || (d <= -1.0 && self.n[i - 1] - self.n[i] < -1)
+            {
+                let d = if d > 0.0 { 1 } else { -1 };
+                let parabolic = self.parabolic(i, d);
+                self.q[i] = if self.q[i - 1] < parabolic && parabolic < self.q[i + 1] {
+                    parabolic
+                } else {
+                    self.linear(i, d)
+                };
+                self.n[i] += d;
+            }
+        }
+    }
+
+    /// `None` until a value has been pushed
+    pub fn estimate(&self) -> Option<VizFloat> {
+        match self.count {
+            0 => None,
+            n if n < 5 => {
+                let mut seen = self.q[..n].to_vec();
+                seen.sort_by(|a, b| a.partial_cmp(b).expect("values should be finite"));
+                let idx = ((n - 1) as VizFloat * self.p).round() as usize;
+                Some(seen[idx])
+            }
+            _ => Some(self.q[2]),
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: isize) -> VizFloat {
+        let (q, n) = (&self.q, &self.n);
+        let d = d as VizFloat;
+        let n_prev = n[i - 1] as VizFloat;
+        let n_cur = n[i] as VizFloat;
+        let n_next = n[i + 1] as VizFloat;
+
+        q[i] + (d / (n_next - n_prev))
+            * ((n_cur - n_prev + d) * (q[i + 1] - q[i]) / (n_next - n_cur)
+                + (n_next - n_cur - d) * (q[i] - q[i - 1]) / (n_cur - n_prev))
+    }
+
+    fn linear(&self, i: usize, d: isize) -> VizFloat {
+        let j = ((i as isize) + d) as usize;
+        self.q[i]
+            + (d as VizFloat) * (self.q[j] - self.q[i]) / ((self.n[j] - self.n[i]) as VizFloat)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::auto_db::{AutoDb, AutoDbConfig, P2Quantile};
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+    use crate::util::VizFloat;
+
+    // deterministic shuffle of 0..n (n must be prime for this to be a permutation)
+    fn shuffled(n: usize) -> impl Iterator<Item = VizFloat> {
+        (0..n).map(move |i| ((i * 7919) % n) as VizFloat)
+    }
+
+    #[test]
+    fn p2_tracks_the_median() {
+        let mut median = P2Quantile::new(0.5);
+        assert_eq!(median.estimate(), None);
+        shuffled(10007).for_each(|v| median.push(v));
+
+        let estimate = median.estimate().expect("should have an estimate");
+        assert!(
+            (estimate - 5003.0).abs() < 100.0,
+            "median estimate {} too far from 5003",
+            estimate
+        );
+    }
+
+    #[test]
+    fn p2_tracks_high_percentiles() {
+        let mut p90 = P2Quantile::new(0.9);
+        shuffled(10007).for_each(|v| p90.push(v));
+
+        let estimate = p90.estimate().expect("should have an estimate");
+        assert!(
+            (estimate - 9006.0).abs() < 100.0,
+            "p90 estimate {}",
+            estimate
+        );
+    }
+
+    #[test]
+    fn auto_db_locks_in_and_remaps() {
+        let config = AutoDbConfig {
+            seconds: 1.0,
+            low: 0.0,
+            high: 1.0,
+        };
+        let mut auto = AutoDb::new(config, 2, -30.0, -10.0, -200.0);
+
+        // learning frames pass through, silence is ignored
+        let mut frame = vec![Channeled::Mono(-60.0), Channeled::Mono(-200.0)];
+        let out = auto
+            .map(&mut frame)
+            .expect("should map")
+            .expect("has frame");
+        assert_eq!(out, &[Channeled::Mono(-60.0), Channeled::Mono(-200.0)]);
+        assert_eq!(auto.locked, None);
+
+        let mut frame = vec![Channeled::Mono(-20.0), Channeled::Mono(-40.0)];
+        auto.map(&mut frame).expect("should map");
+        assert_eq!(auto.locked, Some((-60.0, -20.0)));
+
+        // -60..-20 is shown as -30..-10
+        let mut frame = vec![Channeled::Mono(-60.0), Channeled::Mono(-40.0)];
+        let out = auto
+            .map(&mut frame)
+            .expect("should map")
+            .expect("has frame");
+        assert_eq!(out, &[Channeled::Mono(-30.0), Channeled::Mono(-20.0)]);
+    }
+}
@@ -1,27 +1,116 @@
 use crate::channeled::Channeled;
 use crate::framed::FramedMapper;
-use crate::util::{log_timed, VizFloat};
-use anyhow::Result;
+use crate::util::{fft_bin_hz, log_timed, VizFloat};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 
 pub struct Binner {
     indexes: Vec<usize>,
+    // (source index, output bin, weight), only present for overlapping bins
+    weights: Option<Vec<(usize, usize, VizFloat)>>,
+    buf: Vec<Channeled<VizFloat>>,
     n_bins: usize,
     in_size: usize,
+    // edges of every output bin in Hz
+    edges_hz: Vec<VizFloat>,
+    // what each output bin's sum is divided by
+    divisors: Vec<VizFloat>,
+}
+
+/// The frequencies covered by an output bin
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct BinFrequency {
+    pub low_hz: VizFloat,
+    pub high_hz: VizFloat,
+    /// geometric mean of the edges (arithmetic mean for a bin starting at 0Hz)
+    pub center_hz: VizFloat,
+}
+
+/// How source (FFT) bins are assigned to output bins
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BinOverlap {
+    /// every source bin belongs to exactly one output bin
+    #[default]
+    None,
+    /// source bins are shared between the two nearest output bins with triangular weights (like a
+    /// filterbank), so energy on a boundary doesn't jump from one bin to another
+    Triangular,
+}
+
+/// What the summed magnitudes of an output bin are divided by
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BinNormalization {
+    /// the FFT size, the same for every bin, so wide (high) bins read louder than narrow (low)
+    /// bins with the same energy per source bin
+    #[default]
+    FftSize,
+    /// the number of source bins in the bin (their weights when bins overlap or are
+    /// interpolated), the average magnitude of the source bins
+    Width,
 }
 
 impl Binner {
-    pub fn new(config: BinConfig) -> Self {
+    pub fn new(config: BinConfig) -> Result<Self> {
         log_timed(format!("compute bin constants for {:?}", &config), || {
-            let indexes = compute_bin_indexes(&config, config.bins);
+            let (indexes, weights) = if config.interpolate_low_bins {
+                let (indexes, weights) = interpolated_weights(&config);
+                (indexes, Some(weights))
+            } else {
+                let indexes = match &config.scale {
+                    BinScale::Gamma => compute_bin_indexes(&config, config.bins)?,
+                    BinScale::Explicit(edges) => explicit_bin_indexes(&config, edges.as_slice()),
+                };
+                let weights = match config.overlap {
+                    BinOverlap::None => None,
+                    BinOverlap::Triangular => Some(triangular_weights(indexes.as_slice())),
+                };
+                (indexes, weights)
+            };
             let n_bins = indexes.len() - 1;
             let in_size = config.input_size;
-            Self {
+            let edges_hz = if config.interpolate_low_bins {
+                config.edge_frequencies()
+            } else {
+                // bins start at whole source bins, which aren't exactly at the configured edges
+                indexes.iter().map(|idx| config.src_bin_hz(*idx)).collect()
+            };
+            let divisors = match config.normalization {
+                BinNormalization::FftSize => vec![in_size as VizFloat; n_bins],
+                BinNormalization::Width => bin_widths(&indexes, weights.as_deref(), n_bins),
+            };
+            Ok(Self {
                 indexes,
+                weights,
+                buf: Vec::with_capacity(n_bins),
                 n_bins,
                 in_size,
-            }
+                edges_hz,
+                divisors,
+            })
         })
     }
+
+    /// The frequency range of every output bin, in the order they are output
+    pub fn bin_frequencies(&self) -> Vec<BinFrequency> {
+        self.edges_hz
+            .windows(2)
+            .map(move |win| {
+                let (low_hz, high_hz) = (win[0], win[1]);
+                let center_hz = if low_hz > 0.0 {
+                    (low_hz * high_hz).sqrt()
+                } else {
+                    (low_hz + high_hz) / 2.0
+                };
+                BinFrequency {
+                    low_hz,
+                    high_hz,
+                    center_hz,
+                }
+            })
+            .collect()
+    }
 }
 
 impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Binner {
@@ -33,11 +122,30 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Binner {
             return Ok(None);
         }
 
+        if let Some(weights) = &self.weights {
+            let buf = &mut self.buf;
+            buf.clear();
+            buf.resize(self.n_bins, input[0].as_ref().map(move |_| 0.0));
+            for (src_idx, bin_idx, weight) in weights.iter().copied() {
+                let elem = &input[src_idx];
+                if elem.channels().all(move |elem| elem.is_finite()) {
+                    buf[bin_idx]
+                        .as_mut_ref()
+                        .zip(elem.as_ref())
+                        .expect("mixed stereo/mono?")
+                        .for_each(move |(c, v)| *c += v * weight);
+                }
+            }
+
+            divide(buf.as_mut_slice(), &self.divisors);
+            return Ok(Some(buf.as_mut_slice()));
+        }
+
         let mut bin_idx = 0usize;
         let idx_slice = self.indexes.as_slice();
         let mut zeroed_bin_idx = 0;
         for idx in 0..self.in_size {
-            let elem = input[idx];
+            let elem = input[idx].clone();
             let this_bin_start_at = &idx_slice[bin_idx];
             if idx < *this_bin_start_at {
                 continue;
@@ -52,7 +160,7 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Binner {
                 break;
             }
 
-            if elem.map(move |elem| elem.is_finite()).and() {
+            if elem.channels().all(move |elem| elem.is_finite()) {
                 if bin_idx > idx {
                     panic!(
                         "can't use bin_idx in input slice {} is bin but idx avail is {}",
@@ -61,21 +169,19 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Binner {
                 }
 
                 while zeroed_bin_idx <= bin_idx {
-                    input[zeroed_bin_idx] = elem.map(move |_| 0.0);
+                    input[zeroed_bin_idx] = elem.as_ref().map(move |_| 0.0);
                     zeroed_bin_idx += 1;
                 }
 
-                input[bin_idx] = input[bin_idx]
-                    .zip(elem)
+                input[bin_idx]
+                    .as_mut_ref()
+                    .zip(elem.as_ref())
                     .expect("mixed stereo/mono?")
-                    .map(move |(c, v)| c + v);
+                    .for_each(move |(c, v)| *c += v);
             }
         }
 
-        let in_size = self.in_size as VizFloat;
-        input
-            .iter_mut()
-            .for_each(move |e| e.as_mut_ref().for_each(move |v| *v /= in_size));
+        divide(&mut input[..bin_idx], &self.divisors);
         Ok(Some(&mut input[..bin_idx]))
     }
 
@@ -84,7 +190,40 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Binner {
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+fn divide(bins: &mut [Channeled<VizFloat>], divisors: &[VizFloat]) {
+    bins.iter_mut()
+        .zip(divisors.iter().copied())
+        .for_each(move |(e, d)| e.as_mut_ref().for_each(move |v| *v /= d));
+}
+
+// the number of source bins (or the sum of their weights) in every output bin, never 0 so empty
+// bins stay 0 instead of NaN
+fn bin_widths(
+    indexes: &[usize],
+    weights: Option<&[(usize, usize, VizFloat)]>,
+    n_bins: usize,
+) -> Vec<VizFloat> {
+    let widths = match weights {
+        Some(weights) => {
+            let mut widths = vec![0.0; n_bins];
+            for (_, bin_idx, weight) in weights.iter().copied() {
+                widths[bin_idx] += weight;
+            }
+            widths
+        }
+        None => indexes
+            .windows(2)
+            .map(move |win| (win[1] - win[0]) as VizFloat)
+            .collect(),
+    };
+
+    widths
+        .into_iter()
+        .map(move |w| if w > 0.0 { w } else { 1.0 })
+        .collect()
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub struct BinConfig {
     pub bins: usize,
     pub input_size: usize,
@@ -92,16 +231,172 @@ pub struct BinConfig {
     pub fmin: VizFloat,
     pub fmax: VizFloat,
     pub gamma: VizFloat,
+    pub scale: BinScale,
+    pub overlap: BinOverlap,
+    pub interpolate_low_bins: bool,
+    pub normalization: BinNormalization,
 }
 
-fn compute_bin_indexes(config: &BinConfig, num_bins: usize) -> Vec<usize> {
-    let total_max_freq = (config.sample_rate as VizFloat) / 2.0;
-    let bandwidth_per_src_bin = total_max_freq / (config.input_size as VizFloat);
+/// Where the edges of the output bins are placed
+#[derive(PartialEq, Clone, Debug)]
+pub enum BinScale {
+    /// `bins` bins between `fmin` and `fmax`, spaced by the `gamma` curve
+    Gamma,
+    /// bins between each pair of consecutive edges (in Hz), `bins`, `fmin`, `fmax` and `gamma` are
+    /// not used
+    Explicit(Vec<VizFloat>),
+}
+
+impl BinConfig {
+    /// checks the parts of the config which depend on the FFT size and sample rate
+    pub fn validate(&self) -> Result<()> {
+        if let BinScale::Explicit(edges) = &self.scale {
+            let nyquist = (self.sample_rate as VizFloat) / 2.0;
+            if let Some(over) = edges.iter().find(move |e| **e > nyquist) {
+                return Err(anyhow!(
+                    "bin edge {}Hz is above the nyquist frequency {}Hz",
+                    over,
+                    nyquist
+                ));
+            }
+
+            if edges.windows(2).any(move |win| win[0] >= win[1]) {
+                return Err(anyhow!("bin edges must be sorted, got {:?}", edges));
+            }
+
+            if !self.interpolate_low_bins {
+                let indexes = explicit_bin_indexes(self, edges.as_slice());
+                if let Some(idx) = indexes.windows(2).position(move |win| win[0] >= win[1]) {
+                    return Err(anyhow!(
+                        "bin edges {}Hz and {}Hz are closer than the FFT resolution, \
+                         increase data_window_ms or enable interpolate_low_bins",
+                        edges[idx],
+                        edges[idx + 1]
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // frequency of the source magnitude at `idx`, the FFT skipped DC so it is FFT bin `idx + 1`
+    fn src_bin_hz(&self, idx: usize) -> VizFloat {
+        fft_bin_hz(idx + 1, self.input_size * 2, self.sample_rate)
+    }
+
+    // the (fractional) source index at `hz`, the inverse of `src_bin_hz`
+    fn src_index(&self, hz: VizFloat) -> VizFloat {
+        (hz / fft_bin_hz(1, self.input_size * 2, self.sample_rate)) - 1.0
+    }
+
+    // edges of every output bin in Hz
+    fn edge_frequencies(&self) -> Vec<VizFloat> {
+        match &self.scale {
+            BinScale::Explicit(edges) => edges.clone(),
+            BinScale::Gamma => {
+                let n_bins = self.bins as VizFloat;
+                let freq_range = self.fmax - self.fmin;
+                (0..=self.bins)
+                    .map(move |b| {
+                        self.fmin + ((b as VizFloat) / n_bins).powf(self.gamma) * freq_range
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// maps every explicit edge (in Hz) to the first source bin at or above that frequency
+fn explicit_bin_indexes(config: &BinConfig, edges: &[VizFloat]) -> Vec<usize> {
+    edges
+        .iter()
+        .map(move |hz| config.src_index(*hz).max(0.0).ceil() as usize)
+        .map(move |idx| std::cmp::min(idx, config.input_size))
+        .collect()
+}
+
+// computes weights for exactly config.bins output bins using the exact (fractional) source index
+// of every bin edge
+//
+// bins which cover at least one whole source bin sum the source bins they cover, but bins which are
+// narrower than a single source bin (common at low frequencies) linearly interpolate the magnitude
+// at their center, scaled by their (fractional) width so they are comparable to their neighbors
+fn interpolated_weights(config: &BinConfig) -> (Vec<usize>, Vec<(usize, usize, VizFloat)>) {
+    let max_idx = (config.input_size - 1) as VizFloat;
+    let edges = config
+        .edge_frequencies()
+        .into_iter()
+        .map(move |hz| config.src_index(hz).clamp(0.0, max_idx))
+        .collect::<Vec<_>>();
+
+    let mut weights = Vec::new();
+    for (bin_idx, win) in edges.windows(2).enumerate() {
+        let (from, to) = (win[0], win[1]);
+        let (first, end) = (from.ceil() as usize, to.ceil() as usize);
+        if to - from >= 1.0 && end > first {
+            weights.extend((first..end).map(move |src_idx| (src_idx, bin_idx, 1.0)));
+        } else {
+            let width = to - from;
+            let center = (from + to) / 2.0;
+            let lower = center.floor();
+            let frac = center - lower;
+            let lower = lower as usize;
+            weights.push((lower, bin_idx, (1.0 - frac) * width));
+            if frac > 0.0 && lower + 1 < config.input_size {
+                weights.push((lower + 1, bin_idx, frac * width));
+            }
+        }
+    }
+
+    let indexes = edges.iter().map(move |e| e.floor() as usize).collect();
+    (indexes, weights)
+}
+
+// computes the triangular weight table for the bins described by indexes (bin i covers source
+// indexes indexes[i]..indexes[i + 1])
+//
+// each output bin has a peak at it's center, and source bins between two centers are split between
+// those two output bins in proportion to how close they are to each center
+fn triangular_weights(indexes: &[usize]) -> Vec<(usize, usize, VizFloat)> {
+    let n_bins = indexes.len() - 1;
+    let centers = indexes
+        .windows(2)
+        .map(move |win| ((win[0] + win[1]) as VizFloat - 1.0) / 2.0)
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::with_capacity(indexes[n_bins] - indexes[0]);
+    let mut bin_idx = 0usize;
+    for src_idx in indexes[0]..indexes[n_bins] {
+        let at = src_idx as VizFloat;
+        while bin_idx + 1 < n_bins && at >= centers[bin_idx + 1] {
+            bin_idx += 1;
+        }
+
+        let is_edge = (bin_idx == 0 && at <= centers[0]) || bin_idx + 1 >= n_bins;
+        if is_edge {
+            out.push((src_idx, bin_idx, 1.0));
+            continue;
+        }
+
+        let (from, to) = (centers[bin_idx], centers[bin_idx + 1]);
+        let weight = (to - at) / (to - from);
+        out.push((src_idx, bin_idx, weight));
+        if weight < 1.0 {
+            out.push((src_idx, bin_idx + 1, 1.0 - weight));
+        }
+    }
+
+    out
+}
+
+fn compute_bin_indexes(config: &BinConfig, num_bins: usize) -> Result<Vec<usize>> {
+    let total_max_freq = fft_bin_hz(config.input_size, config.input_size * 2, config.sample_rate);
     let gamma_inv = 1.0 / config.gamma;
     let n_bins = num_bins as VizFloat;
     let freq_range = config.fmax - config.fmin;
     let mut out = vec![None; num_bins + 1];
-    let hz_for_idx = move |idx: usize| (idx as VizFloat) * bandwidth_per_src_bin;
+    let hz_for_idx = move |idx: usize| config.src_bin_hz(idx);
     for i in 0..config.input_size {
         let f_start = hz_for_idx(i);
         if f_start < config.fmin {
@@ -147,6 +442,15 @@ fn compute_bin_indexes(config: &BinConfig, num_bins: usize) -> Vec<usize> {
         }
     }
 
+    if fin_out.len() < 2 {
+        return Err(anyhow!(
+            "no FFT bins between fmin {}Hz and fmax {}Hz (the FFT covers 0Hz..{}Hz)",
+            config.fmin,
+            config.fmax,
+            total_max_freq
+        ));
+    }
+
     let n_bins_out = fin_out.len() - 1;
     if n_bins_out < config.bins {
         println!(
@@ -185,6 +489,220 @@ fn compute_bin_indexes(config: &BinConfig, num_bins: usize) -> Vec<usize> {
             total_size, config.input_size, n_bins_out
         );
 
-        fin_out
+        Ok(fin_out)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::binner::{
+        interpolated_weights, triangular_weights, BinConfig, BinNormalization, BinOverlap,
+        BinScale, Binner,
+    };
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+
+    #[test]
+    fn triangular_weights_share_boundaries() {
+        // centers at 1.5, 5.5, 9.5
+        let weights = triangular_weights(&[0, 4, 8, 12]);
+        let for_src = |idx: usize| {
+            weights
+                .iter()
+                .filter(move |(src, _, _)| *src == idx)
+                .map(move |(_, bin, w)| (*bin, *w))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(for_src(0), vec![(0, 1.0)]);
+        assert_eq!(for_src(4), vec![(0, 0.375), (1, 0.625)]);
+        assert_eq!(for_src(8), vec![(1, 0.375), (2, 0.625)]);
+        assert_eq!(for_src(11), vec![(2, 1.0)]);
+
+        // every source bin keeps all of it's energy
+        for src in 0..12 {
+            let total: f64 = for_src(src).iter().map(|(_, w)| *w).sum();
+            assert!((total - 1.0).abs() < 1e-9, "src {} total {}", src, total);
+        }
+    }
+
+    #[test]
+    fn interpolate_narrow_low_bin() {
+        // 1Hz per source bin
+        let config = BinConfig {
+            bins: 8,
+            input_size: 64,
+            sample_rate: 128,
+            fmin: 1.0,
+            fmax: 60.0,
+            gamma: 2.0,
+            scale: BinScale::Gamma,
+            overlap: BinOverlap::None,
+            interpolate_low_bins: true,
+            normalization: BinNormalization::FftSize,
+        };
+        let (indexes, weights) = interpolated_weights(&config);
+        assert_eq!(indexes.len(), 9);
+
+        // magnitude ramp, so the "true" magnitude at source index x is x
+        let input = (0..64).map(|i| i as f64).collect::<Vec<_>>();
+        let bin_value = |bin: usize| -> f64 {
+            weights
+                .iter()
+                .filter(move |(_, b, _)| *b == bin)
+                .map(|(src, _, w)| input[*src] * *w)
+                .sum()
+        };
+
+        // bin 0 covers 1.0Hz..1.921875Hz, source indexes 0.0..0.921875 (DC is skipped, source
+        // index x is (x + 1)Hz), narrower than a single source bin
+        let (from, to) = (0.0, 59.0 / 64.0);
+        let center = (from + to) / 2.0;
+        let interpolated = bin_value(0) / (to - from);
+        let nearest = input[center.round() as usize];
+        assert!((interpolated - center).abs() < 1e-9, "got {}", interpolated);
+        assert!((nearest - center).abs() > 0.4);
+
+        // a wide bin just sums the source bins it covers
+        let wide_sum: f64 = weights
+            .iter()
+            .filter(|(_, b, _)| *b == 7)
+            .map(|(_, _, w)| *w)
+            .sum();
+        assert!(wide_sum >= 1.0);
+    }
+
+    fn octave_config(edges: Vec<f64>) -> BinConfig {
+        // 44100Hz / 4096 point FFT, ~10.77Hz per source bin
+        BinConfig {
+            bins: 0,
+            input_size: 2048,
+            sample_rate: 44100,
+            fmin: 0.0,
+            fmax: 0.0,
+            gamma: 1.0,
+            scale: BinScale::Explicit(edges),
+            overlap: BinOverlap::None,
+            interpolate_low_bins: false,
+            normalization: BinNormalization::FftSize,
+        }
+    }
+
+    #[test]
+    fn explicit_edges_make_exact_bins() {
+        let edges = vec![
+            31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+        ];
+        let config = octave_config(edges.clone());
+        config.validate().expect("should be valid");
+
+        let binner = Binner::new(config).expect("should bin");
+        assert_eq!(binner.n_bins, edges.len() - 1);
+        assert_eq!(
+            binner.indexes,
+            vec![2, 5, 11, 23, 46, 92, 185, 371, 743, 1486]
+        );
+
+        // every boundary is the first source bin at or above the edge, source index i is FFT bin
+        // i + 1 (DC is skipped)
+        let bw = 22050.0 / 2048.0;
+        for (edge, idx) in edges.iter().zip(binner.indexes.iter()) {
+            assert!(((*idx + 1) as f64) * bw >= *edge);
+            assert!((*idx as f64) * bw < *edge);
+        }
+
+        // and that's the frequency reported for the bin
+        let frequencies = binner.bin_frequencies();
+        assert_eq!(frequencies.len(), edges.len() - 1);
+        assert_eq!(frequencies[0].low_hz, 3.0 * bw);
+        assert_eq!(frequencies[0].high_hz, 6.0 * bw);
+        assert!((frequencies[0].center_hz - 18.0_f64.sqrt() * bw).abs() < 1e-9);
+    }
+
+    #[test]
+    fn explicit_edges_are_validated() {
+        let above_nyquist = octave_config(vec![100.0, 1000.0, 30000.0]);
+        assert!(above_nyquist.validate().is_err());
+
+        let unsorted = octave_config(vec![1000.0, 100.0, 2000.0]);
+        assert!(unsorted.validate().is_err());
+
+        // both in the same source bin
+        let too_close = octave_config(vec![100.0, 101.0, 2000.0]);
+        assert!(too_close.validate().is_err());
+    }
+
+    #[test]
+    fn frequency_range_without_source_bins_is_an_error() {
+        let config = BinConfig {
+            bins: 10,
+            fmin: 30000.0,
+            fmax: 40000.0,
+            gamma: 1.0,
+            scale: BinScale::Gamma,
+            ..octave_config(Vec::new())
+        };
+
+        match Binner::new(config) {
+            Ok(_) => panic!("should not bin above nyquist"),
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "no FFT bins between fmin 30000Hz and fmax 40000Hz (the FFT covers 0Hz..22050Hz)"
+            ),
+        }
+    }
+
+    #[test]
+    fn boundary_source_bin_contributes_to_both_neighbors() {
+        let indexes = vec![0, 4, 8, 12];
+        let mut binner = Binner {
+            weights: Some(triangular_weights(indexes.as_slice())),
+            indexes,
+            buf: Vec::new(),
+            n_bins: 3,
+            in_size: 12,
+            edges_hz: vec![0.0, 4.0, 8.0, 12.0],
+            divisors: vec![12.0; 3],
+        };
+
+        let mut input = vec![Channeled::Mono(0.0); 12];
+        input[4] = Channeled::Mono(12.0);
+        let out = binner.map(&mut input).expect("ok").expect("some").to_vec();
+        assert_eq!(
+            out,
+            vec![
+                Channeled::Mono(0.375),
+                Channeled::Mono(0.625),
+                Channeled::Mono(0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn width_normalization_averages_source_bins() {
+        // 1Hz per source bin, a 2 source bin wide bin next to an 8 source bin wide one
+        let config = |overlap, normalization| BinConfig {
+            input_size: 16,
+            sample_rate: 32,
+            scale: BinScale::Explicit(vec![1.0, 3.0, 11.0]),
+            overlap,
+            normalization,
+            ..octave_config(Vec::new())
+        };
+        let bin = |config| {
+            let mut binner = Binner::new(config).expect("should bin");
+            let mut input = vec![Channeled::Mono(2.0); 16];
+            binner.map(&mut input).expect("ok").expect("some").to_vec()
+        };
+
+        // the same energy per source bin is the same output, whatever the width
+        let width = bin(config(BinOverlap::None, BinNormalization::Width));
+        assert_eq!(width, vec![Channeled::Mono(2.0), Channeled::Mono(2.0)]);
+        let width = bin(config(BinOverlap::Triangular, BinNormalization::Width));
+        assert_eq!(width, vec![Channeled::Mono(2.0), Channeled::Mono(2.0)]);
+
+        // dividing by the FFT size, the wide bin reads 4x louder
+        let fft_size = bin(config(BinOverlap::None, BinNormalization::FftSize));
+        assert_eq!(fft_size, vec![Channeled::Mono(0.25), Channeled::Mono(1.0)]);
     }
 }
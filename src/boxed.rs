@@ -0,0 +1,130 @@
+use crate::framed::Framed;
+use anyhow::Result;
+use std::marker::PhantomData;
+
+/// Owned, type-erased `Framed` which can be moved to another thread (see `Framed::boxed`).
+///
+/// The inner type of the original pipeline is erased, so `into_deep_inner` gives `()`.
+pub struct BoxedFramed<E> {
+    source: Box<dyn DynFramed<E> + Send>,
+    // totals over the whole source, so they don't change while it's read
+    num_frames: usize,
+    num_full_frames: usize,
+}
+
+// object-safe part of Framed
+trait DynFramed<E> {
+    fn seek_frame(&mut self, n: isize) -> Result<()>;
+
+    fn next_frame(&mut self) -> Result<Option<&mut [E]>>;
+
+    fn num_frames_remain(&self) -> usize;
+
+    fn full_frame_size(&self) -> usize;
+}
+
+struct ErasedFramed<S, I> {
+    source: S,
+    _inner_typ: PhantomData<fn() -> I>,
+}
+
+impl<S, E, I> DynFramed<E> for ErasedFramed<S, I>
+where
+    S: Framed<E, I>,
+{
+    fn seek_frame(&mut self, n: isize) -> Result<()> {
+        self.source.seek_frame(n)
+    }
+
+    fn next_frame(&mut self) -> Result<Option<&mut [E]>> {
+        self.source.next_frame()
+    }
+
+    fn num_frames_remain(&self) -> usize {
+        self.source.num_frames_remain()
+    }
+
+    fn full_frame_size(&self) -> usize {
+        self.source.full_frame_size()
+    }
+}
+
+impl<E> BoxedFramed<E> {
+    pub fn new<S, I>(source: S) -> Self
+    where
+        S: Framed<E, I> + Send + 'static,
+        I: 'static,
+    {
+        Self {
+            num_frames: source.num_frames(),
+            num_full_frames: source.num_full_frames(),
+            source: Box::new(ErasedFramed {
+                source,
+                _inner_typ: PhantomData,
+            }),
+        }
+    }
+}
+
+impl<E> Framed<E, ()> for BoxedFramed<E> {
+    fn into_deep_inner(self) {}
+
+    fn seek_frame(&mut self, n: isize) -> Result<()> {
+        self.source.seek_frame(n)
+    }
+
+    fn next_frame(&mut self) -> Result<Option<&mut [E]>> {
+        self.source.next_frame()
+    }
+
+    fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    fn num_frames_remain(&self) -> usize {
+        self.source.num_frames_remain()
+    }
+
+    fn num_full_frames(&self) -> usize {
+        self.num_full_frames
+    }
+
+    fn full_frame_size(&self) -> usize {
+        self.source.full_frame_size()
+    }
+}
+
+impl<E> BoxedFramed<E>
+where
+    E: Clone,
+{
+    /// Iterator over an owned copy of every remaining frame, which can be sent to another thread
+    // an entry point for embedding, the binary reads its frames in place
+    #[allow(dead_code)]
+    pub fn into_owned_frames(self) -> OwnedFrames<E> {
+        OwnedFrames { source: self }
+    }
+}
+
+pub struct OwnedFrames<E> {
+    source: BoxedFramed<E>,
+}
+
+impl<E> Iterator for OwnedFrames<E>
+where
+    E: Clone,
+{
+    type Item = Result<Vec<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.source.next_frame() {
+            Ok(Some(frame)) => Some(Ok(frame.to_vec())),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.source.num_frames_remain()))
+    }
+}
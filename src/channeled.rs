@@ -1,11 +1,14 @@
 use anyhow::Result;
 use std::fmt;
 use std::iter::{FusedIterator, TrustedLen};
+use std::ops::{Add, Div};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Channeled<T> {
     Mono(T),
     Stereo(T, T),
+    /// more than two channels (quad, 5.1, ...), in the order of the file
+    Multi(Vec<T>),
 }
 
 impl<T> fmt::Display for Channeled<T>
@@ -17,6 +20,16 @@ where
         match self {
             Mono(v) => v.fmt(f),
             Stereo(a, b) => write!(f, "({}, {})", a, b),
+            Multi(vs) => {
+                write!(f, "(")?;
+                for (idx, v) in vs.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    v.fmt(f)?;
+                }
+                write!(f, ")")
+            }
         }
     }
 }
@@ -39,6 +52,7 @@ impl<T> Channeled<T> {
         match self {
             Stereo(a, b) => Stereo(f(a), f(b)),
             Mono(a) => Mono(f(a)),
+            Multi(vs) => Multi(vs.into_iter().map(f).collect()),
         }
     }
 
@@ -61,6 +75,7 @@ impl<T> Channeled<T> {
         match self {
             Stereo(a, b) => Stereo(a, b),
             Mono(v) => Mono(v),
+            Multi(vs) => Multi(vs.iter_mut().collect()),
         }
     }
 
@@ -69,6 +84,45 @@ impl<T> Channeled<T> {
         match self {
             Stereo(a, b) => Stereo(a, b),
             Mono(v) => Mono(v),
+            Multi(vs) => Multi(vs.iter().collect()),
+        }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        match self {
+            Channeled::Mono(_) => 1,
+            Channeled::Stereo(_, _) => 2,
+            Channeled::Multi(vs) => vs.len(),
+        }
+    }
+
+    /// the value of every channel, in the order of the file (left then right for stereo)
+    pub fn channels(&self) -> impl Iterator<Item = &T> {
+        use Channeled::*;
+        let (first, second, rest) = match self {
+            Stereo(a, b) => (Some(a), Some(b), &[][..]),
+            Mono(v) => (Some(v), None, &[][..]),
+            Multi(vs) => (None, None, vs.as_slice()),
+        };
+
+        first.into_iter().chain(second).chain(rest.iter())
+    }
+
+    /// average of all channels (zero without any)
+    pub fn to_mono(&self) -> T
+    where
+        T: Copy + Add<Output = T> + Div<Output = T> + From<u8>,
+    {
+        use Channeled::*;
+        match self {
+            Stereo(a, b) => (*a + *b) / T::from(2),
+            Mono(v) => *v,
+            Multi(vs) if vs.is_empty() => T::from(0),
+            Multi(vs) => {
+                let sum = vs.iter().fold(T::from(0), move |sum, v| sum + *v);
+                // the wav reader never has more than u8::MAX channels
+                sum / T::from(vs.len() as u8)
+            }
         }
     }
 
@@ -77,6 +131,9 @@ impl<T> Channeled<T> {
         match (self, other) {
             (Stereo(al, ar), Stereo(bl, br)) => Some(Stereo((al, bl), (ar, br))),
             (Mono(a), Mono(b)) => Some(Mono((a, b))),
+            (Multi(a), Multi(b)) if a.len() == b.len() => {
+                Some(Multi(a.into_iter().zip(b).collect()))
+            }
             _ => None
         }
     }
@@ -89,16 +146,20 @@ impl<R, X> Channeled<Result<R, X>> {
             Stereo(Ok(lv), Ok(rv)) => Ok(Stereo(lv, rv)),
             Mono(Ok(v)) => Ok(Mono(v)),
             Stereo(Err(err), _) | Stereo(_, Err(err)) | Mono(Err(err)) => Err(err),
+            Multi(vs) => vs.into_iter().collect::<Result<_, X>>().map(Multi),
         }
     }
 }
 
+// only the tests compare channels
+#[cfg(test)]
 impl Channeled<bool> {
     pub fn and(self) -> bool {
         use Channeled::*;
         match self {
             Stereo(a, b) => a && b,
             Mono(v) => v,
+            Multi(vs) => vs.into_iter().all(move |v| v),
         }
     }
 }
@@ -132,6 +193,7 @@ where
         match self.iters.as_mut_ref().map(move |v| v.next()) {
             Stereo(Some(vl), Some(vr)) => Some(Stereo(vl, vr)),
             Mono(Some(v)) => Some(Mono(v)),
+            Multi(vs) => vs.into_iter().collect::<Option<_>>().map(Multi),
             _ => None
         }
     }
@@ -148,6 +210,16 @@ where
                 )
             }
             Mono(v) => v.size_hint(),
+            Multi(vs) => {
+                let init = (usize::MAX, Some(0));
+                vs.iter().fold(init, move |(l, h), v| {
+                    let (vl, vh) = v.size_hint();
+                    (
+                        std::cmp::min(l, vl),
+                        h.and_then(move |h| vh.map(move |vh| std::cmp::max(h, vh))),
+                    )
+                })
+            }
         }
     }
 }
@@ -157,3 +229,60 @@ unsafe impl<I> TrustedLen for ChanneledIter<I> where I: Iterator + TrustedLen {}
 impl<I> ExactSizeIterator for ChanneledIter<I> where I: Iterator + ExactSizeIterator {}
 
 impl<I> FusedIterator for ChanneledIter<I> where I: Iterator + FusedIterator {}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+
+    #[test]
+    fn num_channels() {
+        assert_eq!(Channeled::Mono(1.0).num_channels(), 1);
+        assert_eq!(Channeled::Stereo(1.0, 2.0).num_channels(), 2);
+        assert_eq!(Channeled::Multi(vec![1.0; 6]).num_channels(), 6);
+    }
+
+    #[test]
+    fn to_mono_averages() {
+        assert_eq!(Channeled::Mono(0.5).to_mono(), 0.5);
+        assert_eq!(Channeled::Stereo(1.0, -0.5).to_mono(), 0.25);
+        assert_eq!(Channeled::Multi(vec![1.0, -0.5, 0.5, 0.0]).to_mono(), 0.25);
+        assert_eq!(Channeled::<f64>::Multi(vec![]).to_mono(), 0.0);
+    }
+
+    #[test]
+    fn channels_in_order() {
+        assert_eq!(Channeled::Mono(1).channels().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(
+            Channeled::Stereo(1, 2).channels().collect::<Vec<_>>(),
+            vec![&1, &2]
+        );
+        assert_eq!(
+            Channeled::Multi(vec![1, 2, 3])
+                .channels()
+                .collect::<Vec<_>>(),
+            vec![&1, &2, &3]
+        );
+    }
+
+    #[test]
+    fn multi_helpers_keep_every_channel() {
+        let multi = Channeled::Multi(vec![1, 2, 3]);
+        assert_eq!(
+            multi.clone().map(|v| v * 2),
+            Channeled::Multi(vec![2, 4, 6])
+        );
+        assert_eq!(
+            multi.clone().zip(Channeled::Multi(vec![4, 5, 6])),
+            Some(Channeled::Multi(vec![(1, 4), (2, 5), (3, 6)]))
+        );
+        assert_eq!(multi.clone().zip(Channeled::Multi(vec![4, 5])), None);
+        assert_eq!(multi.clone().zip(Channeled::Stereo(4, 5)), None);
+        assert!(multi.clone().map(|v| v > 0).and());
+        assert!(!multi.clone().map(|v| v > 1).and());
+
+        let mut multi = multi;
+        multi.as_mut_ref().for_each(|v| *v += 1);
+        assert_eq!(multi, Channeled::Multi(vec![2, 3, 4]));
+        assert_eq!(format!("{}", multi), "(2, 3, 4)");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::pipeline::{
+    apply_env_overrides, open_config_or_default, open_preset, save_preset, VizPipelineConfig,
+    DEFAULT_PRESETS_DIR,
+};
+use crate::util::parse_timestamp;
+use crate::wav::RawPcmFormat;
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Command line arguments: `vis-rs [options] <file>...`
+///
+/// Multiple files are played one after the other (a playlist), `N` skips to the next file. `A` and
+/// `B` set loop in and loop out at the current position, and `L` repeats the region between them.
+///
+/// Options:
+/// * `--frames N` stop after N frames (live and export)
+/// * `--export <path>` write frames as CSV (or JSON with timestamps for `.json`) to path instead of
+///   opening a window
+/// * `--envelope <path>` with `--export`, also write the peak and RMS of every frame as CSV
+/// * `--raw-spectrum` with `--export`, write the full resolution spectrum instead of the bars
+/// * `--group-delay` with `--export`, write the group delay (in samples) of every FFT bin instead
+///   of the bars
+/// * `--diff <file>` show the bars of the main file minus the bars of `<file>` (A/B comparison)
+/// * `--sample-rate N --channels N --bits N` the format of `.raw`/`.pcm` (headerless PCM) files
+/// * `--strict` refuse WAV files whose length doesn't match the sizes in their header (truncated
+///   files), instead of warning and reading the samples which are there
+/// * `--preset <name>` use the config in `<presets dir>/<name>.yml` instead of `config.yml`
+/// * `--save-preset <name>` save the effective config (after all options) as a preset
+/// * `--presets-dir <dir>` where presets are kept (default: `presets`)
+/// * `--repeat` start over at the first file after the last one
+/// * `--dump-sg` print the Savitzky Golay coefficients of the configured smoothers and exit
+/// * `--seek <time>` start playback (or the export) at a timestamp, `90`, `1:30` or `1:30.5`
+/// * `--explain <config>` print the stages the pipeline builds for a config file and exit, at the
+///   sample rate of `<file>` (and with its sample format) when one is given
+/// * `--print-default-config` print the default config, with comments on the fields, as a
+///   starting point for a `config.yml` and exit
+/// * `--warm-wisdom <path>` measure the FFT sizes of the config at common sample rates and write
+///   the FFTW wisdom to path (load it with `fft_wisdom` in the config), then exit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliArgs {
+    pub targets: Vec<String>,
+    pub frames: Option<usize>,
+    pub export: Option<String>,
+    pub envelope: Option<String>,
+    pub raw_spectrum: bool,
+    pub group_delay: bool,
+    pub diff: Option<String>,
+    pub raw_format: Option<RawPcmFormat>,
+    pub strict: bool,
+    pub preset: Option<String>,
+    pub save_preset: Option<String>,
+    pub presets_dir: Option<String>,
+    pub repeat: bool,
+    pub dump_sg: bool,
+    pub explain: Option<String>,
+    pub seek: Option<Duration>,
+    pub warm_wisdom: Option<String>,
+    pub print_default_config: bool,
+}
+
+impl CliArgs {
+    /// applies the options which override values from the config file
+    pub fn apply(&self, config: &mut VizPipelineConfig) {
+        if let Some(frames) = self.frames {
+            config.max_frames = Some(frames);
+        }
+    }
+
+    /// Loads the config (a preset, or the config file / default) with the `VIZ_*` environment
+    /// variables and then the options applied, and saves it as a preset if asked to
+    pub fn load_config(&self) -> Result<VizPipelineConfig> {
+        let presets_dir = self.presets_dir.as_deref().unwrap_or(DEFAULT_PRESETS_DIR);
+        let config = match &self.preset {
+            Some(name) => open_preset(presets_dir, name)?,
+            None => open_config_or_default(presets_dir)?,
+        };
+        let config = self.with_overrides(config)?;
+
+        if let Some(name) = &self.save_preset {
+            let path = save_preset(presets_dir, name, &config)?;
+            println!("saved preset {} to {}", name, path.display());
+        }
+
+        Ok(config)
+    }
+
+    /// applies the `VIZ_*` environment overrides, then the options, to a config from anywhere
+    pub fn with_overrides(&self, config: VizPipelineConfig) -> Result<VizPipelineConfig> {
+        let mut config = apply_env_overrides(config, std::env::vars())?;
+        self.apply(&mut config);
+        Ok(config)
+    }
+}
+
+pub fn parse_args<I>(args: I) -> Result<CliArgs>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut out = CliArgs::default();
+    let mut args = args.into_iter();
+    let mut sample_rate = None;
+    let mut channels = None;
+    let mut bits = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => out.frames = Some(parse_value(&arg, args.next())?),
+            "--export" => out.export = Some(parse_value(&arg, args.next())?),
+            "--envelope" => out.envelope = Some(parse_value(&arg, args.next())?),
+            "--raw-spectrum" => out.raw_spectrum = true,
+            "--group-delay" => out.group_delay = true,
+            "--diff" => out.diff = Some(parse_value(&arg, args.next())?),
+            "--sample-rate" => sample_rate = Some(parse_value(&arg, args.next())?),
+            "--channels" => channels = Some(parse_value(&arg, args.next())?),
+            "--bits" => bits = Some(parse_value(&arg, args.next())?),
+            "--strict" => out.strict = true,
+            "--preset" => out.preset = Some(parse_value(&arg, args.next())?),
+            "--save-preset" => out.save_preset = Some(parse_value(&arg, args.next())?),
+            "--presets-dir" => out.presets_dir = Some(parse_value(&arg, args.next())?),
+            "--repeat" => out.repeat = true,
+            "--dump-sg" => out.dump_sg = true,
+            "--explain" => out.explain = Some(parse_value(&arg, args.next())?),
+            "--warm-wisdom" => out.warm_wisdom = Some(parse_value(&arg, args.next())?),
+            "--print-default-config" => out.print_default_config = true,
+            "--seek" => {
+                let value: String = parse_value(&arg, args.next())?;
+                out.seek = Some(parse_timestamp(&value)?);
+            }
+            other if other.starts_with("--") => {
+                return Err(anyhow!("unknown option {}", other));
+            }
+            _ => out.targets.push(arg),
+        }
+    }
+
+    if out.envelope.is_some() && out.export.is_none() {
+        return Err(anyhow!(
+            "--envelope is only supported together with --export"
+        ));
+    }
+
+    if out.raw_spectrum && out.export.is_none() {
+        return Err(anyhow!(
+            "--raw-spectrum is only supported together with --export"
+        ));
+    }
+
+    if out.group_delay && out.export.is_none() {
+        return Err(anyhow!(
+            "--group-delay is only supported together with --export"
+        ));
+    }
+
+    if out.group_delay && out.raw_spectrum {
+        return Err(anyhow!(
+            "--group-delay is not supported together with --raw-spectrum"
+        ));
+    }
+
+    if out.diff.is_some() && out.export.is_some() {
+        return Err(anyhow!("--diff is not supported together with --export"));
+    }
+
+    if out.targets.len() > 1 && (out.export.is_some() || out.diff.is_some()) {
+        return Err(anyhow!(
+            "--export and --diff only support a single file, got {}",
+            out.targets.len()
+        ));
+    }
+
+    out.raw_format = match (sample_rate, channels, bits) {
+        (Some(sample_rate), Some(num_channels), Some(bits_per_sample)) => Some(RawPcmFormat {
+            sample_rate,
+            num_channels,
+            bits_per_sample,
+        }),
+        (None, None, None) => None,
+        _ => {
+            return Err(anyhow!(
+                "--sample-rate, --channels and --bits must be specified together"
+            ));
+        }
+    };
+
+    Ok(out)
+}
+
+fn parse_value<T>(flag: &str, value: Option<String>) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Debug,
+{
+    let value = value.ok_or_else(|| anyhow!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|err| anyhow!("invalid value {} for {} :: {:?}", value, flag, err))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::cli::{parse_args, CliArgs};
+    use crate::wav::RawPcmFormat;
+    use std::time::Duration;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_frames_and_export() {
+        let parsed = parse_args(args(&["--frames", "10", "song.wav", "--export", "out.csv"]))
+            .expect("should parse");
+        assert_eq!(
+            parsed,
+            CliArgs {
+                targets: vec!["song.wav".to_string()],
+                frames: Some(10),
+                export: Some("out.csv".to_string()),
+                envelope: None,
+                raw_spectrum: false,
+                group_delay: false,
+                diff: None,
+                raw_format: None,
+                strict: false,
+                preset: None,
+                save_preset: None,
+                presets_dir: None,
+                repeat: false,
+                dump_sg: false,
+                explain: None,
+                seek: None,
+                warm_wisdom: None,
+                print_default_config: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_playlist() {
+        let parsed = parse_args(args(&["a.wav", "--repeat", "b.wav"])).expect("should parse");
+        assert_eq!(parsed.targets, args(&["a.wav", "b.wav"]));
+        assert!(parsed.repeat);
+    }
+
+    #[test]
+    fn parse_seek() {
+        let parsed = parse_args(args(&["song.wav", "--seek", "1:30"])).expect("should parse");
+        assert_eq!(parsed.seek, Some(Duration::from_secs(90)));
+        assert!(parse_args(args(&["song.wav", "--seek", "1:75"])).is_err());
+    }
+
+    #[test]
+    fn parse_strict() {
+        let parsed = parse_args(args(&["song.wav", "--strict"])).expect("should parse");
+        assert!(parsed.strict);
+        let parsed = parse_args(args(&["song.wav"])).expect("should parse");
+        assert!(!parsed.strict);
+    }
+
+    #[test]
+    fn parse_raw_format() {
+        let parsed = parse_args(args(&[
+            "song.raw",
+            "--sample-rate",
+            "48000",
+            "--channels",
+            "2",
+            "--bits",
+            "16",
+        ]))
+        .expect("should parse");
+        assert_eq!(
+            parsed.raw_format,
+            Some(RawPcmFormat {
+                sample_rate: 48000,
+                num_channels: 2,
+                bits_per_sample: 16,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rejects_bad_values() {
+        assert!(parse_args(args(&["--frames", "ten", "song.wav"])).is_err());
+        assert!(parse_args(args(&["song.wav", "--frames"])).is_err());
+        assert!(parse_args(args(&["--bogus", "song.wav"])).is_err());
+        assert!(parse_args(args(&["--envelope", "env.csv", "song.wav"])).is_err());
+        assert!(parse_args(args(&["--raw-spectrum", "song.wav"])).is_err());
+        assert!(parse_args(args(&["--group-delay", "song.wav"])).is_err());
+        assert!(parse_args(args(&[
+            "song.wav",
+            "--export",
+            "out.csv",
+            "--group-delay",
+            "--raw-spectrum"
+        ]))
+        .is_err());
+        assert!(parse_args(args(&["a.wav", "--diff", "b.wav", "--export", "out.csv"])).is_err());
+        assert!(parse_args(args(&["song.raw", "--sample-rate", "44100"])).is_err());
+        assert!(parse_args(args(&["a.wav", "b.wav", "--export", "out.csv"])).is_err());
+    }
+}
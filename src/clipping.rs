@@ -0,0 +1,177 @@
+use crate::channeled::Channeled;
+use crate::delegate_impls;
+use crate::framed::Samples;
+use crate::wav::SampleRaw;
+use anyhow::Result;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// warn when more than this percentage of samples are clipped
+const CLIP_WARN_PERCENT: f64 = 0.01;
+
+/// Counts samples sitting at the minimum or maximum value of their format as they pass through.
+///
+/// The counts are kept in a `ClipStats` handle, so they can be read after the source has been
+/// moved into (and consumed by) a pipeline.
+pub struct ClipDetect<S, I> {
+    source: S,
+    stats: ClipStats,
+    _inner_typ: PhantomData<I>,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct ClipStats {
+    clipped: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+}
+
+impl<S, I> ClipDetect<S, I>
+where
+    S: Samples<Channeled<SampleRaw>, I>,
+{
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            stats: ClipStats::default(),
+            _inner_typ: PhantomData,
+        }
+    }
+
+    pub fn stats(&self) -> ClipStats {
+        self.stats.clone()
+    }
+}
+
+impl ClipStats {
+    /// number of (per channel) samples at full scale
+    pub fn clipped(&self) -> usize {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    /// number of (per channel) samples seen
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn percentage(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            0.0
+        } else {
+            ((self.clipped() as f64) / (total as f64)) * 100.0
+        }
+    }
+
+    pub fn report(&self) {
+        let percentage = self.percentage();
+        if percentage > CLIP_WARN_PERCENT {
+            eprintln!(
+                "[warn] source is clipping, {} of {} samples ({:.3}%) are at full scale",
+                self.clipped(),
+                self.total(),
+                percentage
+            );
+        } else {
+            println!(
+                "clipping: {} of {} samples ({:.3}%) at full scale",
+                self.clipped(),
+                self.total(),
+                percentage
+            );
+        }
+    }
+
+    fn record(&self, sample: &Channeled<SampleRaw>) {
+        let mut clipped = 0;
+        let mut total = 0;
+        sample.as_ref().for_each(|v| {
+            total += 1;
+            if v.is_full_scale() {
+                clipped += 1;
+            }
+        });
+
+        self.total.fetch_add(total, Ordering::Relaxed);
+        if clipped != 0 {
+            self.clipped.fetch_add(clipped, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<S, I> Samples<Channeled<SampleRaw>, I> for ClipDetect<S, I>
+where
+    S: Samples<Channeled<SampleRaw>, I>,
+{
+    fn into_deep_inner(self) -> I {
+        self.source.into_deep_inner()
+    }
+
+    fn seek_samples(&mut self, n: isize) -> Result<()> {
+        self.source.seek_samples(n)
+    }
+
+    #[cfg(any(feature = "gui", test))]
+    fn seek_to_sample(&mut self, abs: usize) -> Result<()> {
+        self.source.seek_to_sample(abs)
+    }
+
+    fn next_sample(&mut self) -> Result<Option<Channeled<SampleRaw>>> {
+        let out = self.source.next_sample()?;
+        if let Some(sample) = &out {
+            self.stats.record(sample);
+        }
+
+        Ok(out)
+    }
+
+    fn num_samples_remain(&self) -> usize {
+        self.source.num_samples_remain()
+    }
+}
+
+delegate_impls!(ClipDetect<S, I>, S, source);
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::clipping::ClipDetect;
+    use crate::framed::Samples;
+    use crate::vec_samples::VecSamples;
+    use crate::wav::SampleRaw;
+
+    #[test]
+    fn counts_full_scale_samples() {
+        use Channeled::*;
+        use SampleRaw::*;
+
+        let samples = vec![
+            Stereo(TwoBytes(i16::MAX), TwoBytes(0)),
+            Stereo(TwoBytes(i16::MIN), TwoBytes(i16::MAX)),
+            Stereo(TwoBytes(i16::MAX - 1), TwoBytes(i16::MIN + 1)),
+            Stereo(TwoBytes(12), TwoBytes(-12)),
+        ];
+        let mut source = ClipDetect::new(VecSamples::new(samples, 44100));
+        let stats = source.stats();
+        while source.next_sample().expect("should read").is_some() {}
+
+        assert_eq!(stats.clipped(), 3);
+        assert_eq!(stats.total(), 8);
+        assert!((stats.percentage() - 37.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn counts_8_bit_full_scale() {
+        let samples = vec![
+            Channeled::Mono(SampleRaw::OneByte(0)),
+            Channeled::Mono(SampleRaw::OneByte(128)),
+            Channeled::Mono(SampleRaw::OneByte(255)),
+        ];
+        let mut source = ClipDetect::new(VecSamples::new(samples, 8000));
+        let stats = source.stats();
+        while source.next_sample().expect("should read").is_some() {}
+
+        assert_eq!(stats.clipped(), 2);
+        assert_eq!(stats.total(), 3);
+    }
+}
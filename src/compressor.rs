@@ -0,0 +1,78 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct CompressorConfig {
+    /// bars louder than this (dB) are compressed
+    pub threshold_db: VizFloat,
+    /// dB above the threshold in for every dB out, 1.0 leaves bars alone
+    pub ratio: VizFloat,
+}
+
+impl CompressorConfig {
+    /// the compressed level of a bar at `db`
+    pub fn compress(&self, db: VizFloat) -> VizFloat {
+        if db > self.threshold_db {
+            self.threshold_db + ((db - self.threshold_db) / self.ratio)
+        } else {
+            db
+        }
+    }
+}
+
+/// Compresses every bar (in dB) above the threshold by the ratio, like a compressor with an
+/// instant attack and release, so transients don't slam the bars to the top while quiet bars keep
+/// their detail. Each bar of each frame is compressed on its own.
+pub struct Compressor {
+    config: CompressorConfig,
+}
+
+impl Compressor {
+    pub fn new(config: CompressorConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for Compressor {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let config = self.config;
+        input
+            .iter_mut()
+            .for_each(move |v| v.as_mut_ref().for_each(move |v| *v = config.compress(*v)));
+        Ok(Some(input))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::compressor::{Compressor, CompressorConfig};
+    use crate::framed::FramedMapper;
+
+    #[test]
+    fn compresses_above_the_threshold_only() {
+        let mut compressor = Compressor::new(CompressorConfig {
+            threshold_db: -20.0,
+            ratio: 4.0,
+        });
+        let mut frame = vec![
+            Channeled::Mono(-8.0),
+            Channeled::Stereo(-30.0, -12.0),
+            Channeled::Mono(-20.0),
+        ];
+        let out = compressor
+            .map(&mut frame)
+            .expect("should map")
+            .expect("has frame");
+        // 12 dB over the threshold is 3 dB over it at 4:1
+        assert_eq!(out[0], Channeled::Mono(-17.0));
+        assert_eq!(out[1], Channeled::Stereo(-30.0, -18.0));
+        assert_eq!(out[2], Channeled::Mono(-20.0));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// Pearson correlation between the left and right channel of a frame of time-domain samples, from
+/// -1.0 (out of phase) through 0.0 (unrelated, very wide) to 1.0 (mono).
+///
+/// Mono frames report 1.0, as does a frame where both channels are constant (e.g. silence). When
+/// only one channel is constant the channels are unrelated, which is 0.0.
+pub fn stereo_correlation(samples: &[Channeled<VizFloat>]) -> VizFloat {
+    let mut n = 0usize;
+    let (mut sum_l, mut sum_r) = (0.0, 0.0);
+    for sample in samples {
+        if let Channeled::Stereo(l, r) = *sample {
+            sum_l += l;
+            sum_r += r;
+            n += 1;
+        }
+    }
+
+    if n == 0 {
+        return 1.0;
+    }
+
+    let mean_l = sum_l / (n as VizFloat);
+    let mean_r = sum_r / (n as VizFloat);
+    let (mut cov, mut var_l, mut var_r) = (0.0, 0.0, 0.0);
+    for sample in samples {
+        if let Channeled::Stereo(l, r) = *sample {
+            let (dl, dr) = (l - mean_l, r - mean_r);
+            cov += dl * dr;
+            var_l += dl * dl;
+            var_r += dr * dr;
+        }
+    }
+
+    match (var_l > 0.0, var_r > 0.0) {
+        (true, true) => (cov / (var_l * var_r).sqrt()).clamp(-1.0, 1.0),
+        (false, false) => 1.0,
+        _ => 0.0,
+    }
+}
+
+/// Reduces every time-domain frame to a single value, its stereo correlation
+#[derive(Default)]
+pub struct StereoCorrelation {
+    out: [VizFloat; 1],
+}
+
+impl StereoCorrelation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, VizFloat> for StereoCorrelation {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [VizFloat]>> {
+        self.out[0] = stereo_correlation(input);
+        Ok(Some(&mut self.out[..]))
+    }
+
+    fn map_frame_size(&self, _orig: usize) -> usize {
+        1
+    }
+}
+
+/// Shared handle to the stereo correlation of the most recent frame seen by a
+/// `CorrelationTapMapper`, works the same way as the `WaveformTap`
+#[derive(Clone)]
+pub struct CorrelationTap {
+    latest: Arc<Mutex<VizFloat>>,
+}
+
+impl CorrelationTap {
+    pub fn new() -> Self {
+        Self {
+            latest: Arc::new(Mutex::new(1.0)),
+        }
+    }
+
+    pub fn mapper(&self) -> CorrelationTapMapper {
+        CorrelationTapMapper {
+            latest: self.latest.clone(),
+            correlation: StereoCorrelation::new(),
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn latest(&self) -> VizFloat {
+        *self.latest.lock().expect("correlation tap poisoned")
+    }
+}
+
+impl Default for CorrelationTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CorrelationTapMapper {
+    latest: Arc<Mutex<VizFloat>>,
+    correlation: StereoCorrelation,
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for CorrelationTapMapper {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        if let Some(out) = self.correlation.map(input)? {
+            *self.latest.lock().expect("correlation tap poisoned") = out[0];
+        }
+
+        Ok(Some(input))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::correlation::{stereo_correlation, StereoCorrelation};
+    use crate::framed::FramedMapper;
+    use crate::util::VizFloat;
+
+    fn stereo<F>(right: F) -> Vec<Channeled<VizFloat>>
+    where
+        F: Fn(VizFloat) -> VizFloat,
+    {
+        (0..256)
+            .map(|i| {
+                let l = ((i as VizFloat) * 0.1).sin() * 0.5;
+                Channeled::Stereo(l, right(l))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_and_inverted_channels() {
+        let mut correlation = StereoCorrelation::new();
+        let mut frame = stereo(|l| l);
+        let out = correlation.map(&mut frame).expect("ok").expect("some");
+        assert_eq!(out.len(), 1);
+        assert!((out[0] - 1.0).abs() < 1e-12, "got {}", out[0]);
+
+        let mut frame = stereo(|l| -l);
+        let out = correlation.map(&mut frame).expect("ok").expect("some");
+        assert!((out[0] + 1.0).abs() < 1e-12, "got {}", out[0]);
+    }
+
+    #[test]
+    fn mono_and_silence() {
+        let frame = [Channeled::Mono(0.5), Channeled::Mono(-0.5)];
+        assert_eq!(stereo_correlation(&frame), 1.0);
+        assert_eq!(stereo_correlation(&stereo(|_| 0.0)), 0.0);
+        assert_eq!(
+            stereo_correlation(&vec![Channeled::Stereo(0.0, 0.0); 16]),
+            1.0
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::framed::Framed;
+use crate::util::VizFloat;
+use anyhow::{anyhow, Result};
+use std::marker::PhantomData;
+
+/// Steps two frame sources in lockstep and outputs `a - b` for every value (for A/B comparing two
+/// files rendered with the same config). Ends with the shorter of the two sources.
+pub struct FramedDiff<A, B, IB> {
+    a: A,
+    b: B,
+    buf: Vec<VizFloat>,
+    _b_inner_typ: PhantomData<fn() -> IB>,
+}
+
+impl<A, B, IB> FramedDiff<A, B, IB> {
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            buf: Vec::new(),
+            _b_inner_typ: PhantomData,
+        }
+    }
+}
+
+impl<A, B, IA, IB> Framed<VizFloat, IA> for FramedDiff<A, B, IB>
+where
+    A: Framed<VizFloat, IA>,
+    B: Framed<VizFloat, IB>,
+{
+    fn into_deep_inner(self) -> IA {
+        self.a.into_deep_inner()
+    }
+
+    fn seek_frame(&mut self, n: isize) -> Result<()> {
+        self.a.seek_frame(n)?;
+        self.b.seek_frame(n)
+    }
+
+    fn next_frame(&mut self) -> Result<Option<&mut [VizFloat]>> {
+        self.buf.clear();
+        if let Some(frame) = self.a.next_frame()? {
+            self.buf.extend_from_slice(frame);
+        } else {
+            return Ok(None);
+        }
+
+        if let Some(frame) = self.b.next_frame()? {
+            if frame.len() != self.buf.len() {
+                return Err(anyhow!(
+                    "cannot diff frames of different sizes ({} vs {})",
+                    self.buf.len(),
+                    frame.len()
+                ));
+            }
+
+            self.buf
+                .iter_mut()
+                .zip(frame.iter())
+                .for_each(move |(a, b)| *a -= *b);
+            Ok(Some(self.buf.as_mut_slice()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn num_frames(&self) -> usize {
+        std::cmp::min(self.a.num_frames(), self.b.num_frames())
+    }
+
+    fn num_frames_remain(&self) -> usize {
+        std::cmp::min(self.a.num_frames_remain(), self.b.num_frames_remain())
+    }
+
+    fn num_full_frames(&self) -> usize {
+        std::cmp::min(self.a.num_full_frames(), self.b.num_full_frames())
+    }
+
+    fn full_frame_size(&self) -> usize {
+        self.a.full_frame_size()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::diff::FramedDiff;
+    use crate::framed::Framed;
+    use crate::pipeline::create_viz_pipeline_from_samples;
+    use crate::pipeline::tests::fast_config;
+
+    #[test]
+    fn identical_inputs_have_no_difference() {
+        let samples = (0..22050)
+            .map(|i| Channeled::Mono(((i as f64) * 0.05).sin() * 0.5))
+            .collect::<Vec<_>>();
+        let pipeline = move |samples: Vec<_>| {
+            create_viz_pipeline_from_samples(samples, 44100, fast_config()).expect("should build")
+        };
+
+        let frames = FramedDiff::new(pipeline(samples.clone()), pipeline(samples.clone()))
+            .collect()
+            .expect("should run");
+        assert!(!frames.is_empty());
+        assert!(frames.iter().flatten().all(move |v| *v == 0.0));
+
+        // the diff ends with the shorter source
+        let short = samples[..11025].to_vec();
+        let n_short = pipeline(short.clone()).collect().expect("should run").len();
+        let frames = FramedDiff::new(pipeline(samples), pipeline(short))
+            .collect()
+            .expect("should run");
+        assert_eq!(frames.len(), n_short);
+    }
+}
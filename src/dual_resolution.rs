@@ -0,0 +1,243 @@
+use crate::channeled::Channeled;
+use crate::fft::FramedFft;
+use crate::framed::FramedMapper;
+use crate::util::{fft_bin_hz, VizFloat};
+use crate::window::{BlackmanNuttall, MemoizedWindowingMapper, WindowingFunction};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct DualResolutionConfig {
+    /// the long window (and FFT) is this many times the main window
+    pub factor: usize,
+    /// bins below this frequency come from the long FFT, the rest from the main FFT
+    pub crossover_hz: VizFloat,
+}
+
+/// Two FFTs over the same frames: a long one for fine frequency resolution in the bass, and the
+/// main (short) one for good time resolution everywhere else.
+///
+/// Frames are `factor` windows long, the long FFT windows the whole frame and the short FFT
+/// windows the `window_size` samples in the middle of it, so both see the same moment. The output
+/// is on the grid of the long FFT (`factor * fft_size / 2` magnitudes, DC skipped): bins below the
+/// crossover are the long FFT, the ones above are the short FFT interpolated onto the finer grid,
+/// and the `2 * factor` bins around the crossover (two bins of the short FFT) fade from one to the
+/// other so there is no step at the crossover. Short FFT magnitudes are scaled by `factor` so a
+/// steady tone reads the same from either FFT.
+pub struct DualResolutionFft {
+    window_size: usize,
+    factor: usize,
+    // first output bin above the crossover frequency, the middle of the crossfade
+    crossover: usize,
+    short_window: MemoizedWindowingMapper,
+    long_window: MemoizedWindowingMapper,
+    short_fft: FramedFft,
+    long_fft: FramedFft,
+    short_buf: Vec<Channeled<VizFloat>>,
+    out: Vec<Channeled<VizFloat>>,
+    n_out: usize,
+}
+
+impl DualResolutionFft {
+    pub fn new(
+        config: DualResolutionConfig,
+        window_size: usize,
+        fft_size: usize,
+        sample_rate: usize,
+        num_samples: usize,
+    ) -> Result<Self> {
+        let factor = config.factor;
+        // sources shorter than the windows are windowed over the samples they have, like
+        // the window of the single FFT
+        let offset = (window_size * (factor - 1)) / 2;
+        let short_size = std::cmp::min(window_size, num_samples.saturating_sub(offset));
+        let long_size = std::cmp::min(window_size * factor, num_samples);
+        let long_fft_size = fft_size * factor;
+        let n_out = long_fft_size / 2;
+        let crossover = (0..n_out)
+            .take_while(move |i| {
+                fft_bin_hz(i + 1, long_fft_size, sample_rate) < config.crossover_hz
+            })
+            .count();
+
+        Ok(Self {
+            window_size,
+            factor,
+            crossover,
+            short_window: BlackmanNuttall::mapper(short_size),
+            long_window: BlackmanNuttall::mapper(long_size),
+            short_fft: FramedFft::new(fft_size)?,
+            long_fft: FramedFft::new(long_fft_size)?,
+            short_buf: Vec::with_capacity(window_size),
+            out: Vec::with_capacity(n_out),
+            n_out,
+        })
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for DualResolutionFft {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let Self {
+            window_size,
+            factor,
+            crossover,
+            short_window,
+            long_window,
+            short_fft,
+            long_fft,
+            short_buf,
+            out,
+            ..
+        } = self;
+
+        // the short window is in the middle of the frame (the end of the source may cut it short)
+        let offset = (*window_size * (*factor - 1)) / 2;
+        let end = std::cmp::min(input.len(), offset + *window_size);
+        short_buf.clear();
+        short_buf.extend_from_slice(input.get(offset..end).unwrap_or(&[]));
+        if short_buf.is_empty() {
+            short_buf.extend(input.first().map(move |v| v.as_ref().map(move |_| 0.0)));
+        }
+
+        short_window.map(short_buf)?;
+        let short = match short_fft.map(short_buf)? {
+            Some(short) => short,
+            None => return Ok(None),
+        };
+        long_window.map(input)?;
+        let long = match long_fft.map(input)? {
+            Some(long) => long,
+            None => return Ok(None),
+        };
+
+        let fade_start = crossover.saturating_sub(*factor);
+        let fade_end = *crossover + *factor;
+        let fade_width = (fade_end - fade_start) as VizFloat;
+        let factor = *factor as VizFloat;
+        let last_short = (short.len() - 1) as VizFloat;
+        out.clear();
+        out.extend(long.iter().enumerate().map(move |(idx, long)| {
+            if idx < fade_start {
+                return long.clone();
+            }
+
+            // position of this bin's frequency on the short FFT's output
+            let at = ((((idx + 1) as VizFloat) / factor) - 1.0).clamp(0.0, last_short);
+            let (below, t) = (at.floor() as usize, at.fract());
+            let above = std::cmp::min(below + 1, short.len() - 1);
+            let short = short[below]
+                .as_ref()
+                .zip(short[above].as_ref())
+                .expect("mixed mono/stereo?")
+                .map(move |(a, b)| (a + ((b - a) * t)) * factor);
+            if idx >= fade_end {
+                return short;
+            }
+
+            // linear crossfade from the long to the short FFT
+            let t = ((idx - fade_start) as VizFloat + 0.5) / fade_width;
+            long.as_ref()
+                .zip(short.as_ref())
+                .expect("mixed mono/stereo?")
+                .map(move |(long, short)| long + ((short - long) * t))
+        }));
+
+        Ok(Some(out.as_mut_slice()))
+    }
+
+    fn map_frame_size(&self, _: usize) -> usize {
+        self.n_out
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::dual_resolution::{DualResolutionConfig, DualResolutionFft};
+    use crate::fft::FramedFft;
+    use crate::framed::FramedMapper;
+    use crate::window::{BlackmanNuttall, WindowingFunction};
+
+    // magnitudes of a windowed FFT of `samples`
+    fn spectrum(samples: &[f64]) -> Vec<f64> {
+        let mut frame = samples
+            .iter()
+            .map(|v| Channeled::Mono(*v))
+            .collect::<Vec<_>>();
+        BlackmanNuttall::mapper(samples.len())
+            .map(&mut frame)
+            .expect("should window");
+        let mut fft = FramedFft::new(samples.len()).expect("should plan");
+        fft.map(&mut frame)
+            .expect("ok")
+            .expect("some")
+            .iter()
+            .map(|v| v.to_mono())
+            .collect()
+    }
+
+    #[test]
+    fn low_bins_from_the_long_fft_high_bins_from_the_short_fft() {
+        // 1Hz per bin of the long FFT, the crossover is between bins 10 and 11 (11Hz and 12Hz) and
+        // bins 7 to 14 fade from the long to the short FFT
+        let samples = (0..256)
+            .map(|i| {
+                let t = (i as f64) / 256.0;
+                (t * 40.0 * std::f64::consts::PI * 2.0).sin()
+                    + (t * 5.0 * std::f64::consts::PI * 2.0).sin()
+            })
+            .collect::<Vec<_>>();
+        let config = DualResolutionConfig {
+            factor: 4,
+            crossover_hz: 11.5,
+        };
+        let mut dual = DualResolutionFft::new(config, 64, 64, 256, 256).expect("should plan");
+        assert_eq!(dual.map_frame_size(256), 128);
+
+        let mut frame = samples
+            .iter()
+            .map(|v| Channeled::Mono(*v))
+            .collect::<Vec<_>>();
+        let out = dual
+            .map(&mut frame)
+            .expect("ok")
+            .expect("some")
+            .iter()
+            .map(|v| v.to_mono())
+            .collect::<Vec<_>>();
+        assert_eq!(out.len(), 128);
+
+        let long = spectrum(&samples);
+        let short = spectrum(&samples[96..160]);
+        for (idx, v) in out.iter().enumerate() {
+            let (freq, expected) = if idx < 7 {
+                (idx + 1, long[idx])
+            } else if idx >= 15 && (idx + 1) % 4 == 0 {
+                // the short FFT's bins are exactly on the long FFT's grid every 4 bins
+                (idx + 1, short[(idx + 1) / 4 - 1] * 4.0)
+            } else {
+                continue;
+            };
+            assert!(
+                (v - expected).abs() < 1e-9,
+                "{}Hz: {} != {}",
+                freq,
+                v,
+                expected
+            );
+        }
+
+        // halfway through the crossfade both FFTs count about the same
+        let t = 4.5 / 8.0;
+        let expected = long[11] + ((short[2] * 4.0 - long[11]) * t);
+        assert!(
+            (out[11] - expected).abs() < 1e-9,
+            "{} != {}",
+            out[11],
+            expected
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// Peak and RMS amplitude of one frame of time-domain samples (all channels together)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Envelope {
+    pub peak: VizFloat,
+    pub rms: VizFloat,
+}
+
+impl Envelope {
+    pub fn of(samples: &[Channeled<VizFloat>]) -> Self {
+        let mut peak: VizFloat = 0.0;
+        let mut sum_squares = 0.0;
+        let mut n = 0usize;
+        samples.iter().for_each(|sample| {
+            sample.channels().for_each(|v| {
+                peak = peak.max(v.abs());
+                sum_squares += v * v;
+                n += 1;
+            })
+        });
+
+        let rms = if n == 0 {
+            0.0
+        } else {
+            (sum_squares / (n as VizFloat)).sqrt()
+        };
+
+        Self { peak, rms }
+    }
+}
+
+/// Shared handle to the envelope of the most recent frame seen by an `EnvelopeTapMapper`, works
+/// the same way as the `WaveformTap`
+#[derive(Clone, Default)]
+pub struct EnvelopeTap {
+    latest: Arc<Mutex<Envelope>>,
+}
+
+impl EnvelopeTap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mapper(&self) -> EnvelopeTapMapper {
+        EnvelopeTapMapper {
+            latest: self.latest.clone(),
+        }
+    }
+
+    pub fn latest(&self) -> Envelope {
+        *self.latest.lock().expect("envelope tap poisoned")
+    }
+}
+
+pub struct EnvelopeTapMapper {
+    latest: Arc<Mutex<Envelope>>,
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for EnvelopeTapMapper {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        *self.latest.lock().expect("envelope tap poisoned") = Envelope::of(input);
+        Ok(Some(input))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::envelope::Envelope;
+
+    #[test]
+    fn envelope_of_stereo_frame() {
+        let frame = vec![Channeled::Stereo(0.5, -1.0), Channeled::Stereo(0.5, 1.0)];
+        let envelope = Envelope::of(&frame);
+        assert_eq!(envelope.peak, 1.0);
+        assert!((envelope.rms - 0.625_f64.sqrt()).abs() < 1e-12);
+    }
+}
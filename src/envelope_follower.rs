@@ -0,0 +1,111 @@
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct EnvelopeFollowerConfig {
+    /// time constant (ms) of a rising bar, 0 follows it immediately
+    pub attack_ms: VizFloat,
+    /// time constant (ms) of a falling bar
+    pub decay_ms: VizFloat,
+}
+
+/// Classic attack/decay envelope follower on every bar (like the needle of a VU meter). Each bar
+/// moves towards its new value with the attack time constant when rising and the decay time
+/// constant when falling, covering 1 - 1/e (~63%) of the way in that time. Bars start at 0.
+pub struct EnvelopeFollower {
+    attack: VizFloat,
+    decay: VizFloat,
+    state: Vec<VizFloat>,
+}
+
+impl EnvelopeFollower {
+    pub fn new(config: EnvelopeFollowerConfig, fps: u64) -> Self {
+        Self {
+            attack: coefficient(config.attack_ms, fps),
+            decay: coefficient(config.decay_ms, fps),
+            state: Vec::new(),
+        }
+    }
+}
+
+impl FramedMapper<VizFloat, VizFloat> for EnvelopeFollower {
+    fn map<'a>(&'a mut self, input: &'a mut [VizFloat]) -> Result<Option<&'a mut [VizFloat]>> {
+        self.state.resize(input.len(), 0.0);
+        let (attack, decay) = (self.attack, self.decay);
+        input
+            .iter_mut()
+            .zip(self.state.iter_mut())
+            .for_each(move |(v, state)| {
+                let coefficient = if *v > *state { attack } else { decay };
+                *state = *v + (coefficient * (*state - *v));
+                *v = *state;
+            });
+
+        Ok(Some(input))
+    }
+}
+
+// how much of the previous value is kept per frame
+fn coefficient(time_ms: VizFloat, fps: u64) -> VizFloat {
+    if time_ms <= 0.0 {
+        0.0
+    } else {
+        (-1000.0 / (time_ms * (fps as VizFloat))).exp()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::envelope_follower::{EnvelopeFollower, EnvelopeFollowerConfig};
+    use crate::framed::FramedMapper;
+    use crate::util::VizFloat;
+
+    const RISEN: VizFloat = 1.0 - (1.0 / std::f64::consts::E);
+
+    fn step(follower: &mut EnvelopeFollower, v: VizFloat, frames: usize) -> Vec<VizFloat> {
+        (0..frames)
+            .map(|_| {
+                let mut frame = vec![v, 0.5];
+                follower
+                    .map(&mut frame)
+                    .expect("should map")
+                    .expect("has frame")[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn step_rises_with_attack_and_falls_with_decay() {
+        // at 100fps, 50ms attack is 5 frames and 300ms decay is 30 frames
+        let config = EnvelopeFollowerConfig {
+            attack_ms: 50.0,
+            decay_ms: 300.0,
+        };
+        let mut follower = EnvelopeFollower::new(config, 100);
+
+        let rise = step(&mut follower, 1.0, 10);
+        assert!(rise[3] < RISEN, "{:?}", rise);
+        assert!(rise[4] >= RISEN - 1e-9, "{:?}", rise);
+        assert!(rise.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(rise[9] > 0.8);
+
+        // starts falling from where it got to, much slower than it rose
+        let top = rise[9];
+        let fall = step(&mut follower, 0.0, 30);
+        assert!(fall[0] > top * 0.9, "{:?}", fall);
+        assert!(fall[28] > top / std::f64::consts::E, "{:?}", fall);
+        assert!(fall[29] <= (top / std::f64::consts::E) + 1e-9, "{:?}", fall);
+    }
+
+    #[test]
+    fn zero_attack_follows_immediately() {
+        let config = EnvelopeFollowerConfig {
+            attack_ms: 0.0,
+            decay_ms: 100.0,
+        };
+        let mut follower = EnvelopeFollower::new(config, 60);
+        assert_eq!(step(&mut follower, 0.75, 1), vec![0.75]);
+    }
+}
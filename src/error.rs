@@ -0,0 +1,24 @@
+use std::fmt;
+
+/// Errors which are expected to happen to users (as opposed to bugs), so `main` can tell them what
+/// went wrong without a backtrace. Everything else is a plain `anyhow` error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VizError {
+    /// the file isn't audio we can decode
+    UnsupportedFormat(String),
+    /// SDL (the window, audio or events) could not be set up
+    #[cfg(feature = "gui")]
+    Sdl(String),
+}
+
+impl fmt::Display for VizError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VizError::UnsupportedFormat(msg) => write!(f, "unsupported audio format, {}", msg),
+            #[cfg(feature = "gui")]
+            VizError::Sdl(msg) => write!(f, "sdl2: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for VizError {}
@@ -1,12 +1,29 @@
 use crate::channeled::Channeled;
 use crate::framed::FramedMapper;
-use anyhow::Result;
 use crate::util::VizFloat;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct AdaptiveSmoothingConfig {
+    /// how strongly the spectral flux lowers alpha, 0.0 never changes it
+    pub sensitivity: VizFloat,
+}
+
+impl AdaptiveSmoothingConfig {
+    /// alpha for a frame with this spectral flux (0.0..1.0), alpha * (1 - flux)^sensitivity
+    pub fn adapt(&self, alpha: VizFloat, flux: VizFloat) -> VizFloat {
+        alpha * (1.0 - flux).max(0.0).powf(self.sensitivity)
+    }
+}
 
 pub struct ExponentialSmoothing {
     previous: Vec<Vec<Channeled<VizFloat>>>,
     n_prev: usize,
     alpha: VizFloat,
+    warmup_frames: usize,
+    n_frames: usize,
+    adaptive: Option<AdaptiveSmoothingConfig>,
 }
 
 impl ExponentialSmoothing {
@@ -15,8 +32,37 @@ impl ExponentialSmoothing {
             previous: Vec::with_capacity(seek_back_limit),
             n_prev: seek_back_limit,
             alpha,
+            warmup_frames: 0,
+            n_frames: 0,
+            adaptive: None,
+        }
+    }
+
+    /// Start from silence and ease alpha from 1.0 (hold the previous frame) down to its target
+    /// over the first `frames` frames, so the first frames fade in instead of popping up
+    pub fn with_warmup(self, frames: usize) -> Self {
+        Self {
+            warmup_frames: frames,
+            ..self
         }
     }
+
+    /// Smooth less when a frame changes a lot from the previous one (an attack) and fully when it
+    /// barely changes (a sustain). Not applied during the warmup, which starts from silence.
+    pub fn with_adaptive(self, adaptive: Option<AdaptiveSmoothingConfig>) -> Self {
+        Self { adaptive, ..self }
+    }
+
+    // alpha for the next frame, linearly from close to 1.0 to alpha during the warmup
+    fn current_alpha(&self) -> VizFloat {
+        if self.n_frames >= self.warmup_frames {
+            return self.alpha;
+        }
+
+        let remaining = (self.warmup_frames - self.n_frames) as VizFloat;
+        let ramp = remaining / ((self.warmup_frames + 1) as VizFloat);
+        self.alpha + ((1.0 - self.alpha) * ramp)
+    }
 }
 
 impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for ExponentialSmoothing {
@@ -24,17 +70,41 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for ExponentialSmoot
         &'a mut self,
         input: &'a mut [Channeled<VizFloat>],
     ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        // a NaN/inf (e.g. from denormals in the FFT) would otherwise stay in the previous frames
+        // and poison every frame after it
+        input
+            .iter_mut()
+            .for_each(move |c| c.as_mut_ref().for_each(move |v| *v = finite_or_zero(*v)));
+
+        // the warmup starts from a silent previous frame
+        if self.previous.is_empty() && self.warmup_frames > 0 {
+            self.previous.push(
+                input
+                    .iter()
+                    .map(move |c| c.as_ref().map(move |_| 0.0))
+                    .collect(),
+            );
+        }
+
+        let mut alpha = self.current_alpha();
+        let warmed_up = self.n_frames >= self.warmup_frames;
+        self.n_frames = self.n_frames.saturating_add(1);
         if let Some(prev) = self.previous.get(0) {
-            let alpha = self.alpha;
+            if let (Some(adaptive), true) = (self.adaptive, warmed_up) {
+                alpha = adaptive.adapt(alpha, spectral_flux(input, prev));
+            }
+
             let alpha_inv = 1.0 - alpha;
 
             input
                 .iter_mut()
                 .map(move |c| c.as_mut_ref())
-                .zip(prev.iter().copied())
-                .map(move |(new, pre)| new.zip(pre).expect("mono/stereo should match"))
+                .zip(prev.iter())
+                .map(move |(new, pre)| new.zip(pre.as_ref()).expect("mono/stereo should match"))
                 .for_each(move |zipped| {
-                    zipped.for_each(move |(new, prev)| *new = (*new * alpha_inv) + (prev * alpha))
+                    zipped.for_each(move |(new, prev)| {
+                        *new = (*new * alpha_inv) + (finite_or_zero(*prev) * alpha)
+                    })
                 })
         }
 
@@ -53,3 +123,115 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for ExponentialSmoot
         Ok(Some(input))
     }
 }
+
+// how much a frame changed from the previous one, the summed absolute difference relative to the
+// summed magnitudes of both frames (0.0 unchanged, 1.0 nothing in common)
+fn spectral_flux(input: &[Channeled<VizFloat>], prev: &[Channeled<VizFloat>]) -> VizFloat {
+    let (diff, total) = input
+        .iter()
+        .zip(prev.iter())
+        .flat_map(move |(new, pre)| new.channels().zip(pre.channels()))
+        .map(move |(new, pre)| (*new, finite_or_zero(*pre)))
+        .fold((0.0, 0.0), move |(diff, total), (new, pre)| {
+            (diff + (new - pre).abs(), total + new.abs() + pre.abs())
+        });
+
+    if total > 0.0 {
+        diff / total
+    } else {
+        0.0
+    }
+}
+
+fn finite_or_zero(v: VizFloat) -> VizFloat {
+    if v.is_finite() {
+        v
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::exponential_smoothing::{AdaptiveSmoothingConfig, ExponentialSmoothing};
+    use crate::framed::FramedMapper;
+
+    #[test]
+    fn recovers_from_nan_frame() {
+        let mut smoothing = ExponentialSmoothing::new(4, 0.5);
+        let mut frames = [
+            vec![Channeled::Mono(1.0)],
+            vec![Channeled::Mono(f64::NAN)],
+            vec![Channeled::Mono(f64::INFINITY)],
+            vec![Channeled::Mono(1.0)],
+        ];
+        let out = frames
+            .iter_mut()
+            .map(|frame| match smoothing.map(frame).expect("should map") {
+                Some(out) => out[0].clone(),
+                None => panic!("should output a frame"),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            out,
+            vec![
+                Channeled::Mono(1.0),
+                Channeled::Mono(0.5),
+                Channeled::Mono(0.25),
+                Channeled::Mono(0.625)
+            ]
+        );
+    }
+
+    #[test]
+    fn warmup_fades_in_from_silence() {
+        // biggest jump between frames of a constant input, starting from silence
+        let biggest_step = |mut smoothing: ExponentialSmoothing| {
+            let mut last = 0.0;
+            (0..8)
+                .map(|_| {
+                    let mut frame = vec![Channeled::Mono(1.0)];
+                    let out = smoothing.map(&mut frame).expect("ok").expect("some")[0].to_mono();
+                    let step = out - last;
+                    last = out;
+                    step
+                })
+                .fold(0.0, f64::max)
+        };
+
+        let without = biggest_step(ExponentialSmoothing::new(1, 0.5));
+        let with = biggest_step(ExponentialSmoothing::new(1, 0.5).with_warmup(4));
+        assert_eq!(without, 1.0);
+        assert!(with < 0.5 * without, "{}", with);
+
+        // alpha is back at its target after the warmup
+        let mut smoothing = ExponentialSmoothing::new(1, 0.5).with_warmup(4);
+        assert!((smoothing.current_alpha() - 0.9).abs() < 1e-12);
+        for _ in 0..4 {
+            smoothing.map(&mut [Channeled::Mono(1.0)]).expect("ok");
+        }
+        assert_eq!(smoothing.current_alpha(), 0.5);
+    }
+
+    #[test]
+    fn adaptive_smooths_large_changes_less() {
+        // how far (0..1) the output moves from a steady 1.0 towards a new frame of `to`
+        let moved = |to: f64| {
+            let mut smoothing = ExponentialSmoothing::new(1, 0.8)
+                .with_adaptive(Some(AdaptiveSmoothingConfig { sensitivity: 4.0 }));
+            smoothing.map(&mut [Channeled::Mono(1.0)]).expect("ok");
+            let mut frame = [Channeled::Mono(to)];
+            let out = smoothing.map(&mut frame).expect("ok").expect("some")[0].to_mono();
+            (out - 1.0) / (to - 1.0)
+        };
+
+        let small = moved(1.1);
+        let large = moved(10.0);
+        assert!(large > 2.0 * small, "{} vs {}", large, small);
+        // a small change is still smoothed, a large one is barely smoothed at all
+        assert!(small < 0.5, "{}", small);
+        assert!(large > 0.99, "{}", large);
+    }
+}
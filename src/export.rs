@@ -0,0 +1,446 @@
+use crate::binner::BinFrequency;
+use crate::cli::CliArgs;
+use crate::clipping::ClipDetect;
+use crate::envelope::EnvelopeTap;
+use crate::framed::Framed;
+use crate::pipeline::{
+    create_group_delay_pipeline, create_raw_spectrum_pipeline, create_viz_pipeline,
+    frame_timestamps, viz_bin_frequencies, VizPipelineTaps,
+};
+use crate::quality::Quality;
+use crate::util::{log_timed, write_csv_row, VizFloat};
+use crate::wav::open_audio;
+use anyhow::Result;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const BUF_SIZE: usize = 32768;
+
+/// Runs the pipeline over `file` without rendering, writing one CSV row of bar values (or of the
+/// raw spectrum, or the group delay of its bins) per frame, and optionally one row of `peak,rms`
+/// per frame to a second file. Paths ending in `.json` get JSON with the timestamp of every frame
+/// instead (see `export_json`).
+///
+/// Bar values come with a `<out>.bins.json` sidecar describing the frequencies of every column.
+pub fn export_file(file: &str, out_path: &str, args: &CliArgs) -> Result<()> {
+    let mut config = args.load_config()?;
+    // exports don't run in real time, and shouldn't depend on how fast the machine is
+    if config.quality == Quality::Adaptive {
+        config.quality = Quality::Full;
+    }
+
+    let max_frames = config.max_frames;
+    // --seek skips whole frames from the start
+    let fps = config.fps as f64;
+    let start_frame = args
+        .seek
+        .map(move |start_at| start_at.as_secs_f64() * fps)
+        .unwrap_or(0.0) as usize;
+    let json = Path::new(out_path).extension() == Some(OsStr::new("json"));
+    let envelope = match &args.envelope {
+        Some(path) => Some((EnvelopeTap::new(), BufWriter::new(File::create(path)?))),
+        None => None,
+    };
+    let taps = VizPipelineTaps {
+        envelope: envelope.as_ref().map(|(tap, _)| tap.clone()),
+        ..VizPipelineTaps::default()
+    };
+    let source = ClipDetect::new(open_audio(file, BUF_SIZE, args.raw_format, args.strict)?);
+    let clipping = source.stats();
+    let timestamp = frame_timestamps(&source, &config);
+    let timestamp = move |n| timestamp(start_frame + n);
+    let out = BufWriter::new(File::create(out_path)?);
+    let description = format!("export {} to {}", file, out_path);
+    let n_frames = if args.raw_spectrum {
+        let mut frames = create_raw_spectrum_pipeline(source, config, taps)?;
+        frames.seek_frame(start_frame as isize)?;
+        log_timed(description, || {
+            if json {
+                export_json(frames, out, envelope, max_frames, timestamp)
+            } else {
+                export_csv(frames, out, envelope, max_frames)
+            }
+        })?
+    } else if args.group_delay {
+        let mut frames = create_group_delay_pipeline(source, config)?;
+        frames.seek_frame(start_frame as isize)?;
+        log_timed(description, || {
+            if json {
+                export_json(frames, out, envelope, max_frames, timestamp)
+            } else {
+                export_csv(frames, out, envelope, max_frames)
+            }
+        })?
+    } else {
+        let bins_path = Path::new(out_path).with_extension("bins.json");
+        let bins = viz_bin_frequencies(&source, &config)?;
+        write_bins_json(BufWriter::new(File::create(&bins_path)?), &bins)?;
+        println!(
+            "wrote {} bin frequencies to {}",
+            bins.len(),
+            bins_path.display()
+        );
+
+        let mut frames = create_viz_pipeline(source, config, taps)?;
+        frames.seek_frame(start_frame as isize)?;
+        log_timed(description, || {
+            if json {
+                export_json(frames, out, envelope, max_frames, timestamp)
+            } else {
+                export_csv(frames, out, envelope, max_frames)
+            }
+        })?
+    };
+    println!("exported {} frames to {}", n_frames, out_path);
+    clipping.report();
+    Ok(())
+}
+
+/// Writes every frame (or at most `max_frames` frames) as a CSV row, returns the number of rows.
+///
+/// If `envelope` is given, the tap's values are written to the second writer after every frame.
+pub fn export_csv<F, I, W>(
+    frames: F,
+    mut out: W,
+    envelope: Option<(EnvelopeTap, W)>,
+    max_frames: Option<usize>,
+) -> Result<usize>
+where
+    F: Framed<VizFloat, I>,
+    W: Write,
+{
+    export_frames(frames, &mut out, envelope, max_frames, |out, _, frame| {
+        write_csv_row(out, frame)
+    })
+}
+
+/// Like `export_csv`, but writes a JSON array with one `{"t": .., "bins": [..]}` object per line.
+///
+/// `t` is `timestamp(n)` for the n-th frame written, in seconds.
+pub fn export_json<F, I, W, T>(
+    frames: F,
+    mut out: W,
+    envelope: Option<(EnvelopeTap, W)>,
+    max_frames: Option<usize>,
+    timestamp: T,
+) -> Result<usize>
+where
+    F: Framed<VizFloat, I>,
+    W: Write,
+    T: Fn(usize) -> f64,
+{
+    out.write_all(b"[")?;
+    let n_frames = export_frames(frames, &mut out, envelope, max_frames, |out, n, frame| {
+        if n != 0 {
+            out.write_all(b",")?;
+        }
+
+        write!(
+            out,
+            "\n  {{\"t\": {}, \"bins\": [",
+            JsonNumber(timestamp(n))
+        )?;
+        for (idx, v) in frame.iter().enumerate() {
+            if idx != 0 {
+                out.write_all(b", ")?;
+            }
+
+            write!(out, "{}", JsonNumber(*v))?;
+        }
+        out.write_all(b"]}")?;
+        Ok(())
+    })?;
+    out.write_all(b"\n]\n")?;
+    out.flush()?;
+    Ok(n_frames)
+}
+
+// writes every frame with `write_frame(out, n, frame)`, n counting the frames written
+fn export_frames<F, I, W, E, R>(
+    mut frames: F,
+    out: &mut W,
+    mut envelope: Option<(EnvelopeTap, E)>,
+    max_frames: Option<usize>,
+    mut write_frame: R,
+) -> Result<usize>
+where
+    F: Framed<VizFloat, I>,
+    W: Write,
+    E: Write,
+    R: FnMut(&mut W, usize, &[VizFloat]) -> Result<()>,
+{
+    let mut n_frames = 0;
+    while max_frames.map(|max| n_frames < max).unwrap_or(true) {
+        if let Some(frame) = frames.next_frame()? {
+            write_frame(out, n_frames, frame)?;
+            if let Some((tap, envelope_out)) = &mut envelope {
+                let latest = tap.latest();
+                write_csv_row(envelope_out, &[latest.peak, latest.rms])?;
+            }
+
+            n_frames += 1;
+        } else {
+            break;
+        }
+    }
+
+    out.flush()?;
+    if let Some((_, envelope_out)) = &mut envelope {
+        envelope_out.flush()?;
+    }
+
+    Ok(n_frames)
+}
+
+/// Writes the bin frequencies as JSON, `{"bins": [{"low_hz": .., "high_hz": .., "center_hz": ..}]}`
+pub fn write_bins_json<W>(mut out: W, bins: &[BinFrequency]) -> Result<()>
+where
+    W: Write,
+{
+    out.write_all(b"{\n  \"bins\": [")?;
+    for (idx, bin) in bins.iter().enumerate() {
+        if idx != 0 {
+            out.write_all(b",")?;
+        }
+
+        write!(
+            out,
+            "\n    {{\"low_hz\": {}, \"high_hz\": {}, \"center_hz\": {}}}",
+            JsonNumber(bin.low_hz),
+            JsonNumber(bin.high_hz),
+            JsonNumber(bin.center_hz)
+        )?;
+    }
+
+    out.write_all(b"\n  ]\n}\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+// JSON has no NaN or infinity, those are written as null
+struct JsonNumber(VizFloat);
+
+impl fmt::Display for JsonNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_finite() {
+            write!(f, "{}", self.0)
+        } else {
+            f.write_str("null")
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::binner::BinFrequency;
+    use crate::channeled::Channeled;
+    use crate::cli::parse_args;
+    use crate::envelope::EnvelopeTap;
+    use crate::export::{export_csv, export_json, write_bins_json, JsonNumber};
+    use crate::framed::Sampled;
+    use crate::pipeline::tests::{fast_config, tone};
+    use crate::pipeline::{
+        create_group_delay_pipeline, create_raw_spectrum_pipeline, create_viz_pipeline,
+        frame_timestamps, viz_bin_frequencies, VizPipelineTaps,
+    };
+    use crate::util::VizFloat;
+    use crate::vec_samples::VecSamples;
+    use crate::wav::tests::WavBuilder;
+    use crate::wav::{SampleRaw, WavFile};
+
+    pub fn sine_i16(freq: f64, sample_rate: u32, n: usize) -> Vec<i16> {
+        (0..n)
+            .map(|i| {
+                let t = (i as f64) / (sample_rate as f64);
+                ((t * freq * std::f64::consts::PI * 2.0).sin() * 16000.0) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn export_respects_frame_limit() {
+        let path = WavBuilder::pcm(1, 44100, 16)
+            .samples_i16(&sine_i16(440.0, 44100, 22050))
+            .write("export-frames");
+        let args =
+            parse_args(vec!["--frames".to_string(), "10".to_string()]).expect("should parse");
+        let mut config = fast_config();
+        args.apply(&mut config);
+
+        let max_frames = config.max_frames;
+        let frames = create_viz_pipeline(
+            WavFile::open(&path, 4096).expect("should open"),
+            config,
+            VizPipelineTaps::default(),
+        )
+        .expect("should build");
+        let mut out = Vec::new();
+        let n = export_csv(frames, &mut out, None, max_frames).expect("should export");
+        let out = String::from_utf8(out).expect("utf8");
+
+        assert_eq!(n, 10);
+        assert_eq!(out.lines().count(), 10);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn export_envelope_of_sine() {
+        // 441Hz at 44100Hz is exactly 100 samples per period, so the peak sample is on the crest
+        let amplitude: VizFloat = SampleRaw::TwoBytes(16000).into();
+        let source = tone(441.0, 44100, 22050);
+
+        let tap = EnvelopeTap::new();
+        let taps = VizPipelineTaps {
+            envelope: Some(tap.clone()),
+            ..VizPipelineTaps::default()
+        };
+        let frames = create_viz_pipeline(source, fast_config(), taps).expect("should build");
+        let mut out = Vec::new();
+        let mut envelope_out = Vec::new();
+        let n = export_csv(frames, &mut out, Some((tap, &mut envelope_out)), Some(20))
+            .expect("should export");
+        let envelope_out = String::from_utf8(envelope_out).expect("utf8");
+
+        assert_eq!(n, 20);
+        assert_eq!(envelope_out.lines().count(), 20);
+        for line in envelope_out.lines() {
+            let values = line
+                .split(',')
+                .map(|v| v.parse::<VizFloat>().expect("should be a number"))
+                .collect::<Vec<_>>();
+            assert_eq!(values.len(), 2);
+            assert!((values[0] - amplitude).abs() < 1e-9, "peak {}", values[0]);
+            let expected_rms = amplitude / (2.0 as VizFloat).sqrt();
+            assert!(
+                (values[1] - expected_rms).abs() < expected_rms * 0.01,
+                "rms {}",
+                values[1]
+            );
+        }
+    }
+
+    #[test]
+    fn export_raw_spectrum_width() {
+        let source = tone(440.0, 44100, 22050);
+        let config = fast_config();
+        let n_out = source.samples_from_dur(config.data_window()) / 2;
+
+        let frames = create_raw_spectrum_pipeline(source, config, VizPipelineTaps::default())
+            .expect("should build");
+        let mut out = Vec::new();
+        let n = export_csv(frames, &mut out, None, Some(5)).expect("should export");
+        let out = String::from_utf8(out).expect("utf8");
+
+        assert_eq!(n, 5);
+        for line in out.lines() {
+            assert_eq!(line.split(',').count(), n_out);
+        }
+    }
+
+    #[test]
+    fn export_group_delay_of_impulse() {
+        // an impulse 100 samples into the first window is delayed by 100 samples at every bin
+        let samples = (0..44100)
+            .map(|i| Channeled::Mono(if i == 100 { 1.0 } else { 0.0 }))
+            .collect::<Vec<Channeled<VizFloat>>>();
+        let frames = create_group_delay_pipeline(VecSamples::new(samples, 44100), fast_config())
+            .expect("should build");
+        let mut out = Vec::new();
+        let n = export_csv(frames, &mut out, None, Some(1)).expect("should export");
+        let out = String::from_utf8(out).expect("utf8");
+
+        assert_eq!(n, 1);
+        for value in out.trim_end().split(',') {
+            let delay: VizFloat = value.parse().expect("should be a number");
+            assert!((delay - 100.0).abs() < 1e-6, "delay {}", delay);
+        }
+    }
+
+    #[test]
+    fn json_frames_are_one_stride_apart() {
+        let source = tone(440.0, 44100, 22050);
+        let config = fast_config();
+        let bins = config.binning.bins;
+        let timestamp = frame_timestamps(&source, &config);
+        let frames = create_viz_pipeline(source, config.clone(), VizPipelineTaps::default())
+            .expect("should build");
+        let mut out = Vec::new();
+        let n = export_json(frames, &mut out, None, Some(10), timestamp).expect("should export");
+        let out = String::from_utf8(out).expect("utf8");
+        assert_eq!(n, 10);
+        assert!(out.starts_with("[\n") && out.ends_with("\n]\n"), "{}", out);
+
+        let frames = out
+            .lines()
+            .filter(|line| line.starts_with("  {\"t\": "))
+            .map(|line| {
+                let (t, bins) = line["  {\"t\": ".len()..]
+                    .split_once(", \"bins\": [")
+                    .expect("should have bins");
+                let values = bins
+                    .trim_end_matches(',')
+                    .trim_end_matches("]}")
+                    .split(", ");
+                (t.parse::<f64>().expect("t is a number"), values.count())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(frames.len(), 10);
+        assert!(frames.iter().all(|(_, n_bins)| *n_bins == bins));
+
+        // the middle of the first window, then one stride (294 samples at 150fps) per frame
+        let window = fast_config().window_size(&VecSamples::<i16>::new(Vec::new(), 44100));
+        assert!((frames[0].0 - (window as f64 / 2.0) / 44100.0).abs() < 1e-12);
+        for pair in frames.windows(2) {
+            assert!(
+                (pair[1].0 - pair[0].0 - 1.0 / 150.0).abs() < 1e-9,
+                "{:?}",
+                pair
+            );
+        }
+    }
+
+    #[test]
+    fn bins_sidecar_has_every_bin() {
+        let source = tone(440.0, 44100, 4410);
+        let config = fast_config();
+        let bins = viz_bin_frequencies(&source, &config).expect("should compute bins");
+        assert_eq!(bins.len(), config.binning.bins);
+        for pair in bins.windows(2) {
+            assert_eq!(pair[0].high_hz, pair[1].low_hz);
+        }
+        for bin in &bins {
+            assert!(bin.low_hz < bin.center_hz && bin.center_hz < bin.high_hz);
+        }
+
+        let mut out = Vec::new();
+        write_bins_json(&mut out, &bins).expect("should write");
+        let out = String::from_utf8(out).expect("utf8");
+        assert!(out.starts_with("{\n  \"bins\": [\n    {\"low_hz\": "));
+        assert_eq!(out.matches("\"center_hz\"").count(), config.binning.bins);
+        assert!(out.ends_with("}\n  ]\n}\n"));
+    }
+
+    #[test]
+    fn json_writes_non_finite_numbers_as_null() {
+        assert_eq!(JsonNumber(1.5).to_string(), "1.5");
+        assert_eq!(JsonNumber(VizFloat::NAN).to_string(), "null");
+        assert_eq!(JsonNumber(VizFloat::NEG_INFINITY).to_string(), "null");
+
+        let bin = BinFrequency {
+            low_hz: 0.0,
+            high_hz: VizFloat::INFINITY,
+            center_hz: VizFloat::NAN,
+        };
+        let mut out = Vec::new();
+        write_bins_json(&mut out, &[bin]).expect("should write");
+        let out = String::from_utf8(out).expect("utf8");
+        assert!(
+            out.contains("{\"low_hz\": 0, \"high_hz\": null, \"center_hz\": null}"),
+            "{}",
+            out
+        );
+    }
+}
@@ -1,12 +1,35 @@
 use crate::channeled::Channeled;
 use crate::framed::FramedMapper;
-use crate::util::{log_timed, slice_copy_from, VizFloat, VizComplex, VizFftPlan};
+use crate::util::{log_timed, VizComplex, VizFftPlan, VizFloat};
 use anyhow::{anyhow, Result};
 use fftw::array::AlignedVec;
 use fftw::plan::R2CPlan;
 use fftw::types::Flag;
+use std::ffi::CString;
+use std::path::Path;
 
+// plans for the wisdom file are measured (Flag::MEASURE is no bits), which takes a while once but
+// finds a faster plan than estimating
+const WISDOM_FLAGS: Flag = Flag::DESTROYINPUT;
+
+/// FFT of each frame, outputting the magnitude of every bin (except DC)
+///
+/// Frames shorter than the FFT size are zero padded, which interpolates the spectrum (more output
+/// bins than input samples).
 pub struct FramedFft {
+    fft: RealFft,
+    out: Vec<Channeled<VizFloat>>,
+}
+
+/// FFT of each frame, keeping the complex output of every bin (except DC) so phase information is
+/// available to later stages
+pub struct FramedComplexFft {
+    fft: RealFft,
+    out: Vec<Channeled<VizComplex>>,
+}
+
+// the plan and buffers of both FFT mappers, which collect the output of the plan in their own way
+struct RealFft {
     plan: VizFftPlan,
     bufs: Option<Channeled<Bufs>>,
     n_out: usize,
@@ -26,15 +49,136 @@ impl Bufs {
             output: AlignedVec::new(out_size),
         }
     }
+
+    // the output of the plan, skipping the DC component
+    fn bins(&self) -> impl Iterator<Item = &VizComplex> + '_ {
+        self.output.iter().skip(1)
+    }
 }
 
 impl FramedFft {
     pub fn new(cap: usize) -> Result<Self> {
+        Ok(Self::with_fft(RealFft::new(cap, false)?))
+    }
+
+    /// Loads the FFTW wisdom file at `wisdom` (see [`warm_wisdom`]) and plans with it, sizes which
+    /// aren't in the file (or every size, when there is no file yet) are planned like
+    /// [`FramedFft::new`]
+    pub fn new_with_wisdom(cap: usize, wisdom: &Path) -> Result<Self> {
+        let from_wisdom = wisdom.exists();
+        if from_wisdom {
+            import_wisdom(wisdom)?;
+        } else {
+            eprintln!(
+                "[warn] no fft wisdom at {} (create it with --warm-wisdom), estimating the plan",
+                wisdom.display()
+            );
+        }
+        Ok(Self::with_fft(RealFft::new(cap, from_wisdom)?))
+    }
+
+    fn with_fft(fft: RealFft) -> Self {
+        let out = Vec::with_capacity(fft.n_out);
+        Self { fft, out }
+    }
+}
+
+/// Plans every size with `MEASURE` and writes the wisdom FFTW collected to `path`, so
+/// [`FramedFft::new_with_wisdom`] gets the measured plans without measuring again
+pub fn warm_wisdom(sizes: &[usize], path: &Path) -> Result<()> {
+    for &size in sizes {
+        check_fft_size(size)?;
+        log_timed(format!("measure fft for size {}", size), || {
+            VizFftPlan::aligned(&[size], WISDOM_FLAGS).map_err(map_fftw_error)
+        })?;
+    }
+
+    let filename = wisdom_filename(path)?;
+    let _lock = fftw::FFTW_MUTEX.lock().expect("fftw mutex poisoned");
+    if unsafe { fftw_sys::fftw_export_wisdom_to_filename(filename.as_ptr()) } == 0 {
+        return Err(anyhow!("could not write fft wisdom to {}", path.display()));
+    }
+
+    Ok(())
+}
+
+fn import_wisdom(path: &Path) -> Result<()> {
+    let filename = wisdom_filename(path)?;
+    let _lock = fftw::FFTW_MUTEX.lock().expect("fftw mutex poisoned");
+    if unsafe { fftw_sys::fftw_import_wisdom_from_filename(filename.as_ptr()) } == 0 {
+        return Err(anyhow!(
+            "could not load fft wisdom from {} (create it with --warm-wisdom)",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+fn wisdom_filename(path: &Path) -> Result<CString> {
+    path.to_str()
+        .and_then(move |path| CString::new(path).ok())
+        .ok_or_else(move || anyhow!("unsupported wisdom path {}", path.display()))
+}
+
+fn check_fft_size(cap: usize) -> Result<()> {
+    if cap < 2 {
+        return Err(anyhow!("fft size must be at least 2 samples, got {}", cap));
+    }
+
+    Ok(())
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for FramedFft {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let bufs = self.fft.run_plan(input)?;
+
+        // converts complex data to real data using norm() (magnitude of complex number), straight
+        // from the output of the plan into our own buffer because a padded FFT has more outputs
+        // than the frame has samples
+        self.out.clear();
+        self.out.extend(
+            bufs.as_ref()
+                .map(move |buf| buf.bins().map(move |v| v.norm())),
+        );
+        Ok(Some(self.out.as_mut_slice()))
+    }
+
+    fn map_frame_size(&self, _: usize) -> usize {
+        self.fft.n_out
+    }
+}
+
+impl FramedComplexFft {
+    pub fn new(cap: usize) -> Result<Self> {
+        let fft = RealFft::new(cap, false)?;
+        let out = Vec::with_capacity(fft.n_out);
+        Ok(Self { fft, out })
+    }
+}
+
+impl RealFft {
+    // with `from_wisdom` the measured plan from loaded wisdom is used if there is one
+    fn new(cap: usize, from_wisdom: bool) -> Result<Self> {
+        check_fft_size(cap)?;
+
         // fft is defined as having (N / 2) + 1 outputs but we skip
         // DC at index 0 so N / 2
         let n_out = cap / 2;
         let plan = log_timed(format!("plan fft for size {}", cap), || {
-            VizFftPlan::aligned(&[cap], Flag::ESTIMATE | Flag::DESTROYINPUT).map_err(map_fftw_error)
+            let wise = if from_wisdom {
+                VizFftPlan::aligned(&[cap], WISDOM_FLAGS | Flag::WISDOWMONLY).ok()
+            } else {
+                None
+            };
+            match wise {
+                Some(plan) => Ok(plan),
+                None => VizFftPlan::aligned(&[cap], Flag::ESTIMATE | Flag::DESTROYINPUT)
+                    .map_err(map_fftw_error),
+            }
         })?;
         Ok(Self {
             plan,
@@ -43,23 +187,24 @@ impl FramedFft {
             n_in: cap,
         })
     }
-}
 
-impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for FramedFft {
-    fn map<'a>(
-        &'a mut self,
-        input: &'a mut [Channeled<VizFloat>],
-    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
-        // lazily setup the bufs
-        let bufs = if let Some(buf) = self.bufs.as_mut() {
-            buf
-        } else {
-            // stereo needs two bufs, mono needs one buf, so this map will handle creating one for
-            // each, depending on whether or not input[0] is mono or stereo
-            let created = (&input[0]).map(|_| Bufs::new(self.n_in));
-            self.bufs = Some(created);
-            self.bufs.as_mut().unwrap()
-        };
+    // runs the plan on `input` (zero padded to the FFT size), leaving the output of every channel
+    // in its bufs
+    fn run_plan(&mut self, input: &[Channeled<VizFloat>]) -> Result<&Channeled<Bufs>> {
+        let n_in = self.n_in;
+        if input.len() > n_in {
+            return Err(anyhow!(
+                "frame of {} samples does not fit into an fft of size {}",
+                input.len(),
+                n_in
+            ));
+        }
+
+        // lazily setup the bufs, one buf per channel (stereo needs two bufs, mono needs one buf),
+        // so this map will handle creating one for each channel of input[0]
+        let bufs = self
+            .bufs
+            .get_or_insert_with(move || input[0].as_ref().map(|_| Bufs::new(n_in)));
 
         // load input into the buffers:
         bufs.as_mut_ref()
@@ -82,33 +227,92 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for FramedFft {
                     .for_each(move |t| *t = 0.0)
             });
 
+        // transform input data in buf: &mut Bufs
+        // input is in buf.input
+        // output (complex) will be in buf.output
         let plan = &mut self.plan;
+        bufs.as_mut_ref().try_map(move |buf| {
+            let i = buf.input.as_slice_mut();
+            let o = buf.output.as_slice_mut();
+            plan.r2c(i, o).map_err(map_fftw_error)
+        })?;
 
-        let updated = slice_copy_from(
-            input,
-            bufs.as_mut_ref()
-                .try_map(move |buf| {
-                    // transform input data in buf: &mut Bufs
-                    // input is in buf.input
-                    // output (complex) will be in buf.output
-                    let i = buf.input.as_slice_mut();
-                    let o = buf.output.as_slice_mut();
-                    plan.r2c(i, o).map_err(map_fftw_error)?;
-
-                    // return an iterator over the output which skips the DC component (skip(1)) and
-                    // converts complex data to real data using norm() (magnitude of complex number)
-                    Ok(o.iter().skip(1).map(move |v| v.norm()))
-                })?
-                .into_iter(),
-        );
-        Ok(Some(updated))
+        Ok(bufs)
+    }
+}
+
+// SAFETY: the plan and the aligned buffers are raw pointers owned by this FFT alone, nothing else
+// keeps a copy of them. Executing a plan is the one thread safe part of FFTW, and the fftw crate
+// takes FFTW_MUTEX to create and destroy plans and to allocate and free the buffers, so they can be
+// used and dropped on another thread than they were made on. It is not Sync, executing the plan
+// needs &mut self.
+unsafe impl Send for RealFft {}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizComplex>> for FramedComplexFft {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizComplex>]>> {
+        let bufs = self.fft.run_plan(input)?;
+
+        // collect the complex output
+        self.out.clear();
+        self.out
+            .extend(bufs.as_ref().map(move |buf| buf.bins().copied()));
+        Ok(Some(self.out.as_mut_slice()))
     }
 
     fn map_frame_size(&self, _: usize) -> usize {
-        self.n_out
+        self.fft.n_out
     }
 }
 
 fn map_fftw_error(err: fftw::error::Error) -> anyhow::Error {
     anyhow!("fftw: {:?}", err)
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::fft::{warm_wisdom, FramedFft, WISDOM_FLAGS};
+    use crate::util::VizFftPlan;
+    use crate::wav::tests::write_bytes;
+    use fftw::plan::R2CPlan;
+    use fftw::types::Flag;
+
+    #[test]
+    fn warmed_wisdom_is_reused() {
+        let path = std::env::temp_dir().join(format!("vis-rs-test-{}-wisdom", std::process::id()));
+        warm_wisdom(&[1764, 2048], &path).expect("should write wisdom");
+        assert!(path.exists());
+        // the planner keeps its wisdom, forget it so this only passes when the file is imported
+        {
+            let _lock = fftw::FFTW_MUTEX.lock().expect("fftw mutex poisoned");
+            unsafe { fftw_sys::fftw_forget_wisdom() };
+        }
+
+        for &size in &[1764, 2048] {
+            FramedFft::new_with_wisdom(size, &path).expect("should plan");
+            // only succeeds when the planner has wisdom for this size
+            VizFftPlan::aligned(&[size], WISDOM_FLAGS | Flag::WISDOWMONLY)
+                .expect("should plan from wisdom");
+        }
+
+        std::fs::remove_file(&path).expect("should clean up");
+        // estimates without a wisdom file, but a broken one is an error
+        FramedFft::new_with_wisdom(1764, &path).expect("should plan without wisdom");
+        let path = write_bytes("bad-wisdom", b"not wisdom");
+        assert!(FramedFft::new_with_wisdom(1764, &path).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn zero_size_fft_is_an_error() {
+        match FramedFft::new(0) {
+            Ok(_) => panic!("should not plan an fft of size 0"),
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "fft size must be at least 2 samples, got 0"
+            ),
+        }
+    }
+}
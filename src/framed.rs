@@ -1,7 +1,9 @@
+#[cfg(any(feature = "gui", test))]
+use crate::boxed::BoxedFramed;
 use crate::channeled::Channeled;
 use crate::util::try_use_iter;
 use anyhow::Result;
-use num_rational::Rational64;
+use std::fmt;
 use std::marker::PhantomData;
 use std::time::Duration;
 
@@ -63,7 +65,7 @@ pub trait Framed<E, I> {
 
     fn num_full_frames_remain(&self) -> usize {
         let total_full_frames = self.num_full_frames();
-        let frames_consumed = self.num_frames() - self.num_frames_remain();
+        let frames_consumed = self.num_frames().saturating_sub(self.num_frames_remain());
         if frames_consumed > total_full_frames {
             0
         } else {
@@ -85,6 +87,8 @@ pub trait Framed<E, I> {
         })
     }
 
+    /// like `map` for transforms which keep the type, but every frame is rewritten in place instead
+    /// of being copied into a separate output buffer
     fn map_mut<F>(self, mapper: F) -> MappedFramed<Self, FramedMutMapFn<E, F>, E, E, I>
     where
         Self: Sized,
@@ -96,18 +100,40 @@ pub trait Framed<E, I> {
         })
     }
 
+    /// calls `f` with every frame passing through (e.g. to debug intermediate stages), without
+    /// changing it
+    fn tap<F>(self, f: F) -> MappedFramed<Self, FramedTapFn<E, F>, E, E, I>
+    where
+        Self: Sized,
+        F: FnMut(&[E]),
+    {
+        self.lift(move |_| FramedTapFn {
+            f,
+            _in_typ: PhantomData,
+        })
+    }
+
     fn collect(mut self) -> Result<Vec<Vec<E>>>
     where
         Self: Sized,
-        E: Copy,
+        E: Clone,
     {
         let mut big_out_buf = Vec::with_capacity(self.num_frames_remain());
         while let Some(frame) = self.next_frame()? {
-            big_out_buf.push(frame.iter().copied().collect::<Vec<_>>());
+            big_out_buf.push(frame.to_vec());
         }
 
         Ok(big_out_buf)
     }
+
+    #[cfg(any(feature = "gui", test))]
+    fn boxed(self) -> BoxedFramed<E>
+    where
+        Self: Sized + Send + 'static,
+        I: 'static,
+    {
+        BoxedFramed::new(self)
+    }
 }
 
 pub trait Samples<T, I>: Sampled {
@@ -124,6 +150,25 @@ pub trait Samples<T, I>: Sampled {
 
     fn seek_samples(&mut self, n: isize) -> Result<()>;
 
+    /// index of the next sample `next_sample` reads
+    #[cfg(any(feature = "gui", test))]
+    fn sample_position(&self) -> usize {
+        self.num_samples().saturating_sub(self.num_samples_remain())
+    }
+
+    /// moves to the absolute sample `abs`, positions past the end are clamped to the end
+    #[cfg(any(feature = "gui", test))]
+    fn seek_to_sample(&mut self, abs: usize) -> Result<()> {
+        let abs = std::cmp::min(abs, self.num_samples());
+        self.seek_samples((abs as isize) - (self.sample_position() as isize))
+    }
+
+    /// moves to the sample `from_start` into the source (rounded to the nearest sample)
+    #[cfg(any(feature = "gui", test))]
+    fn skip_to_duration(&mut self, from_start: Duration) -> Result<()> {
+        self.seek_to_sample(self.samples_from_dur(from_start))
+    }
+
     fn next_sample(&mut self) -> Result<Option<T>>;
 
     fn num_samples_remain(&self) -> usize;
@@ -142,28 +187,61 @@ pub trait Samples<T, I>: Sampled {
 }
 
 pub trait Sampled {
+    /// number of samples in `dur` (rounded), u128 math so hours at high sample rates can't
+    /// overflow
     fn samples_from_dur(&self, dur: Duration) -> usize {
-        *((Rational64::new(self.sample_rate() as i64, 1_000_000_000)) * (dur.as_nanos() as i64))
-            .round()
-            .numer() as usize
+        let samples =
+            ((self.sample_rate() as u128) * dur.as_nanos() + (NANOS_PER_SEC / 2)) / NANOS_PER_SEC;
+        std::cmp::min(samples, usize::MAX as u128) as usize
     }
 
     fn sample_rate(&self) -> usize;
 
     fn num_samples(&self) -> usize;
 
+    /// length of all samples (rounded down to the nanosecond), zero without a sample rate
     fn duration(&self) -> Duration {
-        let samples_per_ms = self.sample_rate() as f64;
-        let num_samples = self.num_samples() as f64;
-        let num_sec = num_samples / samples_per_ms;
-        let num_nano = num_sec * 1_000_000_000.0;
-        let num_nano = num_nano.floor() as u64;
-        Duration::from_nanos(num_nano)
+        self.dur_from_samples(self.num_samples())
+    }
+
+    /// length of `num_samples` samples (rounded down to the nanosecond), zero without a sample
+    /// rate
+    fn dur_from_samples(&self, num_samples: usize) -> Duration {
+        let sample_rate = self.sample_rate();
+        if sample_rate == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let secs = (num_samples / sample_rate) as u64;
+        let nanos = (((num_samples % sample_rate) as u128) * NANOS_PER_SEC) / (sample_rate as u128);
+        Duration::new(secs, nanos as u32)
     }
 }
 
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
 pub trait AudioSource: Sampled {
     fn num_channels(&self) -> usize;
+
+    fn sample_format(&self) -> SampleFormat;
+}
+
+/// How each (per channel) sample is stored in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// integer PCM with the given bit depth
+    Pcm { bits: u16 },
+    /// IEEE floating point with the given bit depth
+    Float { bits: u16 },
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleFormat::Pcm { bits } => write!(f, "{} bit PCM", bits),
+            SampleFormat::Float { bits } => write!(f, "{} bit float", bits),
+        }
+    }
 }
 
 #[macro_export]
@@ -173,6 +251,10 @@ macro_rules! delegate_impls {
             fn num_channels(&self) -> usize {
                 self.$fld.num_channels()
             }
+
+            fn sample_format(&self) -> crate::framed::SampleFormat {
+                self.$fld.sample_format()
+            }
         }
 
         impl<$($g),+> crate::framed::Sampled for $ty<$($g),+> where $s: crate::framed::Sampled {
@@ -199,6 +281,26 @@ pub trait FramedMapper<T, R> {
     }
 }
 
+// an optional stage, passes frames through untouched when disabled
+impl<T, M> FramedMapper<T, T> for Option<M>
+where
+    M: FramedMapper<T, T>,
+{
+    fn map<'a>(&'a mut self, input: &'a mut [T]) -> Result<Option<&'a mut [T]>> {
+        match self {
+            Some(mapper) => mapper.map(input),
+            None => Ok(Some(input)),
+        }
+    }
+
+    fn map_frame_size(&self, orig: usize) -> usize {
+        match self {
+            Some(mapper) => mapper.map_frame_size(orig),
+            None => orig,
+        }
+    }
+}
+
 pub struct FramedMutMapFn<T, F> {
     mapper: F,
     _in_typ: PhantomData<T>,
@@ -214,6 +316,21 @@ where
     }
 }
 
+pub struct FramedTapFn<T, F> {
+    f: F,
+    _in_typ: PhantomData<T>,
+}
+
+impl<T, F> FramedMapper<T, T> for FramedTapFn<T, F>
+where
+    F: FnMut(&[T]),
+{
+    fn map<'a>(&'a mut self, input: &'a mut [T]) -> Result<Option<&'a mut [T]>> {
+        (self.f)(input);
+        Ok(Some(input))
+    }
+}
+
 pub struct FramedMapFn<T, R, F> {
     mapper: F,
     buf: Vec<R>,
@@ -321,6 +438,11 @@ where
         self.source.seek_samples(n)
     }
 
+    #[cfg(any(feature = "gui", test))]
+    fn seek_to_sample(&mut self, abs: usize) -> Result<()> {
+        self.source.seek_to_sample(abs)
+    }
+
     fn next_sample(&mut self) -> Result<Option<R>> {
         Ok(if let Some(next) = self.source.next_sample()? {
             let mapper = &self.mapper;
@@ -391,3 +513,115 @@ impl<T, R, M> SplitChanneledFramedMapper<T, R> for M where
     M: FramedMapper<Channeled<T>, Channeled<R>> + Sized
 {
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::framed::{Framed, FramedMapFn, FramedMapper, FramedMutMapFn, Sampled, Samples};
+    use crate::sliding::SlidingFrame;
+    use crate::vec_samples::VecSamples;
+    use std::marker::PhantomData;
+    use std::time::Duration;
+
+    struct Length {
+        sample_rate: usize,
+        num_samples: usize,
+    }
+
+    impl Sampled for Length {
+        fn sample_rate(&self) -> usize {
+            self.sample_rate
+        }
+
+        fn num_samples(&self) -> usize {
+            self.num_samples
+        }
+    }
+
+    #[test]
+    fn long_durations_at_high_sample_rates() {
+        let ten_hours = Duration::from_secs(10 * 60 * 60);
+        let source = Length {
+            sample_rate: 192_000,
+            num_samples: 6_912_000_000,
+        };
+        assert_eq!(source.samples_from_dur(ten_hours), 6_912_000_000);
+        assert_eq!(source.duration(), ten_hours);
+
+        // rounds to the nearest sample, durations round down to the nanosecond
+        let source = Length {
+            sample_rate: 44100,
+            num_samples: 1,
+        };
+        assert_eq!(source.samples_from_dur(Duration::from_millis(98)), 4322);
+        assert_eq!(source.duration(), Duration::from_nanos(22675));
+    }
+
+    #[test]
+    fn skip_to_duration_lands_on_the_sample() {
+        let mut source = VecSamples::new((0..48000).collect::<Vec<u32>>(), 8000);
+        source
+            .skip_to_duration(Duration::from_millis(1250))
+            .expect("should seek");
+        assert_eq!(source.sample_position(), 10000);
+        assert_eq!(source.next_sample().expect("ok"), Some(10000));
+
+        // backwards too, and past the end is clamped to the end
+        source
+            .skip_to_duration(Duration::from_micros(500))
+            .expect("should seek");
+        assert_eq!(source.next_sample().expect("ok"), Some(4));
+        source
+            .skip_to_duration(Duration::from_secs(60))
+            .expect("should seek");
+        assert_eq!(source.sample_position(), 48000);
+        assert_eq!(source.next_sample().expect("ok"), None);
+    }
+
+    #[test]
+    fn tap_sees_every_frame_once_in_order() {
+        let frames = move || SlidingFrame::new(VecSamples::new((0..50).collect(), 1), 8, 4);
+        let expected = frames().collect().expect("should collect");
+
+        let mut seen = Vec::new();
+        let out = frames()
+            .tap(|frame: &[i64]| seen.push(frame.to_vec()))
+            .collect()
+            .expect("should collect");
+        assert_eq!(out, expected);
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn map_mut_matches_map() {
+        let frames = move || SlidingFrame::new(VecSamples::new((0..50).collect(), 1), 8, 4);
+        let mapped = frames().map(|v| v * 3 - 1).collect().expect("should map");
+        let in_place = frames()
+            .map_mut(|v| *v = *v * 3 - 1)
+            .collect()
+            .expect("should map");
+        assert_eq!(mapped, in_place);
+    }
+
+    #[test]
+    fn map_mut_reuses_input_frame() {
+        let mut in_place = FramedMutMapFn {
+            mapper: |v: &mut i64| *v += 1,
+            _in_typ: PhantomData,
+        };
+        let mut copying = FramedMapFn {
+            mapper: |v: &i64| v + 1,
+            buf: Vec::new(),
+            _in_typ: PhantomData,
+        };
+
+        let mut frame = vec![1, 2, 3];
+        let frame_at = frame.as_ptr();
+        let out = in_place.map(&mut frame).expect("ok").expect("some");
+        assert_eq!(out, &[2, 3, 4]);
+        assert_eq!(out.as_ptr(), frame_at);
+
+        let out = copying.map(&mut frame).expect("ok").expect("some");
+        assert_eq!(out, &[3, 4, 5]);
+        assert_ne!(out.as_ptr(), frame_at);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::{VizComplex, VizFloat};
+use anyhow::Result;
+use std::f64::consts::PI;
+
+/// Computes the group delay (−dφ/dω) across the bins of a complex spectrum, such as the output of
+/// `FramedComplexFft`. The output has one value (in samples) per input bin.
+///
+/// The derivative is taken between consecutive bins, so the last bin repeats the delay of the
+/// one before it. Delays longer than half of the FFT size can't be told apart from negative ones.
+pub struct GroupDelay {
+    // angular frequency between two consecutive bins (radians per sample)
+    bin_step: VizFloat,
+    out: Vec<Channeled<VizFloat>>,
+}
+
+impl GroupDelay {
+    pub fn new(fft_size: usize) -> Self {
+        Self {
+            bin_step: (2.0 * PI) / (fft_size as VizFloat),
+            out: Vec::new(),
+        }
+    }
+}
+
+impl FramedMapper<Channeled<VizComplex>, Channeled<VizFloat>> for GroupDelay {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizComplex>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let bin_step = self.bin_step;
+        self.out.clear();
+        self.out.extend(input.windows(2).map(move |pair| {
+            pair[0]
+                .as_ref()
+                .zip(pair[1].as_ref())
+                .expect("mono/stereo should match")
+                .map(move |(cur, next)| -wrap_phase(next.arg() - cur.arg()) / bin_step)
+        }));
+
+        if let Some(last) = self.out.last().cloned() {
+            self.out.push(last);
+        } else if let Some(first) = input.first() {
+            self.out.push(first.as_ref().map(|_| 0.0));
+        }
+
+        Ok(Some(self.out.as_mut_slice()))
+    }
+}
+
+/// Unwraps a phase difference into [-π, π), which is the same as unwrapping the phases themselves
+/// before taking the difference
+fn wrap_phase(delta: VizFloat) -> VizFloat {
+    (delta + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+    use crate::group_delay::GroupDelay;
+    use crate::util::{VizComplex, VizFloat};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn linear_phase_has_constant_group_delay() {
+        const FFT_SIZE: usize = 64;
+        const DELAY: VizFloat = 5.3;
+
+        // a pure delay of DELAY samples: X(ω) = e^(-iωD), skipping DC like the FFT mapper does
+        let mut spectrum = (1..=(FFT_SIZE / 2))
+            .map(|k| {
+                let omega = (2.0 * PI * (k as VizFloat)) / (FFT_SIZE as VizFloat);
+                let phase = -omega * DELAY;
+                Channeled::Stereo(
+                    VizComplex::new(phase.cos(), phase.sin()),
+                    VizComplex::new(phase.cos() * 0.25, phase.sin() * 0.25),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut mapper = GroupDelay::new(FFT_SIZE);
+        let delays = mapper
+            .map(&mut spectrum)
+            .expect("should map")
+            .expect("should produce a frame");
+
+        assert_eq!(delays.len(), FFT_SIZE / 2);
+        for delay in delays.iter() {
+            delay
+                .channels()
+                .for_each(|d| assert!((d - DELAY).abs() < 1e-9, "got {}", d));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::framed::Framed;
+use crate::util::VizFloat;
+use anyhow::Result;
+
+/// Decides, for every rendered frame, how many new data frames are needed and how far between the
+/// two most recent data frames the rendered frame sits.
+///
+/// The renderer reads one data frame ahead, so the frame shown at render time `t` is between data
+/// frame `floor(t * data_fps)` and the one after it.
+pub struct RenderClock {
+    data_fps: u64,
+    render_fps: u64,
+    // first data frame shown after the last seek
+    base: u64,
+    // render frames since the last seek
+    ticks: u64,
+    // data frames read so far (counting skipped frames)
+    data_frames: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderStep {
+    /// number of data frames to pull before rendering this frame
+    pub new_data_frames: u64,
+    /// position between the previous and latest data frame, 0.0 is exactly the previous frame
+    pub t: VizFloat,
+}
+
+impl RenderClock {
+    pub fn new(data_fps: u64, render_fps: u64) -> Self {
+        Self {
+            data_fps,
+            render_fps,
+            base: 0,
+            ticks: 0,
+            data_frames: 0,
+        }
+    }
+
+    /// advances by one rendered frame
+    pub fn tick(&mut self) -> RenderStep {
+        let position = self.ticks * self.data_fps;
+        let whole = self.base + (position / self.render_fps);
+        let t = ((position % self.render_fps) as VizFloat) / (self.render_fps as VizFloat);
+        self.ticks += 1;
+
+        let needed = whole + 2;
+        let new_data_frames = needed.saturating_sub(self.data_frames);
+        self.data_frames += new_data_frames;
+        RenderStep { new_data_frames, t }
+    }
+
+    /// data frames read so far, the position of the data source
+    pub fn data_frames(&self) -> u64 {
+        self.data_frames
+    }
+
+    /// the data source skipped `n` frames (seek), the next data frame read is shown next
+    pub fn seek(&mut self, n: isize) {
+        self.base = std::cmp::max(0, (self.data_frames as isize) + n) as u64;
+        self.data_frames = self.base;
+        self.ticks = 0;
+    }
+}
+
+/// Keeps the two most recent data frames and blends between them
+#[derive(Default)]
+pub struct FrameInterpolator {
+    prev: Vec<VizFloat>,
+    next: Vec<VizFloat>,
+    out: Vec<VizFloat>,
+}
+
+impl FrameInterpolator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, frame: &[VizFloat]) {
+        if self.next.is_empty() {
+            self.prev.extend_from_slice(frame);
+        } else {
+            std::mem::swap(&mut self.prev, &mut self.next);
+        }
+
+        self.next.clear();
+        self.next.extend_from_slice(frame);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next.is_empty()
+    }
+
+    /// forgets previous frames, the next pushed frame is used for both ends
+    pub fn reset(&mut self) {
+        self.prev.clear();
+        self.next.clear();
+    }
+
+    pub fn interpolate(&mut self, t: VizFloat) -> &[VizFloat] {
+        let t_inv = 1.0 - t;
+        self.out.clear();
+        self.out.extend(
+            self.prev
+                .iter()
+                .zip(self.next.iter())
+                .map(move |(a, b)| (a * t_inv) + (b * t)),
+        );
+        self.out.as_slice()
+    }
+}
+
+/// Pulls the data frames each rendered frame needs into the interpolator.
+///
+/// The last data frame has no frame after it to read ahead, so when the data runs out (or
+/// `max_frames` were pulled) it is drawn once more on its own before the feed ends.
+pub struct FrameFeed {
+    clock: RenderClock,
+    interpolator: FrameInterpolator,
+    max_frames: Option<usize>,
+    // data frames pulled since the start, the limit for max_frames
+    pulled: usize,
+    ended: bool,
+}
+
+impl FrameFeed {
+    pub fn new(clock: RenderClock, max_frames: Option<usize>) -> Self {
+        Self {
+            clock,
+            interpolator: FrameInterpolator::new(),
+            max_frames,
+            pulled: 0,
+            ended: false,
+        }
+    }
+
+    /// advances by one rendered frame, pulling from `frames` as needed, returns the frame to draw
+    /// or None once every data frame was drawn
+    pub fn advance<F, I>(&mut self, frames: &mut F) -> Result<Option<&[VizFloat]>>
+    where
+        F: Framed<VizFloat, I>,
+    {
+        if self.ended {
+            return Ok(None);
+        }
+
+        let step = self.clock.tick();
+        for _ in 0..step.new_data_frames {
+            let limited = self.max_frames.is_some_and(|max| self.pulled >= max);
+            let frame = if limited { None } else { frames.next_frame()? };
+            match frame {
+                Some(frame) => {
+                    self.pulled += 1;
+                    self.interpolator.push(frame);
+                }
+                None => {
+                    self.ended = true;
+                    break;
+                }
+            }
+        }
+
+        if !self.ended {
+            Ok(Some(self.interpolator.interpolate(step.t)))
+        } else if self.interpolator.is_empty() {
+            Ok(None)
+        } else {
+            // the last frame, which was read but not yet shown on its own
+            Ok(Some(self.interpolator.interpolate(1.0)))
+        }
+    }
+
+    /// data frames read so far, the position of the data source
+    pub fn data_frames(&self) -> u64 {
+        self.clock.data_frames()
+    }
+
+    /// the data source skipped `n` frames (seek), the next data frame read is shown next
+    pub fn seek(&mut self, n: isize) {
+        self.clock.seek(n);
+        self.interpolator.reset();
+        self.ended = false;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::interpolate::{FrameFeed, FrameInterpolator, RenderClock};
+    use crate::sliding::SlidingFrame;
+    use crate::util::VizFloat;
+    use crate::vec_samples::VecSamples;
+
+    #[test]
+    fn render_clock_interpolates_between_data_frames() {
+        // 3 render frames per data frame: one exact, two interpolated
+        let mut clock = RenderClock::new(60, 180);
+        let steps = (0..9).map(|_| clock.tick()).collect::<Vec<_>>();
+        let new_frames = steps.iter().map(|s| s.new_data_frames).collect::<Vec<_>>();
+        assert_eq!(new_frames, vec![2, 0, 0, 1, 0, 0, 1, 0, 0]);
+        for (idx, step) in steps.iter().enumerate() {
+            let expected = ((idx % 3) as f64) / 3.0;
+            assert!((step.t - expected).abs() < 1e-9);
+        }
+
+        // 144 Hz display with 60 fps data: one second has 12 exact frames, the rest interpolated
+        let mut clock = RenderClock::new(60, 144);
+        let steps = (0..144).map(|_| clock.tick()).collect::<Vec<_>>();
+        let interpolated = steps.iter().filter(|s| s.t != 0.0).count();
+        let data_frames: u64 = steps.iter().map(|s| s.new_data_frames).sum();
+        assert_eq!(interpolated, 132);
+        assert_eq!(data_frames, 61);
+    }
+
+    #[test]
+    fn render_clock_restarts_after_seek() {
+        let mut clock = RenderClock::new(60, 120);
+        clock.tick();
+        clock.tick();
+        clock.seek(10);
+        let step = clock.tick();
+        assert_eq!(step.new_data_frames, 2);
+        assert_eq!(step.t, 0.0);
+
+        clock.seek(-100);
+        let step = clock.tick();
+        assert_eq!(step.new_data_frames, 2);
+        assert_eq!(step.t, 0.0);
+    }
+
+    #[test]
+    fn interpolator_blends_frames() {
+        let mut interpolator = FrameInterpolator::new();
+        interpolator.push(&[0.0, 1.0]);
+        assert_eq!(interpolator.interpolate(0.5), &[0.0, 1.0]);
+        interpolator.push(&[1.0, 0.0]);
+        assert_eq!(interpolator.interpolate(0.25), &[0.25, 0.75]);
+    }
+
+    fn drawn_frames(n: usize, render_fps: u64, max_frames: Option<usize>) -> Vec<VizFloat> {
+        let samples = (0..n).map(|v| v as VizFloat).collect();
+        let mut frames = SlidingFrame::new(VecSamples::new(samples, 1), 1, 1);
+        let mut feed = FrameFeed::new(RenderClock::new(60, render_fps), max_frames);
+        let mut drawn = Vec::new();
+        while let Some(frame) = feed.advance(&mut frames).expect("should pull") {
+            drawn.push(frame[0]);
+        }
+        drawn
+    }
+
+    #[test]
+    fn feed_draws_every_data_frame() {
+        let expected = (0..10).map(|v| v as VizFloat).collect::<Vec<_>>();
+        assert_eq!(drawn_frames(10, 60, None), expected);
+        assert_eq!(drawn_frames(1, 60, None), vec![0.0]);
+        assert!(drawn_frames(0, 60, None).is_empty());
+
+        // interpolated frames in between, and the last one exactly
+        let drawn = drawn_frames(10, 180, None);
+        assert_eq!(drawn.len(), 28);
+        assert_eq!(drawn.last(), Some(&9.0));
+        assert_eq!(drawn.iter().filter(|v| v.fract() == 0.0).count(), 10);
+
+        // max_frames limits the data frames which are drawn
+        assert_eq!(drawn_frames(10, 60, Some(4)), vec![0.0, 1.0, 2.0, 3.0]);
+    }
+}
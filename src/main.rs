@@ -2,37 +2,249 @@
 #![feature(trusted_len)]
 #![feature(associated_type_defaults)]
 
-#[cfg(not(target_env = "msvc"))]
+#[cfg(all(feature = "jemalloc", not(target_env = "msvc")))]
 use jemallocator::Jemalloc;
 
-#[cfg(not(target_env = "msvc"))]
+#[cfg(all(feature = "jemalloc", not(target_env = "msvc")))]
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+use crate::cli::{parse_args, CliArgs};
+use crate::error::VizError;
+use crate::export::export_file;
+use crate::fft::warm_wisdom;
+use crate::framed::{AudioSource, Sampled};
+use crate::pipeline::{explain_viz_pipeline, fft_sizes, open_config_file, write_default_config};
+use crate::wav::open_audio;
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use std::io::ErrorKind;
+use std::path::Path;
+#[cfg(feature = "gui")]
 use crate::viz::visualize;
 
+mod agc;
+mod auto_db;
 mod binner;
+#[cfg(any(feature = "gui", test))]
+mod boxed;
 mod channeled;
+mod cli;
+mod clipping;
+mod compressor;
+mod correlation;
+#[cfg(feature = "gui")]
+mod diff;
+mod dual_resolution;
+mod envelope;
+mod envelope_follower;
+mod error;
 mod exponential_smoothing;
+mod export;
 mod fft;
 mod framed;
+mod group_delay;
+#[cfg(feature = "gui")]
+mod interpolate;
+mod mono_check;
+mod nyquist_compensation;
+mod parallel;
 mod pipeline;
+#[cfg(feature = "gui")]
 mod player;
+#[cfg(feature = "gui")]
+mod playlist;
+mod pre_emphasis;
+mod quality;
+mod resample;
 mod savitzky_golay;
 mod sliding;
+// an entry point for embedding the DSP without the viz config, the binary doesn't use it
+#[allow(dead_code)]
+mod stft;
 mod timer;
 mod util;
+mod vec_samples;
+#[cfg(feature = "gui")]
 mod viz;
 mod wav;
+mod waveform;
 mod window;
 
 fn main() {
-    if let Some(target) = std::env::args().nth(1) {
-        match visualize(target.as_str()) {
-            Ok(()) => {}
-            Err(err) => panic!("got error: {:?}", err),
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("err: {}", err);
+            // usage error
+            std::process::exit(2);
         }
+    };
+
+    if let Err(err) = run(&args) {
+        eprintln!("err: {}", describe_error(&err));
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &CliArgs) -> Result<()> {
+    if args.dump_sg {
+        return dump_smoothing_coefficients(args);
+    }
+
+    if args.print_default_config {
+        return write_default_config(std::io::stdout().lock());
+    }
+
+    if let Some(config) = &args.explain {
+        return explain_config(config, args);
+    }
+
+    if let Some(path) = &args.warm_wisdom {
+        return warm_fft_wisdom(path, args);
+    }
+
+    let target = args
+        .targets
+        .first()
+        .ok_or_else(|| anyhow!("specify target file as first arg!"))?;
+    if let Some(export_to) = &args.export {
+        export_file(target.as_str(), export_to.as_str(), args)
     } else {
-        eprintln!("err: specify target file as first arg!")
+        visualize(&args.targets, args)
+    }
+}
+
+fn dump_smoothing_coefficients(args: &CliArgs) -> Result<()> {
+    let config = args.load_config()?;
+    for (name, smoothing) in &[
+        ("smoothing0", config.smoothing0),
+        ("smoothing1", config.smoothing1),
+    ] {
+        println!("{}: {:?}", name, smoothing);
+        for row in smoothing.coefficients_as_f64() {
+            let sum: f64 = row.iter().sum();
+            println!(
+                "  [{}] sum={:.6}",
+                row.iter().map(|c| format!("{:+.6}", c)).join(", "),
+                sum
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn explain_config(path: &str, args: &CliArgs) -> Result<()> {
+    const DEFAULT_SAMPLE_RATE: usize = 44100;
+
+    let config = args.with_overrides(
+        open_config_file(path)?.ok_or_else(|| anyhow!("no config file at {}", path))?,
+    )?;
+    let sample_rate = match args.targets.first() {
+        Some(target) => {
+            let source = open_audio(target.as_str(), 4096, args.raw_format, args.strict)?;
+            println!(
+                "{}: {}, {} channel(s) at {}Hz",
+                target,
+                source.sample_format(),
+                source.num_channels(),
+                source.sample_rate()
+            );
+            source.sample_rate()
+        }
+        None => DEFAULT_SAMPLE_RATE,
+    };
+
+    println!("pipeline for {} at {}Hz:", path, sample_rate);
+    for (idx, stage) in explain_viz_pipeline(&config, sample_rate)?.iter().enumerate() {
+        let line = format!(
+            "{:>3}. {:<18} {:>6}  {}",
+            idx + 1,
+            stage.name,
+            stage.frame_size,
+            stage.detail
+        );
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+fn warm_fft_wisdom(path: &str, args: &CliArgs) -> Result<()> {
+    const SAMPLE_RATES: [usize; 2] = [44100, 48000];
+
+    let config = args.load_config()?;
+    let mut sizes = Vec::new();
+    for sample_rate in SAMPLE_RATES.iter() {
+        sizes.extend(fft_sizes(&config, *sample_rate)?);
+    }
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    warm_wisdom(&sizes, Path::new(path))?;
+    println!(
+        "wrote fft wisdom for sizes {} to {}",
+        sizes.iter().join(", "),
+        path
+    );
+    Ok(())
+}
+
+// a one line message for the user, with a hint for the problems users run into most
+fn describe_error(err: &anyhow::Error) -> String {
+    for cause in err.chain() {
+        if let Some(io_err) = cause.downcast_ref::<std::io::Error>() {
+            if io_err.kind() == ErrorKind::NotFound {
+                return format!("file not found ({:#})", err);
+            }
+        }
+
+        if let Some(viz_err) = cause.downcast_ref::<VizError>() {
+            return match viz_err {
+                VizError::UnsupportedFormat(_) => format!(
+                    "{:#} (supported: 8, 16 or 24 bit PCM or 32 bit float WAV files, or .raw/.pcm \
+                     with --sample-rate, --channels and --bits)",
+                    err
+                ),
+                #[cfg(feature = "gui")]
+                VizError::Sdl(_) => format!(
+                    "could not set up the window or audio output, {:#} (use --export to run \
+                     without a window)",
+                    err
+                ),
+            };
+        }
+    }
+
+    format!("{:#}", err)
+}
+
+#[cfg(not(feature = "gui"))]
+fn visualize(_files: &[String], _args: &CliArgs) -> Result<()> {
+    Err(anyhow!(
+        "built without the gui feature, only --export is supported"
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::cli::parse_args;
+    use crate::{describe_error, run};
+
+    #[test]
+    fn missing_file_is_a_clean_error() {
+        let args = parse_args(vec![
+            "does-not-exist.wav".to_string(),
+            "--export".to_string(),
+            "does-not-exist.csv".to_string(),
+        ])
+        .expect("should parse");
+
+        let err = run(&args).expect_err("should fail");
+        let message = describe_error(&err);
+        assert!(message.starts_with("file not found"), "{}", message);
+        assert!(message.contains("does-not-exist.wav"), "{}", message);
+        assert!(!std::path::Path::new("does-not-exist.csv").exists());
     }
 }
@@ -0,0 +1,99 @@
+use crate::channeled::Channeled;
+use crate::framed::Samples;
+use crate::util::VizFloat;
+use anyhow::Result;
+
+// how much of the start of the source is scanned when checking for dual-mono
+const MONO_CHECK_SECONDS: usize = 5;
+// channels at least this similar are treated as identical
+const MONO_SIMILARITY_THRESHOLD: VizFloat = 0.999;
+
+/// Similarity of two channels, `2 * Σ(l * r) / Σ(l² + r²)`.
+///
+/// Identical channels give 1.0, uncorrelated channels give about 0.0, and inverted channels give
+/// -1.0. Differences in level also reduce the similarity. Silence on both sides counts as
+/// identical.
+pub fn channel_similarity<I>(samples: I) -> VizFloat
+where
+    I: IntoIterator<Item = (VizFloat, VizFloat)>,
+{
+    let (cross, power) = samples
+        .into_iter()
+        .fold((0.0, 0.0), move |(cross, power), (l, r)| {
+            (cross + (l * r), power + (l * l) + (r * r))
+        });
+
+    if power == 0.0 {
+        1.0
+    } else {
+        (2.0 * cross) / power
+    }
+}
+
+/// Scans the start of a stereo source and reports whether both channels carry (nearly) the same
+/// signal. The source is seeked back to where it was before the scan.
+pub fn is_dual_mono<E, I, S>(source: &mut S) -> Result<bool>
+where
+    S: Samples<Channeled<E>, I>,
+    E: Into<VizFloat>,
+{
+    let max_samples = source.sample_rate() * MONO_CHECK_SECONDS;
+    let mut pairs = Vec::with_capacity(std::cmp::min(max_samples, source.num_samples_remain()));
+    while pairs.len() < max_samples {
+        match source.next_sample()? {
+            Some(Channeled::Stereo(l, r)) => pairs.push((l.into(), r.into())),
+            Some(Channeled::Mono(_)) | Some(Channeled::Multi(_)) => {
+                // already mono, or more than a pair of channels, nothing to collapse
+                source.seek_samples(-((pairs.len() + 1) as isize))?;
+                return Ok(false);
+            }
+            None => break,
+        }
+    }
+
+    source.seek_samples(-(pairs.len() as isize))?;
+    Ok(!pairs.is_empty() && channel_similarity(pairs) >= MONO_SIMILARITY_THRESHOLD)
+}
+
+pub fn collapse_to_mono(input: Channeled<VizFloat>) -> Channeled<VizFloat> {
+    Channeled::Mono(input.to_mono())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::Samples;
+    use crate::mono_check::{channel_similarity, is_dual_mono};
+    use crate::util::VizFloat;
+    use crate::vec_samples::VecSamples;
+
+    fn sine(freq: VizFloat, phase: VizFloat, n: usize) -> Vec<VizFloat> {
+        (0..n)
+            .map(|i| ((i as VizFloat) * freq * 2.0 * std::f64::consts::PI / 8000.0 + phase).sin())
+            .collect()
+    }
+
+    #[test]
+    fn similarity_of_identical_and_decorrelated_channels() {
+        let a = sine(440.0, 0.0, 8000);
+        let b = sine(1234.5, 0.3, 8000);
+
+        let identical = channel_similarity(a.iter().copied().zip(a.iter().copied()));
+        assert!((identical - 1.0).abs() < 1e-9, "got {}", identical);
+
+        let decorrelated = channel_similarity(a.iter().copied().zip(b.iter().copied()));
+        assert!(decorrelated.abs() < 0.05, "got {}", decorrelated);
+
+        let inverted = channel_similarity(a.iter().copied().zip(a.iter().map(|v| -v)));
+        assert!((inverted + 1.0).abs() < 1e-9, "got {}", inverted);
+    }
+
+    #[test]
+    fn dual_mono_scan_seeks_back() {
+        let a = sine(440.0, 0.0, 100);
+        let mut source =
+            VecSamples::new(a.iter().map(|v| Channeled::Stereo(*v, *v)).collect(), 8000);
+        assert!(is_dual_mono(&mut source).expect("should scan"));
+        assert_eq!(source.num_samples_remain(), 100);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+
+/// High shelf on the magnitude spectrum which makes up for the anti-alias filter of the source
+/// rolling off the top octave.
+///
+/// The gain (in dB) rises with log frequency from 0 dB at half of Nyquist to `db` at Nyquist,
+/// every bin below the top octave is left alone.
+pub struct NyquistCompensation {
+    gains: Vec<VizFloat>,
+}
+
+impl NyquistCompensation {
+    /// `size` FFT magnitudes (DC skipped), so bin `i` is `(i + 1) / size` of Nyquist
+    pub fn new(size: usize, db: VizFloat) -> Self {
+        let gains = (0..size)
+            .map(move |i| {
+                let octaves_below_nyquist = (((i + 1) as VizFloat) / (size as VizFloat)).log2();
+                let ramp = VizFloat::max(0.0, 1.0 + octaves_below_nyquist);
+                VizFloat::powf(10.0, (db * ramp) / 20.0)
+            })
+            .collect();
+        Self { gains }
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for NyquistCompensation {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        input
+            .iter_mut()
+            .zip(self.gains.iter().copied())
+            .for_each(move |(v, gain)| v.as_mut_ref().for_each(move |v| *v *= gain));
+
+        Ok(Some(input))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+    use crate::nyquist_compensation::NyquistCompensation;
+
+    #[test]
+    fn boosts_only_the_top_octave() {
+        let mut compensation = NyquistCompensation::new(16, 6.0);
+        let mut input = vec![Channeled::Mono(1.0); 16];
+        let out = compensation
+            .map(&mut input)
+            .expect("ok")
+            .expect("some")
+            .iter()
+            .map(|v| v.to_mono())
+            .collect::<Vec<_>>();
+
+        // the top bin is Nyquist, it gets the full boost
+        let top_db = 20.0 * out[15].log10();
+        assert!((top_db - 6.0).abs() < 1e-9, "{}", top_db);
+        // half of Nyquist and below are untouched
+        assert!(out[..8].iter().all(|v| *v == 1.0), "{:?}", out);
+        // rising in between
+        assert!(out[8..].windows(2).all(|w| w[0] < w[1]), "{:?}", out);
+    }
+}
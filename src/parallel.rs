@@ -0,0 +1,169 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::try_use_iter;
+use anyhow::{anyhow, Result};
+
+/// Runs the two channels of stereo frames through two instances of a mapper at the same time (on
+/// the rayon thread pool), each instance sees mono frames of one channel. Only for mappers which
+/// treat the channels independently, like the FFT and Savitzky Golay smoothing.
+///
+/// Without a second instance (or for mono and multichannel frames) frames go through the first
+/// instance as they are.
+pub struct ParallelChannels<M, T, R> {
+    left: M,
+    right: Option<M>,
+    left_in: Vec<Channeled<T>>,
+    right_in: Vec<Channeled<T>>,
+    out: Vec<Channeled<R>>,
+}
+
+impl<M, T, R> ParallelChannels<M, T, R>
+where
+    M: FramedMapper<Channeled<T>, Channeled<R>>,
+{
+    pub fn sequential(mapper: M) -> Self {
+        Self {
+            left: mapper,
+            right: None,
+            left_in: Vec::new(),
+            right_in: Vec::new(),
+            out: Vec::new(),
+        }
+    }
+
+    pub fn parallel(left: M, right: M, cap: usize) -> Self {
+        let cap_mapped = left.map_frame_size(cap);
+        Self {
+            left,
+            right: Some(right),
+            left_in: Vec::with_capacity(cap),
+            right_in: Vec::with_capacity(cap),
+            out: Vec::with_capacity(cap_mapped),
+        }
+    }
+}
+
+impl<M, T, R> FramedMapper<Channeled<T>, Channeled<R>> for ParallelChannels<M, T, R>
+where
+    M: FramedMapper<Channeled<T>, Channeled<R>> + Send,
+    T: Copy + Send,
+    R: Copy + Send,
+{
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<T>],
+    ) -> Result<Option<&'a mut [Channeled<R>]>> {
+        let Self {
+            left,
+            right,
+            left_in,
+            right_in,
+            out,
+        } = self;
+        let is_stereo = matches!(input.first(), Some(Channeled::Stereo(_, _)));
+        let right = match right {
+            Some(right) if is_stereo => right,
+            _ => return left.map(input),
+        };
+
+        left_in.clear();
+        right_in.clear();
+        for v in input.iter() {
+            match *v {
+                Channeled::Stereo(l, r) => {
+                    left_in.push(Channeled::Mono(l));
+                    right_in.push(Channeled::Mono(r));
+                }
+                _ => return Err(anyhow!("mixed mono and stereo data in one frame")),
+            }
+        }
+
+        let (l, r) = rayon::join(move || left.map(left_in), move || right.map(right_in));
+        let (l, r) = match (l?, r?) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return Ok(None),
+        };
+
+        out.clear();
+        try_use_iter(
+            l.iter().zip(r.iter()).map(move |(l, r)| match (l, r) {
+                (Channeled::Mono(l), Channeled::Mono(r)) => Ok(Channeled::Stereo(*l, *r)),
+                _ => Err(anyhow!("stereo output from one channel")),
+            }),
+            |itr| out.extend(itr),
+        )?;
+
+        Ok(Some(out.as_mut_slice()))
+    }
+
+    fn map_frame_size(&self, orig: usize) -> usize {
+        self.left.map_frame_size(orig)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::fft::FramedFft;
+    use crate::framed::FramedMapper;
+    use crate::parallel::ParallelChannels;
+    use crate::savitzky_golay::SavitzkyGolayConfig;
+
+    #[test]
+    fn parallel_stereo_matches_sequential() {
+        let frame = (0..256)
+            .map(|i| {
+                let t = (i as f64) / 256.0;
+                Channeled::Stereo(
+                    (t * 13.0 * std::f64::consts::PI * 2.0).sin(),
+                    (t * 41.0 * std::f64::consts::PI * 2.0).cos() * 0.25,
+                )
+            })
+            .collect::<Vec<_>>();
+        let fft = || FramedFft::new(512).expect("should plan");
+        let smoothing = SavitzkyGolayConfig {
+            window_size: 9,
+            degree: 3,
+            order: 0,
+        }
+        .into_mapper(256);
+
+        let mut sequential = ParallelChannels::sequential(fft());
+        let mut parallel = ParallelChannels::parallel(fft(), fft(), 256);
+        let expected = sequential
+            .map(&mut frame.clone())
+            .expect("ok")
+            .expect("some")
+            .to_vec();
+        let got = parallel
+            .map(&mut frame.clone())
+            .expect("ok")
+            .expect("some")
+            .to_vec();
+        assert_eq!(got.len(), 256);
+        assert_eq!(got, expected);
+
+        let mut sequential = ParallelChannels::sequential(smoothing.clone());
+        let mut parallel = ParallelChannels::parallel(smoothing.clone(), smoothing, 256);
+        let expected = sequential
+            .map(&mut expected.clone())
+            .expect("ok")
+            .expect("some")
+            .to_vec();
+        let got = parallel
+            .map(&mut got.clone())
+            .expect("ok")
+            .expect("some")
+            .to_vec();
+        assert_eq!(got, expected);
+        assert!(got.iter().all(|v| matches!(v, Channeled::Stereo(_, _))));
+
+        // mono frames go through the first instance as they are
+        let mut mono = frame
+            .iter()
+            .map(|v| Channeled::Mono(v.to_mono()))
+            .collect::<Vec<_>>();
+        let out = parallel.map(&mut mono).expect("ok").expect("some");
+        assert!(out.iter().all(|v| matches!(v, Channeled::Mono(_))));
+    }
+}
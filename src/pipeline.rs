@@ -1,129 +1,1181 @@
-use crate::binner::{BinConfig, Binner};
+use crate::agc::Agc;
+use crate::auto_db::{AutoDb, AutoDbConfig};
+use crate::binner::{BinConfig, BinFrequency, BinNormalization, BinOverlap, BinScale, Binner};
 use crate::channeled::Channeled;
-use crate::exponential_smoothing::ExponentialSmoothing;
-use crate::fft::FramedFft;
+use crate::compressor::{Compressor, CompressorConfig};
+use crate::correlation::CorrelationTap;
+use crate::dual_resolution::{DualResolutionConfig, DualResolutionFft};
+use crate::envelope::EnvelopeTap;
+use crate::envelope_follower::{EnvelopeFollower, EnvelopeFollowerConfig};
+use crate::exponential_smoothing::{AdaptiveSmoothingConfig, ExponentialSmoothing};
+use crate::fft::{FramedComplexFft, FramedFft};
 use crate::framed::{Framed, Sampled, Samples};
-use crate::savitzky_golay::SavitzkyGolayConfig;
-use crate::sliding::SlidingFrame;
+use crate::group_delay::GroupDelay;
+use crate::mono_check::{collapse_to_mono, is_dual_mono};
+use crate::nyquist_compensation::NyquistCompensation;
+use crate::pre_emphasis::PreEmphasis;
+use crate::parallel::ParallelChannels;
+use crate::quality::{Quality, QualityGovernor, QualitySmoothing, QualitySwitch, QualityTap};
+use crate::resample::{Resample, ResampleConfig, ResampleQuality, ResampledLength};
+use crate::savitzky_golay::{SavitzkyGolayConfig, SmoothingBlend};
+use crate::sliding::{frame_start, SlidingFrame};
 use crate::timer::FramedTimed;
+use crate::vec_samples::VecSamples;
 use crate::window::{BlackmanNuttall, WindowingFunction};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use num_rational::Rational64;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::include_str;
-use std::io::ErrorKind;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use crate::util::VizFloat;
+use crate::util::{magnitude_to_db, write_csv_row, VizFloat};
+use crate::waveform::WaveformTap;
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct VizPipelineConfig {
     pub fps: u64,
+    /// frame rate of the display, frames in between data frames are interpolated (default: fps)
+    #[serde(default)]
+    pub render_fps: Option<u64>,
+    /// shift the visuals against the audio, positive values show frames later and negative values
+    /// earlier, to make up for output latency or to taste
+    #[serde(default)]
+    pub sync_offset_ms: VizFloat,
     pub data_window_ms: u64,
+    /// size of the analysis window in samples, replaces data_window_ms
+    #[serde(default)]
+    pub window_samples: Option<usize>,
+    /// zero pad every window to this many samples before the FFT (at least the window size), for
+    /// a finer (interpolated) spectrum without a longer window
+    #[serde(default)]
+    pub fft_samples: Option<usize>,
+    /// a second, longer FFT for the bins below a crossover frequency, fine frequency resolution
+    /// in the bass without giving up the time resolution of the main window everywhere else
+    #[serde(default)]
+    pub dual_resolution: Option<DualResolutionConfig>,
+    /// analyze every source at this sample rate, so files at different rates get the same bins
+    #[serde(default)]
+    pub resample: Option<ResampleConfig>,
+    /// an FFTW wisdom file written by `--warm-wisdom`, the FFT uses the measured plan from it (and
+    /// estimates one with a warning while the file does not exist yet)
+    #[serde(default)]
+    pub fft_wisdom: Option<String>,
     pub alpha0: VizFloat,
     pub alpha1: VizFloat,
+    /// ease both alpha0 and alpha1 in from silence over this many frames, so playback fades in
+    /// instead of popping up at the first frame
+    #[serde(default)]
+    pub warmup_frames: usize,
+    /// lower alpha0 and alpha1 on frames which change a lot from the previous one (spectral
+    /// flux), so attacks stay crisp while sustained sounds are smoothed fully
+    #[serde(default)]
+    pub adaptive_time_smoothing: Option<AdaptiveSmoothingConfig>,
     pub smoothing0: SavitzkyGolayConfig,
     pub smoothing1: SavitzkyGolayConfig,
+    /// blend smoothing1 with the unsmoothed bars, from `low` at the lowest bar to `high` at the
+    /// highest (1.0 is fully smoothed), to smooth the noisy high bars more than the bass
+    #[serde(default)]
+    pub smoothing1_blend: Option<SmoothingBlend>,
+    /// size the smoothing1 window to this fraction of the number of bars (e.g. 0.1) instead of
+    /// using smoothing1.window_size, so fewer bars aren't over-smoothed
+    #[serde(default)]
+    pub smoothing1_auto_window: Option<VizFloat>,
+    /// fast attack, slow decay on every bar (like a VU meter), after all other smoothing
+    #[serde(default)]
+    pub envelope_follower: Option<EnvelopeFollowerConfig>,
+    /// only smooth after binning (smoothing1). smoothing0 runs on the raw FFT, where bins are
+    /// evenly spaced in Hz, so it blurs the low bars (few FFT bins each) much more than the high
+    /// bars. Skipping it keeps the low end sharp, at the cost of noisier high bars.
+    #[serde(default)]
+    pub skip_smoothing0: bool,
+    /// full smoothing, fast (no smoothing0 and a smaller smoothing1 window) for many bars on slow
+    /// machines, or adaptive to switch to fast when frames take too long to compute
+    #[serde(default)]
+    pub quality: Quality,
+    /// run the FFT and the Savitzky Golay smoothing of the two channels of stereo sources on two
+    /// cores at the same time
+    #[serde(default)]
+    pub parallel_channels: bool,
     pub min_db: VizFloat,
     pub max_db: VizFloat,
+    /// magnitudes below this (including silence) are raised to it before converting to dB, so no
+    /// bin is ever -inf (the default of 1e-10 is -200 dB)
+    #[serde(default = "default_magnitude_floor")]
+    pub magnitude_floor: VizFloat,
+    /// learn the loudness range from the first seconds of playback and map it onto min_db..max_db,
+    /// so quiet and loud files both use the full height
+    #[serde(default)]
+    pub auto_db: Option<AutoDbConfig>,
+    /// scale magnitudes by the overlap factor of the sliding window (stride / size), so frames
+    /// which overlap more don't read louder (min_db/max_db need to be adjusted when enabled)
+    #[serde(default)]
+    pub normalize_overlap: bool,
+    /// boost the top octave of the spectrum by up to this many dB at Nyquist, to make up for the
+    /// anti-alias filter of the source rolling off the highest frequencies (0.0 disables it)
+    #[serde(default)]
+    pub nyquist_compensation_db: VizFloat,
+    /// compress bars louder than a threshold (dB) by a ratio, before they are normalized, so
+    /// transients don't slam the bars to the top (only with the db amplitude response)
+    #[serde(default)]
+    pub compressor: Option<CompressorConfig>,
+    /// how values outside of min_db..max_db are brought into 0..1
+    #[serde(default)]
+    pub clamp_mode: ClampMode,
+    /// show bars in dB (min_db..max_db), or as linear magnitudes
+    #[serde(default)]
+    pub amplitude_response: AmplitudeResponse,
     pub binning: VizBinningConfig,
+    #[serde(default)]
+    pub render: VizRenderConfig,
+    /// how far the left/right arrow keys seek (shift + arrow seeks further)
+    #[serde(default = "default_seek_seconds")]
+    pub seek_seconds: u64,
+    /// stop after this many frames, useful for quick previews
+    #[serde(default)]
+    pub max_frames: Option<usize>,
+    /// pre-emphasis filter coefficient (0.0..1.0, e.g. 0.95), boosts the high frequencies of the
+    /// samples before the FFT, 0.0 disables it
+    #[serde(default)]
+    pub pre_emphasis: VizFloat,
+    /// stereo balance, -1.0 is only the left channel, 1.0 is only the right channel
+    #[serde(default)]
+    pub balance: VizFloat,
+    /// scale every frame so its loudest bar is full height (quiet parts still show detail)
+    #[serde(default)]
+    pub agc: bool,
+    /// for measuring rather than looking at: the bars are the normalized magnitudes, without the
+    /// clamp to 0..1 (or the soft knee), smoothing1 or the discrete levels
+    #[serde(default)]
+    pub metering: bool,
+    /// scan the start of stereo sources and process them as mono if both channels are identical
+    #[serde(default)]
+    pub mono_check: bool,
+    /// for debugging, write the frames of intermediate stages (fft, spectrum before binning, bins
+    /// before dB conversion) as CSV files into this directory
+    #[serde(default)]
+    pub debug_dump_dir: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct VizBinningConfig {
     pub bins: usize,
     pub fmax: VizFloat,
     pub fmin: VizFloat,
     pub gamma: VizFloat,
     pub discrete_levels: u32,
+    #[serde(default)]
+    pub overlap: BinOverlap,
+    /// interpolate bins narrower than the FFT resolution instead of widening them
+    #[serde(default)]
+    pub interpolate_low_bins: bool,
+    /// explicit bin edges in Hz (N edges make N - 1 bins), replaces bins/fmin/fmax/gamma
+    #[serde(default)]
+    pub edges: Option<Vec<VizFloat>>,
+    /// divide each bin by the FFT size (default) or by its own width (average magnitude)
+    #[serde(default)]
+    pub normalization: BinNormalization,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ClampMode {
+    /// below min_db is 0, above max_db is 1
+    #[default]
+    Hard,
+    /// values within `width` (a fraction of the min_db..max_db range) of either end bend smoothly
+    /// towards 0/1 (tanh knee), so being further out of range still shows a little
+    SoftKnee(VizFloat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AmplitudeResponse {
+    /// magnitudes are converted to dB, min_db..max_db is 0..1
+    #[default]
+    Db,
+    /// magnitudes are used as they are (1.0 is full height), minus a floor (0.0..1.0) which is
+    /// subtracted and the rest rescaled, so low level noise doesn't fill the bottom of every bar
+    Linear(VizFloat),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct VizRenderConfig {
+    /// draw the time-domain waveform of the current window behind the bars
+    #[serde(default)]
+    pub waveform: bool,
+    /// RGB color for bars at full height (e.g. `[255, 0, 0]`), to spot over-driven bands
+    #[serde(default)]
+    pub overflow_color: Option<[u8; 3]>,
+    /// how much of the previous frame is kept (motion blur), 0.0 clears every frame and values
+    /// close to 1.0 leave long trails
+    #[serde(default)]
+    pub persistence: VizFloat,
+    /// how a bar's value (0..1) maps to its height
+    #[serde(default)]
+    pub height_curve: HeightCurve,
+    /// left to right order of the bars
+    #[serde(default)]
+    pub bar_order: BarOrder,
+    /// draw a stereo correlation meter (-1..1) along the top of the window
+    #[serde(default)]
+    pub correlation_meter: bool,
+    /// draw a dB scale (min_db..max_db) along the left edge, only with the db amplitude response
+    #[serde(default)]
+    pub db_scale: bool,
+    /// warn when rendering is ahead of or behind the audio by more than this many frames
+    #[serde(default = "default_drift_warn_frames")]
+    pub drift_warn_frames: u32,
+}
+
+impl Default for VizRenderConfig {
+    fn default() -> Self {
+        Self {
+            waveform: false,
+            overflow_color: None,
+            persistence: 0.0,
+            height_curve: HeightCurve::default(),
+            bar_order: BarOrder::default(),
+            correlation_meter: false,
+            db_scale: false,
+            drift_warn_frames: default_drift_warn_frames(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HeightCurve {
+    #[default]
+    Linear,
+    /// `log10(1 + 9v)`, lifts quiet bars the most
+    Log,
+    Sqrt,
+}
+
+impl HeightCurve {
+    /// fraction of the full height for a value in 0..1 (0 and 1 stay where they are)
+    #[cfg(any(feature = "gui", test))]
+    pub fn apply(self, v: VizFloat) -> VizFloat {
+        match self {
+            HeightCurve::Linear => v,
+            HeightCurve::Log => (1.0 + (9.0 * v)).log10(),
+            HeightCurve::Sqrt => v.sqrt(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BarOrder {
+    /// bass on the left, the order the bars are computed in
+    #[default]
+    LowToHigh,
+    /// treble on the left, the bars in reverse
+    HighToLow,
+    /// mirrored, bass at both edges and the highest bar in the middle (twice the bars minus one)
+    BassCentered,
+}
+
+impl BarOrder {
+    /// the bars of `frame` in drawing order (left to right), into `out`
+    #[cfg(any(feature = "gui", test))]
+    pub fn arrange(self, frame: &[VizFloat], out: &mut Vec<VizFloat>) {
+        out.clear();
+        match self {
+            BarOrder::LowToHigh => out.extend_from_slice(frame),
+            BarOrder::HighToLow => out.extend(frame.iter().rev()),
+            BarOrder::BassCentered => {
+                out.extend_from_slice(frame);
+                out.extend(frame.iter().rev().skip(1));
+            }
+        }
+    }
+}
+
+/// Optional taps into intermediate stages of the pipeline, used by the renderer
+#[derive(Clone, Default)]
+pub struct VizPipelineTaps {
+    /// receives the windowed time-domain samples right before the FFT
+    pub waveform: Option<WaveformTap>,
+    /// receives the peak and RMS of the time-domain samples before windowing
+    pub envelope: Option<EnvelopeTap>,
+    /// receives the stereo correlation of the time-domain samples before windowing
+    pub correlation: Option<CorrelationTap>,
+    /// receives the average frame time when adaptive quality switches to fast
+    pub quality: Option<QualityTap>,
+}
+
+fn default_seek_seconds() -> u64 {
+    10
+}
+
+fn default_magnitude_floor() -> VizFloat {
+    1e-10
+}
+
+fn default_drift_warn_frames() -> u32 {
+    2
 }
 
 impl VizPipelineConfig {
+    /// the sample rate a source at `sample_rate` is analyzed at, see resample
+    pub fn analysis_rate(&self, sample_rate: usize) -> usize {
+        self.resample
+            .map(move |resample| resample.rate)
+            .unwrap_or(sample_rate)
+    }
+
+    /// the rate and length of this source as the analysis sees it (after resampling)
+    pub fn analysis_length<S: Sampled>(&self, source: &S) -> ResampledLength {
+        ResampledLength::new(source, self.analysis_rate(source.sample_rate()))
+    }
+
     pub fn data_window(&self) -> Duration {
         Duration::from_millis(self.data_window_ms)
     }
+
+    /// samples in the analysis window of this source, window_samples or data_window_ms
+    pub fn window_size<S: Sampled>(&self, source: &S) -> usize {
+        self.window_samples
+            .unwrap_or_else(move || source.samples_from_dur(self.data_window()))
+    }
+
+    /// samples each window is zero padded to for the FFT, fft_samples or the window size
+    pub fn fft_size<S: Sampled>(&self, source: &S) -> usize {
+        self.fft_samples
+            .unwrap_or_else(move || self.window_size(source))
+    }
+
+    /// the option the FFT size comes from, to point at it in errors
+    fn fft_size_option(&self) -> &'static str {
+        if self.fft_samples.is_some() {
+            "fft_samples"
+        } else if self.window_samples.is_some() {
+            "window_samples"
+        } else {
+            "data_window_ms"
+        }
+    }
+
+    /// samples in every frame, the window or the long window of dual_resolution
+    pub fn frame_size<S: Sampled>(&self, source: &S) -> usize {
+        self.window_size(source) * self.dual_resolution_factor()
+    }
+
+    /// magnitudes in every frame of the spectrum (what the binner gets)
+    pub fn spectrum_size<S: Sampled>(&self, source: &S) -> usize {
+        (self.fft_size(source) * self.dual_resolution_factor()) / 2
+    }
+
+    /// length of the frames of this source, the display shows a frame at its middle
+    #[cfg(feature = "gui")]
+    pub fn window_duration<S: Sampled>(&self, source: &S) -> Duration {
+        let source = self.analysis_length(source);
+        let sample_rate = source.sample_rate();
+        if sample_rate == 0 {
+            return Duration::from_secs(0);
+        }
+
+        Duration::from_secs_f64(self.frame_size(&source) as f64 / sample_rate as f64)
+    }
+
+    fn dual_resolution_factor(&self) -> usize {
+        self.dual_resolution
+            .map(move |dual| dual.factor)
+            .unwrap_or(1)
+    }
+
+    /// dB value of magnitude_floor, what silence is reported as
+    pub fn db_floor(&self) -> VizFloat {
+        20.0 * self.magnitude_floor.log10()
+    }
+
+    /// this config with the debug frames dumped into `<debug_dump_dir>/<name>`, so pipelines
+    /// running side by side (--diff) don't overwrite each other's dumps
+    #[cfg(any(feature = "gui", test))]
+    pub fn with_debug_dump_subdir(&self, name: &str) -> Self {
+        let debug_dump_dir = self
+            .debug_dump_dir
+            .as_ref()
+            .map(move |dir| Path::new(dir).join(name).to_string_lossy().into_owned());
+        Self {
+            debug_dump_dir,
+            ..self.clone()
+        }
+    }
+}
+
+/// One stage of `create_viz_pipeline`, as described by `explain_viz_pipeline`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplainedStage {
+    pub name: &'static str,
+    /// the parameters of the stage
+    pub detail: String,
+    /// size of the frames the stage outputs
+    pub frame_size: usize,
 }
 
 const SEEK_BACK_LIMIT: usize = 1;
+// window_samples and fft_samples above this are a typo, not a window anyone wants to look at
+const MAX_FFT_SAMPLES: usize = 1 << 20;
+const MAX_DUAL_RESOLUTION_FACTOR: usize = 16;
+// more than a second out of sync is a typo (seconds instead of ms)
+const MAX_SYNC_OFFSET_MS: VizFloat = 1000.0;
+// frames quieter than this aren't amplified any further by AGC
+const AGC_FLOOR: VizFloat = 0.1;
 
-pub fn create_viz_pipeline<E, I, S>(source: S, config: VizPipelineConfig) -> Result<impl Framed<VizFloat, I>>
+// keep explain_viz_pipeline in sync when adding, removing or reordering stages
+pub fn create_viz_pipeline<E, I, S>(
+    source: S,
+    config: VizPipelineConfig,
+    taps: VizPipelineTaps,
+) -> Result<impl Framed<VizFloat, I>>
 where
     S: Samples<Channeled<E>, I>,
     E: Into<VizFloat>,
 {
+    let alpha1 = config.alpha1;
+    let warmup_frames = config.warmup_frames;
+    let adaptive_time_smoothing = config.adaptive_time_smoothing;
+    let (governor, quality) = QualityGovernor::for_quality(config.quality, config.fps);
+    let quality_tap = taps.quality.clone();
+    let smoothing1_quality = quality.clone();
+    let reduce_smoothing1 = config.quality != Quality::Full;
+    let smoothing1 = config.smoothing1;
+    let smoothing1_blend = config.smoothing1_blend;
+    let smoothing1_auto_window = config.smoothing1_auto_window;
+    let parallel_channels = config.parallel_channels;
+    let (min_db, max_db) = (config.min_db, config.max_db);
+    let db_floor = config.db_floor();
+    let clamp_mode = config.clamp_mode;
+    let amplitude_response = config.amplitude_response;
+    let auto_db = config.auto_db;
+    let compressor = match amplitude_response {
+        AmplitudeResponse::Db => config.compressor,
+        AmplitudeResponse::Linear(_) => None,
+    };
+    let fps = config.fps;
+    let agc = config.agc;
+    let metering = config.metering;
+    let envelope_follower = config.envelope_follower;
+    let mut quantize = discrete_levels(config.binning.discrete_levels);
+    let binning = config.binning.clone();
+    let dump_spectrum = debug_dump(config.debug_dump_dir.as_deref(), "spectrum")?;
+    let dump_binned = debug_dump(config.debug_dump_dir.as_deref(), "binned")?;
+
+    Ok(create_spectrum_pipeline(source, &config, taps, quality)?
+        .tap(dump_spectrum)
+        // bin the FFT output into a smaller number of bars
+        .compose(move |source| -> Result<_> {
+            let config = bin_config(binning, source.full_frame_size(), source.sample_rate());
+            config.validate()?;
+            Ok(source.apply_mapper(Binner::new(config)?))
+        })?
+        .tap(dump_binned)
+        // dB conversion
+        .map_mut(channeled_map_mut(move |v| {
+            if let AmplitudeResponse::Db = amplitude_response {
+                *v = magnitude_to_db(*v, db_floor)
+            }
+        }))
+        // tame loud bars
+        .lift(move |_| compressor.map(Compressor::new))
+        // learned loudness range -> min/max dB
+        .lift(move |_| auto_db.map(move |c| AutoDb::new(c, fps, min_db, max_db, db_floor)))
+        // clamp between min/max dB (or above the linear floor) -> (0, 1), metering only scales
+        .map_mut(channeled_map_mut(move |v| match amplitude_response {
+            AmplitudeResponse::Db if metering => normalize_unclamped(v, min_db, max_db),
+            AmplitudeResponse::Db => normalize_between(v, min_db, max_db, clamp_mode),
+            AmplitudeResponse::Linear(floor) => subtract_linear_floor(v, floor),
+        }))
+        // normalize infinities and NaNs
+        .map_mut(channeled_map_mut(normalize_infs))
+        // per frame normalization
+        .lift(move |_| if agc { Some(Agc::new(AGC_FLOOR)) } else { None })
+        // more savitzky golay smoothing after binning
+        .lift(move |size| {
+            if metering {
+                return None;
+            }
+
+            let smoothing1 = match smoothing1_auto_window {
+                Some(fraction) => {
+                    let auto = smoothing1.with_auto_window(size, fraction);
+                    println!(
+                        "smoothing1: window size {} (degree {}) for {} bars",
+                        auto.window_size, auto.degree, size
+                    );
+                    auto
+                }
+                None => smoothing1,
+            };
+            let full = smoothing1.into_blended_mapper(size, smoothing1_blend);
+            let fast = if reduce_smoothing1 {
+                let reduced = smoothing1.reduced();
+                Some(reduced.into_blended_mapper(size, smoothing1_blend))
+            } else {
+                None
+            };
+            let smoothing = QualitySmoothing::new(Some(full), fast, smoothing1_quality);
+            Some(in_parallel(smoothing, parallel_channels, size))
+        })
+        // keep smooth data inside (0, 1)
+        .map_mut(channeled_map_mut(move |v| {
+            if !metering {
+                constrain_normalized(v)
+            }
+        }))
+        // time smoothing again
+        .lift(move |_| {
+            ExponentialSmoothing::new(SEEK_BACK_LIMIT, alpha1)
+                .with_warmup(warmup_frames)
+                .with_adaptive(adaptive_time_smoothing)
+        })
+        // Channeled data to single value per bar
+        .map(flatten_channels)
+        // attack/decay per bar
+        .lift(move |_| envelope_follower.map(move |c| EnvelopeFollower::new(c, fps)))
+        // 48 distinct "levels" each bar can take on
+        .map_mut(move |v| {
+            if !metering {
+                quantize(v)
+            }
+        })
+        // time the frames and log it
+        .compose(move |frames| FramedTimed::new(frames, 1024))
+        // switch to fast quality when frames take too long
+        .with_governor(governor.map(move |governor| governor.with_tap(quality_tap))))
+}
+
+/// The same pipeline as `create_viz_pipeline`, for audio which is already decoded into memory
+/// (for embedding, the binary itself always reads files)
+#[allow(dead_code)]
+pub fn create_viz_pipeline_from_samples(
+    samples: Vec<Channeled<VizFloat>>,
+    sample_rate: usize,
+    config: VizPipelineConfig,
+) -> Result<impl Framed<VizFloat, VecSamples<Channeled<VizFloat>>>> {
+    create_viz_pipeline(
+        VecSamples::new(samples, sample_rate),
+        config,
+        VizPipelineTaps::default(),
+    )
+}
+
+/// The frequencies of the bars output by `create_viz_pipeline` for this source
+pub fn viz_bin_frequencies<S>(source: &S, config: &VizPipelineConfig) -> Result<Vec<BinFrequency>>
+where
+    S: Sampled,
+{
+    let source = config.analysis_length(source);
+    let input_size = config.spectrum_size(&source);
+    let config = bin_config(config.binning.clone(), input_size, source.sample_rate());
+    config.validate()?;
+    Ok(Binner::new(config)?.bin_frequencies())
+}
+
+/// The number of frames `create_viz_pipeline` outputs for this source (at most `max_frames`),
+/// which is what the viz loop renders, e.g. for showing "frame X of Y".
+///
+/// Frames start every `sample_rate / fps` samples (a fractional stride), so `num_frames` of the
+/// pipeline is only an estimate. The FFT pads the short frames at the end, so the binner never
+/// drops them, and every frame starting before the last sample is rendered.
+#[cfg(any(feature = "gui", test))]
+pub fn total_renderable_frames<S: Sampled>(source: &S, config: &VizPipelineConfig) -> usize {
+    let source = config.analysis_length(source);
+    let stride = std::cmp::max(
+        frame_stride(source.sample_rate(), config.fps),
+        Rational64::from(1),
+    );
+    let frames = (Rational64::from(source.num_samples() as i64) / stride)
+        .ceil()
+        .to_integer() as usize;
+    match config.max_frames {
+        Some(max_frames) => std::cmp::min(frames, max_frames),
+        None => frames,
+    }
+}
+
+/// The time (in seconds from the start of the source) of the middle of every frame of
+/// `create_viz_pipeline` by frame index, the moment the viz shows the frame at
+pub fn frame_timestamps<S: Sampled>(
+    source: &S,
+    config: &VizPipelineConfig,
+) -> impl Fn(usize) -> f64 + Send {
+    let source = config.analysis_length(source);
+    let sample_rate = source.sample_rate() as f64;
+    let stride = std::cmp::max(
+        frame_stride(source.sample_rate(), config.fps),
+        Rational64::from(1),
+    );
+    let half_frame = (config.frame_size(&source) as f64) / 2.0;
+    move |idx| ((frame_start(stride, idx) as f64) + half_frame) / sample_rate
+}
+
+// samples between the starts of two frames, frame i starts at floor(i * stride)
+fn frame_stride(sample_rate: usize, fps: u64) -> Rational64 {
+    Rational64::new(sample_rate as i64, fps as i64)
+}
+
+// stride / size, the share of every frame which wasn't in the frame before it (1.0 and more
+// without overlap)
+fn overlap_scale(stride: Rational64, frame_size: usize) -> VizFloat {
+    (*stride.numer() as VizFloat) / (*stride.denom() as VizFloat) / (frame_size as VizFloat)
+}
+
+/// The sizes of the FFTs the viz pipeline plans for this config at `sample_rate`
+pub fn fft_sizes(config: &VizPipelineConfig, sample_rate: usize) -> Result<Vec<usize>> {
+    let sample_rate = config.analysis_rate(sample_rate);
+    let source = VecSamples::<Channeled<VizFloat>>::new(Vec::new(), sample_rate);
+    let (_, fft_size) = window_and_fft_size(config, &source)?;
+    let mut sizes = vec![fft_size];
+    if let Some(dual) = config.dual_resolution {
+        sizes.push(fft_size * dual.factor);
+    }
+
+    Ok(sizes)
+}
+
+/// The stages `create_viz_pipeline` builds for this config at `sample_rate`, in order, with the
+/// stages that are disabled (or do nothing) left out. The pipeline's types are erased behind
+/// `impl Framed`, so this is a description kept next to it rather than read from the pipeline.
+pub fn explain_viz_pipeline(
+    config: &VizPipelineConfig,
+    sample_rate: usize,
+) -> Result<Vec<ExplainedStage>> {
+    let source_rate = sample_rate;
+    let sample_rate = config.analysis_rate(source_rate);
+    let source = VecSamples::<Channeled<VizFloat>>::new(Vec::new(), sample_rate);
+    let (window_size, fft_size) = window_and_fft_size(config, &source)?;
+    let frame_size = config.frame_size(&source);
+    let spectrum_size = config.spectrum_size(&source);
+    let stride = frame_stride(sample_rate, config.fps);
+    let binning = bin_config(config.binning.clone(), spectrum_size, sample_rate);
+    binning.validate()?;
+    let n_bins = Binner::new(binning)?.bin_frequencies().len();
+
+    let mut out = Vec::new();
+    let mut stage = |name, detail: String, frame_size| {
+        out.push(ExplainedStage {
+            name,
+            detail,
+            frame_size,
+        })
+    };
+
+    if let (Some(resample), true) = (config.resample, sample_rate != source_rate) {
+        stage(
+            "resample",
+            format!(
+                "{}Hz to {}Hz, {:?}",
+                source_rate, sample_rate, resample.quality
+            ),
+            1,
+        );
+    }
+    if config.balance != 0.0 {
+        stage("balance", format!("{}", config.balance), 1);
+    }
+    if config.mono_check {
+        stage("mono_check", "dual-mono sources as mono".to_string(), 1);
+    }
+    stage(
+        "sliding_window",
+        format!("stride {} samples ({} fps)", stride, config.fps),
+        frame_size,
+    );
+    if config.pre_emphasis > 0.0 {
+        stage(
+            "pre_emphasis",
+            format!("{}", config.pre_emphasis),
+            frame_size,
+        );
+    }
+    let fft = format!(
+        "{} samples ({} zero padded)",
+        fft_size,
+        fft_size - window_size
+    );
+    match config.dual_resolution {
+        Some(dual) => stage(
+            "dual_resolution_fft",
+            format!("{}, {}x below {}Hz", fft, dual.factor, dual.crossover_hz),
+            spectrum_size,
+        ),
+        None if config.parallel_channels => {
+            stage("blackman_nuttall", String::new(), window_size);
+            stage(
+                "fft",
+                format!("{}, channels in parallel", fft),
+                spectrum_size,
+            );
+        }
+        None => {
+            stage("blackman_nuttall", String::new(), window_size);
+            stage("fft", fft, spectrum_size);
+        }
+    }
+    if config.normalize_overlap {
+        stage(
+            "overlap_gain",
+            format!("x{:.4} (stride / size)", overlap_scale(stride, frame_size)),
+            spectrum_size,
+        );
+    }
+    if config.nyquist_compensation_db > 0.0 {
+        stage(
+            "nyquist_compensation",
+            format!("{} dB", config.nyquist_compensation_db),
+            spectrum_size,
+        );
+    }
+    let alpha = move |alpha| {
+        let warmup = match config.warmup_frames {
+            0 => format!("{}", alpha),
+            frames => format!("{} (warmup {} frames)", alpha, frames),
+        };
+        match config.adaptive_time_smoothing {
+            Some(adaptive) => format!("{} adaptive {}", warmup, adaptive.sensitivity),
+            None => warmup,
+        }
+    };
+    stage("alpha0", alpha(config.alpha0), spectrum_size);
+    match (config.skip_smoothing0, config.quality) {
+        (true, _) | (false, Quality::Fast) => {}
+        (false, Quality::Full) => stage(
+            "smoothing0",
+            format!("{:?}", config.smoothing0),
+            spectrum_size,
+        ),
+        (false, Quality::Adaptive) => stage(
+            "smoothing0",
+            format!("{:?} (skipped when slow)", config.smoothing0),
+            spectrum_size,
+        ),
+    }
+    stage(
+        "binner",
+        format!("{:?}", config.binning.normalization),
+        n_bins,
+    );
+    if let AmplitudeResponse::Db = config.amplitude_response {
+        stage("db", format!("floor {} dB", config.db_floor()), n_bins);
+    }
+    if let (Some(compressor), AmplitudeResponse::Db) =
+        (config.compressor, config.amplitude_response)
+    {
+        stage("compressor", format!("{:?}", compressor), n_bins);
+    }
+    if let Some(auto_db) = config.auto_db {
+        stage("auto_db", format!("{:?}", auto_db), n_bins);
+    }
+    let clamp = match config.amplitude_response {
+        AmplitudeResponse::Db if config.metering => {
+            format!("{} dB..{} dB unclamped", config.min_db, config.max_db)
+        }
+        AmplitudeResponse::Db => format!(
+            "{} dB..{} dB {:?}",
+            config.min_db, config.max_db, config.clamp_mode
+        ),
+        AmplitudeResponse::Linear(floor) => format!("linear above {}", floor),
+    };
+    stage("clamp", clamp, n_bins);
+    stage("normalize_infs", String::new(), n_bins);
+    if config.agc {
+        stage("agc", format!("floor {}", AGC_FLOOR), n_bins);
+    }
+    let smoothing1 = match config.smoothing1_auto_window {
+        Some(fraction) => config.smoothing1.with_auto_window(n_bins, fraction),
+        None => config.smoothing1,
+    };
+    let smoothing1 = match config.quality {
+        Quality::Full => format!("{:?}", smoothing1),
+        Quality::Fast => format!("{:?}", smoothing1.reduced()),
+        Quality::Adaptive => format!("{:?} ({:?} when slow)", smoothing1, smoothing1.reduced()),
+    };
+    let smoothing1 = match config.smoothing1_blend {
+        Some(blend) => format!("{} {:?}", smoothing1, blend),
+        None => smoothing1,
+    };
+    if !config.metering {
+        stage("smoothing1", smoothing1, n_bins);
+        stage("constrain", String::new(), n_bins);
+    }
+    stage("alpha1", alpha(config.alpha1), n_bins);
+    stage("flatten_channels", String::new(), n_bins);
+    if let Some(follower) = config.envelope_follower {
+        stage("envelope_follower", format!("{:?}", follower), n_bins);
+    }
+    if !config.metering {
+        stage(
+            "discrete_levels",
+            format!("{}", config.binning.discrete_levels),
+            n_bins,
+        );
+    }
+    if let (Some(governor), _) = QualityGovernor::for_quality(config.quality, config.fps) {
+        stage(
+            "quality_governor",
+            format!("fast when frames average over {:?}", governor.budget()),
+            n_bins,
+        );
+    }
+
+    Ok(out)
+}
+
+fn bin_config(binning: VizBinningConfig, input_size: usize, sample_rate: usize) -> BinConfig {
+    BinConfig {
+        bins: binning.bins,
+        fmin: binning.fmin,
+        fmax: binning.fmax,
+        gamma: binning.gamma,
+        scale: match binning.edges {
+            Some(edges) => BinScale::Explicit(edges),
+            None => BinScale::Gamma,
+        },
+        overlap: binning.overlap,
+        interpolate_low_bins: binning.interpolate_low_bins,
+        normalization: binning.normalization,
+        input_size,
+        sample_rate,
+    }
+}
+
+/// The full resolution magnitude spectrum (FFT + smoothing, `frame_size / 2` values per frame),
+/// without any binning or dB conversion
+pub fn create_raw_spectrum_pipeline<E, I, S>(
+    source: S,
+    config: VizPipelineConfig,
+    taps: VizPipelineTaps,
+) -> Result<impl Framed<VizFloat, I>>
+where
+    S: Samples<Channeled<E>, I>,
+    E: Into<VizFloat>,
+{
+    // only the viz pipeline adapts the quality
+    let (_, quality) = QualityGovernor::for_quality(config.quality, config.fps);
+    Ok(create_spectrum_pipeline(source, &config, taps, quality)?.map(flatten_channels))
+}
+
+/// Builds the pipeline of `--group-delay` exports: the group delay (in samples, channels
+/// averaged) of every bin of the windowed FFT, at the window and FFT size of the viz pipeline but
+/// without any of its smoothing
+pub fn create_group_delay_pipeline<E, I, S>(
+    source: S,
+    config: VizPipelineConfig,
+) -> Result<impl Framed<VizFloat, I>>
+where
+    S: Samples<Channeled<E>, I>,
+    E: Into<VizFloat>,
+{
+    if source.num_samples() == 0 {
+        return Err(anyhow!("input has no audio samples"));
+    }
+
+    let length = config.analysis_length(&source);
+    let (window_size, fft_size) = window_and_fft_size(&config, &length)?;
+    let sample_rate = length.sample_rate();
+    let num_samples = length.num_samples();
+    let resample_quality = config
+        .resample
+        .map(move |resample| resample.quality)
+        .unwrap_or_default();
+    let fps = config.fps;
+
+    Ok(source
+        .map(move |v| v.map(move |c| c.into()))
+        .compose(move |source| Resample::new(source, sample_rate, resample_quality))?
+        .compose(move |wav| {
+            let frame_stride = frame_stride(wav.sample_rate(), fps);
+            SlidingFrame::with_fractional_stride(wav, window_size, frame_stride)
+        })
+        .lift(move |size| BlackmanNuttall::mapper(std::cmp::min(size, num_samples)))
+        .try_lift(move |_| FramedComplexFft::new(fft_size))?
+        .lift(move |_| GroupDelay::new(fft_size))
+        .map(flatten_channels))
+}
+
+// the stages shared by the viz and raw spectrum pipelines: everything up to binning
+fn create_spectrum_pipeline<E, I, S>(
+    mut source: S,
+    config: &VizPipelineConfig,
+    taps: VizPipelineTaps,
+    quality: QualitySwitch,
+) -> Result<impl Framed<Channeled<VizFloat>, I> + Sampled>
+where
+    S: Samples<Channeled<E>, I>,
+    E: Into<VizFloat>,
+{
+    if source.num_samples() == 0 {
+        return Err(anyhow!("input has no audio samples"));
+    }
+
+    let as_mono = config.mono_check && is_dual_mono(&mut source)?;
+    if as_mono {
+        println!("both channels are identical, processing as mono");
+    }
+
+    // the sizes are all at the rate after resampling
+    let length = config.analysis_length(&source);
+    let (window_size, fft_size) = window_and_fft_size(config, &length)?;
+    let frame_size = config.frame_size(&length);
+    let sample_rate = length.sample_rate();
+    // a source shorter than one frame is a single short frame, windowed over the samples it has
+    // and zero padded by the FFT
+    let num_samples = length.num_samples();
+    if num_samples < frame_size {
+        println!(
+            "input is shorter than one window ({} of {} samples), zero padding it",
+            num_samples, frame_size
+        );
+    }
+    let dual_resolution = config.dual_resolution;
+    let resample_quality = config
+        .resample
+        .map(move |resample| resample.quality)
+        .unwrap_or_default();
+    let fft_size_option = config.fft_size_option();
+    let fft_wisdom = config.fft_wisdom.clone();
+    let parallel_channels = config.parallel_channels;
+    let balance = config.balance;
+    let pre_emphasis = config.pre_emphasis;
+    let nyquist_compensation_db = config.nyquist_compensation_db;
+    let fps = config.fps;
+    let alpha0 = config.alpha0;
+    let warmup_frames = config.warmup_frames;
+    let adaptive_time_smoothing = config.adaptive_time_smoothing;
+    let smoothing0 = if config.skip_smoothing0 {
+        None
+    } else {
+        Some(config.smoothing0)
+    };
+    let overlap_gain = if config.normalize_overlap {
+        Some(overlap_scale(frame_stride(sample_rate, fps), frame_size))
+    } else {
+        None
+    };
+    let VizPipelineTaps {
+        waveform,
+        envelope,
+        correlation,
+        quality: _,
+    } = taps;
+    let dump_fft = debug_dump(config.debug_dump_dir.as_deref(), "fft")?;
+
     Ok(source
         // change RawSample to VizFloat
         .map(move |v| v.map(move |c| c.into()))
+        // to the analysis rate (passes through at the rate of the source)
+        .compose(move |source| Resample::new(source, sample_rate, resample_quality))?
+        // left/right balance
+        .map(move |v| apply_balance(v, balance))
+        // dual-mono sources only need one channel
+        .map(move |v| if as_mono { collapse_to_mono(v) } else { v })
         // sliding frames of data
         .compose(move |wav| {
-            let frame_size = wav.samples_from_dur(config.data_window());
-            let sample_rate: Rational64 = (wav.sample_rate() as i64).into();
-            let frame_rate = Rational64::new_raw(1, config.fps as i64);
-            let frame_stride = frame_rate * sample_rate;
-            let frame_stride = *frame_stride.round().numer() as usize;
+            let frame_stride = frame_stride(wav.sample_rate(), fps);
             println!(
                 "sliding window: stride={}, size={}",
                 frame_stride, frame_size
             );
-            SlidingFrame::new(wav, frame_size, frame_stride)
+            SlidingFrame::with_fractional_stride(wav, frame_size, frame_stride)
         })
-        // blackman nuttall window
-        .lift(move |size| BlackmanNuttall::mapper(size))
-        // FFT
-        .try_lift(move |size| FramedFft::new(size))?
-        // time smoothing
-        .lift(move |_| ExponentialSmoothing::new(SEEK_BACK_LIMIT, config.alpha0))
-        // nearby bars smoothing Savitzky Golay
-        .lift(move |size| config.smoothing0.into_mapper(size))
-        // bin the FFT output into a smaller number of bars
-        .compose(move |source| {
-            let config = BinConfig {
-                bins: config.binning.bins,
-                fmin: config.binning.fmin,
-                fmax: config.binning.fmax,
-                gamma: config.binning.gamma,
-                input_size: source.full_frame_size(),
-                sample_rate: source.sample_rate(),
+        // envelope tap (time domain data before windowing)
+        .lift(move |_| envelope.map(move |tap| tap.mapper()))
+        // stereo correlation tap (time domain data before windowing)
+        .lift(move |_| correlation.map(move |tap| tap.mapper()))
+        // pre-emphasis (time domain high-pass)
+        .lift(move |_| {
+            if pre_emphasis > 0.0 {
+                Some(PreEmphasis::new(pre_emphasis))
+            } else {
+                None
+            }
+        })
+        // blackman nuttall window (only over the window, the FFT pads with zeros after it), the
+        // dual resolution FFT windows its two FFTs itself
+        .lift(move |size| match dual_resolution {
+            Some(_) => None,
+            None => Some(BlackmanNuttall::mapper(std::cmp::min(size, num_samples))),
+        })
+        // waveform tap (pre-FFT time domain data)
+        .lift(move |_| waveform.map(move |tap| tap.mapper()))
+        // FFT (zero padded to fft_size)
+        .try_lift(move |size| -> Result<_> {
+            if dual_resolution.is_some() {
+                return Ok(None);
+            }
+
+            let plan = move || match &fft_wisdom {
+                Some(path) => FramedFft::new_with_wisdom(fft_size, Path::new(path)),
+                None => FramedFft::new(fft_size),
             };
-            source.apply_mapper(Binner::new(config))
+            Ok(Some(if parallel_channels {
+                ParallelChannels::parallel(plan()?, plan()?, size)
+            } else {
+                ParallelChannels::sequential(plan()?)
+            }))
         })
-        // dB conversion
-        .map_mut(channeled_map_mut(to_db))
-        // clamp between min/max dB -> (0, 1)
+        .with_context(move || {
+            format!(
+                "could not set up the FFT of {} samples for a window of {} samples, \
+                 adjust {} to change its size",
+                fft_size, window_size, fft_size_option
+            )
+        })?
+        // or the long and the short FFT spliced at the crossover
+        .try_lift(move |_| {
+            dual_resolution
+                .map(move |dual| {
+                    DualResolutionFft::new(dual, window_size, fft_size, sample_rate, num_samples)
+                })
+                .transpose()
+        })?
+        .tap(dump_fft)
+        // overlap normalization
         .map_mut(channeled_map_mut(move |v| {
-            normalize_between(v, config.min_db, config.max_db)
+            if let Some(scale) = overlap_gain {
+                *v *= scale
+            }
+        }))
+        // top octave high shelf
+        .lift(move |size| {
+            if nyquist_compensation_db > 0.0 {
+                Some(NyquistCompensation::new(size, nyquist_compensation_db))
+            } else {
+                None
+            }
+        })
+        // time smoothing
+        .lift(move |_| {
+            ExponentialSmoothing::new(SEEK_BACK_LIMIT, alpha0)
+                .with_warmup(warmup_frames)
+                .with_adaptive(adaptive_time_smoothing)
+        })
+        // nearby bars smoothing Savitzky Golay (skipped at fast quality)
+        .lift(move |size| {
+            smoothing0.map(move |s| {
+                let smoothing = QualitySmoothing::new(Some(s.into_mapper(size)), None, quality);
+                in_parallel(smoothing, parallel_channels, size)
+            })
         }))
-        // normalize infinities and NaNs
-        .map_mut(channeled_map_mut(normalize_infs))
-        // more savitzky golay smoothing after binning
-        .lift(move |size| config.smoothing1.into_mapper(size))
-        // keep smooth data inside (0, 1)
-        .map_mut(channeled_map_mut(constrain_normalized))
-        // time smoothing again
-        .lift(move |_| ExponentialSmoothing::new(SEEK_BACK_LIMIT, config.alpha1))
-        // Channeled data to single value per bar
-        .map(flatten_channels)
-        // 48 distinct "levels" each bar can take on
-        .map_mut(discrete_levels(config.binning.discrete_levels))
-        // time the frames and log it
-        .compose(move |frames| FramedTimed::new(frames, 1024)))
 }
 
-fn to_db(v: &mut VizFloat) {
-    *v = 20.0 * v.log10();
+// two copies of the smoothing for the two channels with parallel_channels
+fn in_parallel(
+    smoothing: QualitySmoothing,
+    parallel_channels: bool,
+    size: usize,
+) -> ParallelChannels<QualitySmoothing, VizFloat, VizFloat> {
+    if parallel_channels {
+        ParallelChannels::parallel(smoothing.clone(), smoothing, size)
+    } else {
+        ParallelChannels::sequential(smoothing)
+    }
 }
 
-fn normalize_between(v: &mut VizFloat, min: VizFloat, max: VizFloat) {
+// fft_samples is only checked against data_window_ms here, where the sample rate is known
+fn window_and_fft_size<S: Sampled>(
+    config: &VizPipelineConfig,
+    source: &S,
+) -> Result<(usize, usize)> {
+    let window_size = config.window_size(source);
+    let fft_size = config.fft_size(source);
+    if fft_size < window_size {
+        return Err(anyhow!(
+            "fft_samples must be at least the window size, got {} for a window of {} samples",
+            fft_size,
+            window_size
+        ));
+    }
+
+    Ok((window_size, fft_size))
+}
+
+// writes every frame passing through a stage as a CSV row to `<dir>/<stage>.csv` (channels
+// averaged), does nothing without a dump dir
+fn debug_dump(
+    dir: Option<&str>,
+    stage: &'static str,
+) -> Result<impl FnMut(&[Channeled<VizFloat>]) + Send> {
+    let mut out = match dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            let path = Path::new(dir).join(format!("{}.csv", stage));
+            eprintln!("[debug] writing {} frames to {}", stage, path.display());
+            Some(BufWriter::new(File::create(path)?))
+        }
+        None => None,
+    };
+    let mut row = Vec::new();
+
+    Ok(move |frame: &[Channeled<VizFloat>]| {
+        if let Some(writer) = &mut out {
+            row.clear();
+            row.extend(frame.iter().map(move |v| v.to_mono()));
+            if let Err(err) = write_csv_row(writer, &row) {
+                eprintln!("[debug] stopped writing {} frames :: {:?}", stage, err);
+                out = None;
+            }
+        }
+    })
+}
+
+fn apply_balance(input: Channeled<VizFloat>, balance: VizFloat) -> Channeled<VizFloat> {
+    use Channeled::*;
+    match input {
+        Stereo(l, r) => {
+            let l_gain = (1.0 - balance).min(1.0);
+            let r_gain = (1.0 + balance).min(1.0);
+            Stereo(l * l_gain, r * r_gain)
+        }
+        Mono(v) => Mono(v),
+        // no left and right to balance
+        Multi(vs) => Multi(vs),
+    }
+}
+
+// the same fixed window is used for every value, so both channels of a stereo frame keep their
+// relative loudness (a quiet channel isn't stretched to look as loud as the other one)
+pub fn normalize_between(v: &mut VizFloat, min: VizFloat, max: VizFloat, mode: ClampMode) {
     let vv = *v;
-    if vv < min {
-        *v = 0.0;
-    } else if vv > max {
-        *v = 1.0;
-    } else {
-        *v = (vv - min) / (max - min);
+    match mode {
+        ClampMode::Hard => {
+            if vv < min {
+                *v = 0.0;
+            } else if vv > max {
+                *v = 1.0;
+            } else {
+                *v = (vv - min) / (max - min);
+            }
+        }
+        ClampMode::SoftKnee(width) => {
+            let x = (vv - min) / (max - min);
+            *v = if x > 1.0 - width {
+                (1.0 - width) + (width * ((x - (1.0 - width)) / width).tanh())
+            } else if x < width {
+                width - (width * ((width - x) / width).tanh())
+            } else {
+                x
+            };
+        }
     }
 }
 
+// min..max -> 0..1 like ClampMode::Hard, but values outside of the range are kept
+fn normalize_unclamped(v: &mut VizFloat, min: VizFloat, max: VizFloat) {
+    *v = (*v - min) / (max - min);
+}
+
+// floor..1 -> 0..1, anything at or below the floor is 0
+fn subtract_linear_floor(v: &mut VizFloat, floor: VizFloat) {
+    *v = VizFloat::max(0.0, (*v - floor) / (1.0 - floor));
+}
+
 fn normalize_infs(v: &mut VizFloat) {
     let vv = *v;
     if v.is_nan() || vv == VizFloat::NEG_INFINITY {
@@ -143,11 +1195,7 @@ fn constrain_normalized(v: &mut VizFloat) {
 }
 
 fn flatten_channels(input: &Channeled<VizFloat>) -> VizFloat {
-    use Channeled::*;
-    match *input {
-        Stereo(a, b) => (a + b) / (2.0 as VizFloat),
-        Mono(v) => v,
-    }
+    input.to_mono()
 }
 
 fn discrete_levels(levels: u32) -> impl FnMut(&mut VizFloat) {
@@ -164,25 +1212,103 @@ where
     }
 }
 
-pub fn open_config_or_default() -> Result<VizPipelineConfig> {
-    match open_config() {
+/// where `--preset` and `--save-preset` look for presets unless `--presets-dir` is given
+pub const DEFAULT_PRESETS_DIR: &str = "presets";
+// environment variables starting with this override config fields
+const ENV_PREFIX: &str = "VIZ_";
+// loaded from the presets dir when there is no config file in the working directory
+const DEFAULT_PRESET: &str = "default";
+
+/// writes the commented default config, a starting point for a `config.yml`
+pub fn write_default_config<W: Write>(mut out: W) -> Result<()> {
+    out.write_all(DEFAULT_CONFIG_YML.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+pub fn open_config_or_default(presets_dir: &str) -> Result<VizPipelineConfig> {
+    match open_config(presets_dir) {
         Ok(Some(config)) => Ok(config),
         Ok(None) => Ok(default_config()),
         Err(err) => Err(err),
     }
 }
 
+/// Overrides config fields with `VIZ_*` variables (from the environment, for containers), the
+/// values are YAML like in the config file:
+///
+/// | variable | field |
+/// |---|---|
+/// | `VIZ_FPS` | `fps` |
+/// | `VIZ_RENDER_FPS` | `render_fps` |
+/// | `VIZ_DATA_WINDOW_MS` | `data_window_ms` |
+/// | `VIZ_ALPHA0`, `VIZ_ALPHA1` | `alpha0`, `alpha1` |
+/// | `VIZ_BINS` | `binning.bins` |
+/// | `VIZ_FMIN`, `VIZ_FMAX` | `binning.fmin`, `binning.fmax` |
+/// | `VIZ_GAMMA` | `binning.gamma` |
+/// | `VIZ_MIN_DB`, `VIZ_MAX_DB` | `min_db`, `max_db` |
+/// | `VIZ_CLAMP_MODE` | `clamp_mode` |
+/// | `VIZ_BALANCE` | `balance` |
+/// | `VIZ_AGC` | `agc` |
+/// | `VIZ_MAX_FRAMES` | `max_frames` |
+/// | `VIZ_SEEK_SECONDS` | `seek_seconds` |
+///
+/// Other `VIZ_*` variables are ignored with a warning. The result is validated like a config file.
+pub fn apply_env_overrides<I>(mut config: VizPipelineConfig, vars: I) -> Result<VizPipelineConfig>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (name, value) in vars {
+        if !name.starts_with(ENV_PREFIX) {
+            continue;
+        }
+
+        let value = value.as_str();
+        match name.as_str() {
+            "VIZ_FPS" => config.fps = parse_env(&name, value)?,
+            "VIZ_RENDER_FPS" => config.render_fps = parse_env(&name, value)?,
+            "VIZ_DATA_WINDOW_MS" => config.data_window_ms = parse_env(&name, value)?,
+            "VIZ_ALPHA0" => config.alpha0 = parse_env(&name, value)?,
+            "VIZ_ALPHA1" => config.alpha1 = parse_env(&name, value)?,
+            "VIZ_BINS" => config.binning.bins = parse_env(&name, value)?,
+            "VIZ_FMIN" => config.binning.fmin = parse_env(&name, value)?,
+            "VIZ_FMAX" => config.binning.fmax = parse_env(&name, value)?,
+            "VIZ_GAMMA" => config.binning.gamma = parse_env(&name, value)?,
+            "VIZ_MIN_DB" => config.min_db = parse_env(&name, value)?,
+            "VIZ_MAX_DB" => config.max_db = parse_env(&name, value)?,
+            "VIZ_CLAMP_MODE" => config.clamp_mode = parse_env(&name, value)?,
+            "VIZ_BALANCE" => config.balance = parse_env(&name, value)?,
+            "VIZ_AGC" => config.agc = parse_env(&name, value)?,
+            "VIZ_MAX_FRAMES" => config.max_frames = parse_env(&name, value)?,
+            "VIZ_SEEK_SECONDS" => config.seek_seconds = parse_env(&name, value)?,
+            _ => {
+                eprintln!("[config] ignoring unknown variable {}", name);
+                continue;
+            }
+        }
+        eprintln!("[config] {}={} from the environment", name, value);
+    }
+
+    validate_config(config)
+}
+
+fn parse_env<T: DeserializeOwned>(name: &str, value: &str) -> Result<T> {
+    serde_yaml::from_str(value)
+        .with_context(move || format!("invalid value {} for {}", value, name))
+}
+
 macro_rules! try_load_config_from {
-    ($e: literal) => {
-        match open_config_file($e) {
+    ($e: expr) => {
+        let path = $e;
+        match open_config_file(&path) {
             Ok(Some(v)) => {
-                eprintln!("[config] loaded config from {}", $e);
+                eprintln!("[config] loaded config from {}", path.display());
                 return Ok(Some(v));
             }
             Ok(None) => {
                 eprintln!(
                     "[config] skipping load from {}, no config at this location",
-                    $e
+                    path.display()
                 );
             }
             Err(err) => return Err(err),
@@ -190,21 +1316,58 @@ macro_rules! try_load_config_from {
     };
 }
 
-pub fn open_config() -> Result<Option<VizPipelineConfig>> {
-    try_load_config_from!("config.yaml");
-    try_load_config_from!("config.yml");
-    try_load_config_from!("config");
+pub fn open_config(presets_dir: &str) -> Result<Option<VizPipelineConfig>> {
+    try_load_config_from!(Path::new("config.yaml"));
+    try_load_config_from!(Path::new("config.yml"));
+    try_load_config_from!(Path::new("config"));
+    try_load_config_from!(preset_path(presets_dir, DEFAULT_PRESET)?);
     Ok(None)
 }
 
-pub fn open_config_file(file: &str) -> Result<Option<VizPipelineConfig>> {
+/// `<dir>/<name>.yml`, names are plain file names (no path separators)
+pub fn preset_path(dir: &str, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(anyhow!("invalid preset name {:?}", name));
+    }
+
+    Ok(Path::new(dir).join(format!("{}.yml", name)))
+}
+
+pub fn open_preset(dir: &str, name: &str) -> Result<VizPipelineConfig> {
+    let path = preset_path(dir, name)?;
+    match open_config_file(&path)? {
+        Some(config) => {
+            eprintln!("[config] loaded preset {} from {}", name, path.display());
+            Ok(config)
+        }
+        None => Err(anyhow!("no preset {} at {}", name, path.display())),
+    }
+}
+
+/// Writes `config` as the preset `name`, creating the presets dir if needed
+pub fn save_preset(dir: &str, name: &str, config: &VizPipelineConfig) -> Result<PathBuf> {
+    let path = preset_path(dir, name)?;
+    std::fs::create_dir_all(dir)?;
+    serde_yaml::to_writer(File::create(&path)?, config)?;
+    Ok(path)
+}
+
+pub fn open_config_file<P>(file: P) -> Result<Option<VizPipelineConfig>>
+where
+    P: AsRef<Path>,
+{
+    let file = file.as_ref();
     Ok(Some(validate_config(serde_yaml::from_reader(
         match File::open(file) {
             Ok(f) => f,
             Err(err) => {
                 return match err.kind() {
                     ErrorKind::NotFound => Ok(None),
-                    other => Err(anyhow!("error opening file {} :: {:?}", file, other)),
+                    other => Err(anyhow!(
+                        "error opening file {} :: {:?}",
+                        file.display(),
+                        other
+                    )),
                 }
             }
         },
@@ -216,6 +1379,12 @@ fn validate_config(cfg: VizPipelineConfig) -> Result<VizPipelineConfig> {
         return Err(anyhow!("fps must be > 1, got {}", cfg.fps));
     }
 
+    if let Some(render_fps) = cfg.render_fps {
+        if render_fps <= 1 {
+            return Err(anyhow!("render fps must be > 1, got {}", render_fps));
+        }
+    }
+
     if cfg.data_window_ms <= 1 {
         return Err(anyhow!(
             "data window ms must be > 1ms, got {}ms",
@@ -223,6 +1392,69 @@ fn validate_config(cfg: VizPipelineConfig) -> Result<VizPipelineConfig> {
         ));
     }
 
+    let valid_size = move |size: usize| (2..=MAX_FFT_SAMPLES).contains(&size);
+    if let Some(window_samples) = cfg.window_samples {
+        if !valid_size(window_samples) {
+            return Err(anyhow!(
+                "window_samples out of range, got {} need [2, {}]",
+                window_samples,
+                MAX_FFT_SAMPLES
+            ));
+        }
+    }
+
+    if let Some(dual) = &cfg.dual_resolution {
+        if dual.factor < 2 || dual.factor > MAX_DUAL_RESOLUTION_FACTOR {
+            return Err(anyhow!(
+                "dual_resolution factor out of range, got {} need [2, {}]",
+                dual.factor,
+                MAX_DUAL_RESOLUTION_FACTOR
+            ));
+        }
+
+        if !dual.crossover_hz.is_normal() || dual.crossover_hz <= 0.0 {
+            return Err(anyhow!(
+                "dual_resolution crossover_hz must be > 0.0, got {}",
+                dual.crossover_hz
+            ));
+        }
+    }
+
+    if let Some(resample) = &cfg.resample {
+        if resample.rate == 0 {
+            return Err(anyhow!("resample rate must be > 0"));
+        }
+
+        if let ResampleQuality::Sinc { taps } = resample.quality {
+            if taps < 2 || taps % 2 != 0 {
+                return Err(anyhow!(
+                    "resample sinc taps must be even and at least 2, got {}",
+                    taps
+                ));
+            }
+        }
+    }
+
+    if let Some(fft_samples) = cfg.fft_samples {
+        if !valid_size(fft_samples) {
+            return Err(anyhow!(
+                "fft_samples out of range, got {} need [2, {}]",
+                fft_samples,
+                MAX_FFT_SAMPLES
+            ));
+        }
+
+        if let Some(window_samples) = cfg.window_samples {
+            if fft_samples < window_samples {
+                return Err(anyhow!(
+                    "fft_samples must be at least window_samples, got {} < {}",
+                    fft_samples,
+                    window_samples
+                ));
+            }
+        }
+    }
+
     if cfg.alpha0 <= 0.0 || cfg.alpha0 > 1.0 || !cfg.alpha0.is_normal() {
         return Err(anyhow!(
             "smoothing constant alpha0 out of range, got {} need (0.0, 1.0]",
@@ -237,9 +1469,111 @@ fn validate_config(cfg: VizPipelineConfig) -> Result<VizPipelineConfig> {
         ));
     }
 
+    if !cfg.pre_emphasis.is_finite() || cfg.pre_emphasis < 0.0 || cfg.pre_emphasis >= 1.0 {
+        return Err(anyhow!(
+            "pre_emphasis out of range, got {} need [0.0, 1.0)",
+            cfg.pre_emphasis
+        ));
+    }
+
+    if !cfg.nyquist_compensation_db.is_finite() || cfg.nyquist_compensation_db < 0.0 {
+        return Err(anyhow!(
+            "nyquist_compensation_db out of range, got {} need >= 0.0",
+            cfg.nyquist_compensation_db
+        ));
+    }
+
+    if !cfg.sync_offset_ms.is_finite() || cfg.sync_offset_ms.abs() > MAX_SYNC_OFFSET_MS {
+        return Err(anyhow!(
+            "sync_offset_ms out of range, got {} need [-{}, {}]",
+            cfg.sync_offset_ms,
+            MAX_SYNC_OFFSET_MS,
+            MAX_SYNC_OFFSET_MS
+        ));
+    }
+
+    if !cfg.balance.is_finite() || cfg.balance < -1.0 || cfg.balance > 1.0 {
+        return Err(anyhow!(
+            "balance out of range, got {} need [-1.0, 1.0]",
+            cfg.balance
+        ));
+    }
+
+    if let ClampMode::SoftKnee(width) = cfg.clamp_mode {
+        if !width.is_normal() || width <= 0.0 || width > 0.5 {
+            return Err(anyhow!(
+                "soft knee width out of range, got {} need (0.0, 0.5]",
+                width
+            ));
+        }
+    }
+
+    if !cfg.render.persistence.is_finite()
+        || cfg.render.persistence < 0.0
+        || cfg.render.persistence >= 1.0
+    {
+        return Err(anyhow!(
+            "persistence out of range, got {} need [0.0, 1.0)",
+            cfg.render.persistence
+        ));
+    }
+
     validate_smoothing_config(&cfg.smoothing0)?;
     validate_smoothing_config(&cfg.smoothing1)?;
 
+    if let Some(fraction) = cfg.smoothing1_auto_window {
+        if !fraction.is_normal() || fraction <= 0.0 || fraction > 1.0 {
+            return Err(anyhow!(
+                "smoothing1_auto_window out of range, got {} need (0.0, 1.0]",
+                fraction
+            ));
+        }
+    }
+
+    if let Some(compressor) = &cfg.compressor {
+        if !compressor.threshold_db.is_finite()
+            || !compressor.ratio.is_finite()
+            || compressor.ratio < 1.0
+        {
+            return Err(anyhow!(
+                "compressor out of range, got threshold_db={}, ratio={} need ratio >= 1.0",
+                compressor.threshold_db,
+                compressor.ratio
+            ));
+        }
+    }
+
+    if let Some(adaptive) = &cfg.adaptive_time_smoothing {
+        if !adaptive.sensitivity.is_finite() || adaptive.sensitivity < 0.0 {
+            return Err(anyhow!(
+                "adaptive_time_smoothing sensitivity must be >= 0.0, got {}",
+                adaptive.sensitivity
+            ));
+        }
+    }
+
+    if let Some(follower) = &cfg.envelope_follower {
+        let valid = move |ms: VizFloat| ms.is_finite() && ms >= 0.0;
+        if !valid(follower.attack_ms) || !valid(follower.decay_ms) {
+            return Err(anyhow!(
+                "envelope_follower times must be >= 0ms, got attack_ms={}, decay_ms={}",
+                follower.attack_ms,
+                follower.decay_ms
+            ));
+        }
+    }
+
+    if let Some(blend) = &cfg.smoothing1_blend {
+        let in_range = move |v: VizFloat| (0.0..=1.0).contains(&v);
+        if !in_range(blend.low) || !in_range(blend.high) {
+            return Err(anyhow!(
+                "smoothing1_blend out of range, need low and high in [0.0, 1.0], got low={}, high={}",
+                blend.low,
+                blend.high
+            ));
+        }
+    }
+
     if !cfg.min_db.is_normal() {
         return Err(anyhow!("invalid min_db, non-normal number {}", cfg.min_db));
     }
@@ -248,6 +1582,43 @@ fn validate_config(cfg: VizPipelineConfig) -> Result<VizPipelineConfig> {
         return Err(anyhow!("invalid max_db, non-normal number {}", cfg.min_db));
     }
 
+    if !cfg.magnitude_floor.is_normal() || cfg.magnitude_floor <= 0.0 {
+        return Err(anyhow!(
+            "magnitude_floor must be a small positive number, got {}",
+            cfg.magnitude_floor
+        ));
+    }
+
+    if let AmplitudeResponse::Linear(floor) = cfg.amplitude_response {
+        if !(0.0..1.0).contains(&floor) {
+            return Err(anyhow!(
+                "linear amplitude floor out of range, got {} need [0.0, 1.0)",
+                floor
+            ));
+        }
+
+        if cfg.auto_db.is_some() {
+            return Err(anyhow!("auto_db only works with the db amplitude response"));
+        }
+    }
+
+    if let Some(auto_db) = &cfg.auto_db {
+        if !auto_db.seconds.is_normal() || auto_db.seconds <= 0.0 {
+            return Err(anyhow!(
+                "auto_db seconds must be positive, got {}",
+                auto_db.seconds
+            ));
+        }
+
+        if !(0.0 <= auto_db.low && auto_db.low < auto_db.high && auto_db.high <= 1.0) {
+            return Err(anyhow!(
+                "auto_db percentiles out of range, need 0.0 <= low < high <= 1.0, got low={}, high={}",
+                auto_db.low,
+                auto_db.high
+            ));
+        }
+    }
+
     if cfg.min_db >= cfg.max_db {
         return Err(anyhow!(
             "min_db must be strictly less than max_db, got min={}, max={}",
@@ -290,6 +1661,36 @@ fn validate_config(cfg: VizPipelineConfig) -> Result<VizPipelineConfig> {
         ));
     }
 
+    if let Some(edges) = &binning.edges {
+        if edges.len() < 3 {
+            return Err(anyhow!(
+                "need at least 3 explicit bin edges (2 bins), got {}",
+                edges.len()
+            ));
+        }
+
+        if edges.iter().any(move |e| !e.is_normal() || *e <= 0.0) {
+            return Err(anyhow!(
+                "explicit bin edges must be positive normal numbers, got {:?}",
+                edges
+            ));
+        }
+
+        if edges.windows(2).any(move |win| win[0] >= win[1]) {
+            return Err(anyhow!(
+                "explicit bin edges must be strictly increasing, got {:?}",
+                edges
+            ));
+        }
+    }
+
+    if binning.interpolate_low_bins && binning.overlap != BinOverlap::None {
+        return Err(anyhow!(
+            "interpolate_low_bins cannot be combined with overlap {:?}",
+            binning.overlap
+        ));
+    }
+
     if binning.discrete_levels <= 2 {
         return Err(anyhow!(
             "discrete_levels must be a number greater than 2, got {}",
@@ -319,8 +1720,908 @@ fn validate_smoothing_config(cfg: &SavitzkyGolayConfig) -> Result<()> {
     Ok(())
 }
 
+/// the default config, with a comment on the fields (embedded at build time)
+pub const DEFAULT_CONFIG_YML: &str = include_str!("default-config.yml");
+
 fn default_config() -> VizPipelineConfig {
-    let out = serde_yaml::from_str(include_str!("default-config.yml")).expect("should be valid");
+    let out = serde_yaml::from_str(DEFAULT_CONFIG_YML).expect("should be valid");
     eprintln!("[config] using default config...");
     out
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::agc::Agc;
+    use crate::binner::Binner;
+    use crate::channeled::Channeled;
+    use crate::dual_resolution::DualResolutionConfig;
+    use crate::envelope_follower::EnvelopeFollowerConfig;
+    use crate::export::tests::sine_i16;
+    use crate::framed::{Framed, FramedMapper, Sampled};
+    use crate::pipeline::{
+        apply_balance, apply_env_overrides, bin_config, create_raw_spectrum_pipeline,
+        create_spectrum_pipeline, create_viz_pipeline, create_viz_pipeline_from_samples,
+        default_config, explain_viz_pipeline, flatten_channels, normalize_between, open_preset,
+        preset_path, save_preset, subtract_linear_floor, total_renderable_frames, validate_config,
+        viz_bin_frequencies, write_default_config, AmplitudeResponse, BarOrder, ClampMode,
+        HeightCurve, VizPipelineConfig, VizPipelineTaps, VizRenderConfig, DEFAULT_CONFIG_YML,
+    };
+    use crate::quality::QualitySwitch;
+    use crate::resample::{ResampleConfig, ResampleQuality};
+    use crate::savitzky_golay::{SavitzkyGolayConfig, SmoothingBlend};
+    use crate::util::{magnitude_to_db, VizFloat};
+    use crate::vec_samples::VecSamples;
+    use crate::wav::tests::WavBuilder;
+    use crate::wav::{SampleRaw, WavFile};
+    use crate::window::{BlackmanNuttall, WindowingFunction};
+    use anyhow::Result;
+    use std::path::{Path, PathBuf};
+
+    // small smoothing windows keep computing the coefficients quick in debug builds
+    pub fn fast_config() -> VizPipelineConfig {
+        let smoothing = SavitzkyGolayConfig {
+            window_size: 5,
+            degree: 2,
+            order: 0,
+        };
+        let mut config = default_config();
+        config.smoothing0 = smoothing;
+        config.smoothing1 = smoothing;
+        config
+    }
+
+    // a 16 bit mono sine of `n` samples, like a decoded wav file
+    pub fn tone(freq: f64, sample_rate: usize, n: usize) -> VecSamples<Channeled<SampleRaw>> {
+        let samples = sine_i16(freq, sample_rate as u32, n)
+            .into_iter()
+            .map(|v| Channeled::Mono(SampleRaw::TwoBytes(v)))
+            .collect();
+        VecSamples::new(samples, sample_rate)
+    }
+
+    // every frame of the viz pipeline over half a second of a sine at 44.1kHz
+    pub fn run_tone(freq: f64, config: VizPipelineConfig) -> Vec<Vec<VizFloat>> {
+        create_viz_pipeline(tone(freq, 44100, 22050), config, VizPipelineTaps::default())
+            .expect("should build")
+            .collect()
+            .expect("should run")
+    }
+
+    #[test]
+    fn balance_left_zeroes_right() {
+        assert_eq!(
+            apply_balance(Channeled::Stereo(0.5, 0.5), -1.0),
+            Channeled::Stereo(0.5, 0.0)
+        );
+        assert_eq!(
+            apply_balance(Channeled::Stereo(0.5, 0.5), 1.0),
+            Channeled::Stereo(0.0, 0.5)
+        );
+        assert_eq!(
+            apply_balance(Channeled::Stereo(0.5, 0.5), 0.0),
+            Channeled::Stereo(0.5, 0.5)
+        );
+        assert_eq!(
+            apply_balance(Channeled::Stereo(1.0, 1.0), -0.5),
+            Channeled::Stereo(1.0, 0.5)
+        );
+        assert_eq!(
+            apply_balance(Channeled::Mono(0.5), -1.0),
+            Channeled::Mono(0.5)
+        );
+    }
+
+    #[test]
+    fn silence_is_the_configured_db_floor() {
+        let mut config = default_config();
+        assert_eq!(config.db_floor(), -200.0);
+
+        config.magnitude_floor = 1e-6;
+        assert_eq!(magnitude_to_db(0.0, config.db_floor()), -120.0);
+        assert_eq!(magnitude_to_db(1e-9, config.db_floor()), -120.0);
+    }
+
+    #[test]
+    fn height_curves() {
+        assert_eq!(HeightCurve::Linear.apply(0.25), 0.25);
+        assert_eq!(HeightCurve::Sqrt.apply(0.25), 0.5);
+        assert!((HeightCurve::Log.apply(0.25) - 3.25_f64.log10()).abs() < 1e-12);
+        for curve in &[HeightCurve::Linear, HeightCurve::Log, HeightCurve::Sqrt] {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert_eq!(curve.apply(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn bar_orders() {
+        let frame = [0.0, 0.1, 0.2, 0.3];
+        let arranged = |order: BarOrder| {
+            let mut out = vec![1.0];
+            order.arrange(&frame, &mut out);
+            out
+        };
+        assert_eq!(arranged(BarOrder::LowToHigh), frame.to_vec());
+        assert_eq!(arranged(BarOrder::HighToLow), vec![0.3, 0.2, 0.1, 0.0]);
+
+        // bin 0 at both edges, the highest bin in the middle
+        let centered = arranged(BarOrder::BassCentered);
+        assert_eq!(centered, vec![0.0, 0.1, 0.2, 0.3, 0.2, 0.1, 0.0]);
+        assert_eq!(centered[centered.len() / 2], 0.3);
+    }
+
+    #[test]
+    fn soft_knee_keeps_how_far_over() {
+        let normalize = |v, mode| {
+            let mut v = v;
+            normalize_between(&mut v, -30.0, -10.0, mode);
+            v
+        };
+        let soft = ClampMode::SoftKnee(0.1);
+
+        // just above max_db, hard clamps to the top, soft stays below 1 but above the max_db value
+        assert_eq!(normalize(-9.0, ClampMode::Hard), 1.0);
+        let soft_over = normalize(-9.0, soft);
+        assert!(soft_over < 1.0);
+        assert!(soft_over > normalize(-10.0, soft));
+        assert!(normalize(-8.0, soft) > soft_over);
+
+        // the middle of the range is untouched
+        assert_eq!(normalize(-20.0, soft), normalize(-20.0, ClampMode::Hard));
+        assert!(normalize(-31.0, soft) > 0.0);
+    }
+
+    #[test]
+    fn stereo_channels_share_the_db_window() {
+        let normalize = |frame: &mut Vec<Channeled<VizFloat>>, mode| {
+            frame.iter_mut().for_each(move |v| {
+                v.as_mut_ref()
+                    .for_each(move |v| normalize_between(v, -30.0, -10.0, mode))
+            })
+        };
+
+        // left at -15 dB, right 10 dB quieter in every bin
+        let quiet_right = vec![Channeled::Stereo(-15.0, -25.0); 4];
+        let mut soft = quiet_right.clone();
+        normalize(&mut soft, ClampMode::SoftKnee(0.1));
+        assert_eq!(soft[0], Channeled::Stereo(0.75, 0.25));
+
+        let mut hard = quiet_right;
+        normalize(&mut hard, ClampMode::Hard);
+        assert_eq!(hard[0], Channeled::Stereo(0.75, 0.25));
+
+        // AGC scales both channels by the same amount
+        let mut agc = Agc::new(0.1);
+        let out = agc.map(&mut hard).expect("should map").expect("has frame");
+        assert_eq!(out[0], Channeled::Stereo(1.0, 0.25 / 0.75));
+    }
+
+    #[test]
+    fn linear_floor_is_subtracted_and_rescaled() {
+        let linear = |v| {
+            let mut v = v;
+            subtract_linear_floor(&mut v, 0.1);
+            v
+        };
+
+        assert_eq!(linear(0.1), 0.0);
+        assert_eq!(linear(1.0), 1.0);
+        assert_eq!(linear(0.05), 0.0);
+        assert!((linear(0.55) - 0.5).abs() < 1e-12);
+
+        let mut config = default_config();
+        config.amplitude_response = AmplitudeResponse::Linear(1.0);
+        assert!(validate_config(config).is_err());
+    }
+
+    #[test]
+    fn fft_error_has_window_context() {
+        // 2ms at 100Hz is not even one sample
+        let source = VecSamples::new(vec![Channeled::Mono(0.0); 100], 100);
+        let mut config = fast_config();
+        config.data_window_ms = 2;
+        match create_viz_pipeline(source, config, VizPipelineTaps::default()) {
+            Ok(_) => panic!("should not build a pipeline with an empty window"),
+            Err(err) => {
+                let message = format!("{:#}", err);
+                assert!(
+                    message.contains("window of 0 samples, adjust data_window_ms"),
+                    "{}",
+                    message
+                );
+                assert!(
+                    message.contains("fft size must be at least 2"),
+                    "{}",
+                    message
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_padding_interpolates_the_spectrum() {
+        let samples = (0..22050)
+            .map(|i| {
+                let t = (i as f64) / 44100.0;
+                Channeled::Mono((t * 1000.0 * std::f64::consts::PI * 2.0).sin() * 0.5)
+            })
+            .collect::<Vec<_>>();
+        let spectrum = |fft_samples| {
+            let mut config = fast_config();
+            config.skip_smoothing0 = true;
+            config.window_samples = Some(1024);
+            config.fft_samples = fft_samples;
+            let frames = create_raw_spectrum_pipeline(
+                VecSamples::new(samples.clone(), 44100),
+                config,
+                VizPipelineTaps::default(),
+            )
+            .expect("should build")
+            .collect()
+            .expect("should run");
+            frames[frames.len() / 2].clone()
+        };
+
+        let unpadded = spectrum(None);
+        let padded = spectrum(Some(4096));
+        assert_eq!(unpadded.len(), 512);
+        assert_eq!(padded.len(), 2048);
+
+        // bin k of the 1024 point FFT is bin 4k of the 4096 point FFT (outputs skip DC)
+        let peak = unpadded.iter().cloned().fold(0.0, VizFloat::max);
+        for (k, v) in unpadded.iter().enumerate() {
+            let interpolated = padded[(k + 1) * 4 - 1];
+            assert!(
+                (v - interpolated).abs() < peak * 1e-9,
+                "bin {}: {} != {}",
+                k + 1,
+                v,
+                interpolated
+            );
+        }
+
+        let mut config = default_config();
+        config.window_samples = Some(2048);
+        config.fft_samples = Some(1024);
+        assert!(validate_config(config).is_err());
+        let mut config = default_config();
+        config.window_samples = Some(1);
+        assert!(validate_config(config).is_err());
+    }
+
+    #[test]
+    fn fft_errors_name_the_size_option() {
+        let mut config = default_config();
+        assert_eq!(config.fft_size_option(), "data_window_ms");
+        config.window_samples = Some(1024);
+        assert_eq!(config.fft_size_option(), "window_samples");
+        config.fft_samples = Some(2048);
+        assert_eq!(config.fft_size_option(), "fft_samples");
+    }
+
+    // the spectrum frame in the middle of a test WAV file
+    fn middle_spectrum(wav: &WavBuilder, name: &str) -> Vec<VizFloat> {
+        let path = wav.write(name);
+        let wav = WavFile::open(&path, 64).expect("should open");
+        std::fs::remove_file(path).ok();
+        let frames = create_raw_spectrum_pipeline(wav, fast_config(), VizPipelineTaps::default())
+            .expect("should build")
+            .collect()
+            .expect("should run");
+        frames[frames.len() / 2].clone()
+    }
+
+    #[test]
+    fn float_wav_matches_pcm_wav() {
+        let tone = (0..8820)
+            .map(|i| ((i as f64) / 44100.0 * 1000.0 * std::f64::consts::PI * 2.0).sin() * 0.5)
+            .collect::<Vec<_>>();
+        let as_f32 = tone.iter().map(|v| *v as f32).collect::<Vec<_>>();
+        let as_i16 = tone
+            .iter()
+            .map(|v| (v * 32768.0).round() as i16)
+            .collect::<Vec<_>>();
+        let float = WavBuilder::float(1, 44100).samples_f32(&as_f32);
+        let pcm = WavBuilder::pcm(1, 44100, 16).samples_i16(&as_i16);
+        let float = middle_spectrum(&float, "float-tone");
+        let pcm = middle_spectrum(&pcm, "pcm-tone");
+        assert_eq!(float.len(), pcm.len());
+        let peak = float.iter().cloned().fold(0.0, VizFloat::max);
+        for (k, (a, b)) in float.iter().zip(pcm.iter()).enumerate() {
+            assert!((a - b).abs() < peak * 1e-3, "bin {}: {} != {}", k + 1, a, b);
+        }
+    }
+
+    #[test]
+    fn four_channel_wav_flattens_like_mono() {
+        let tone = sine_i16(1000.0, 44100, 8820);
+        let quad = tone.iter().flat_map(|v| vec![*v; 4]).collect::<Vec<_>>();
+        let mono = WavBuilder::pcm(1, 44100, 16).samples_i16(&tone);
+        let quad = WavBuilder::pcm(4, 44100, 16).samples_i16(&quad);
+        let mono = middle_spectrum(&mono, "mono-tone");
+        // real quad files have an extensible header
+        let extensible = middle_spectrum(&quad.clone().extensible(0x33), "quad-ext-tone");
+        let quad = middle_spectrum(&quad, "quad-tone");
+        for (name, quad) in [("quad", quad), ("extensible quad", extensible)].iter() {
+            assert_eq!(mono.len(), quad.len());
+            for (k, (a, b)) in mono.iter().zip(quad.iter()).enumerate() {
+                assert!((a - b).abs() < 1e-9, "{}, bin {}: {} != {}", name, k, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn dual_resolution_outputs_the_long_grid() {
+        let mut config = fast_config();
+        config.window_samples = Some(1024);
+        config.dual_resolution = Some(DualResolutionConfig {
+            factor: 4,
+            crossover_hz: 200.0,
+        });
+        let frames = create_raw_spectrum_pipeline(
+            tone(1000.0, 44100, 22050),
+            config.clone(),
+            VizPipelineTaps::default(),
+        )
+        .expect("should build")
+        .collect()
+        .expect("should run");
+        assert!(frames.iter().all(|frame| frame.len() == 2048));
+
+        let stages = explain_viz_pipeline(&config, 44100).expect("should explain");
+        let names = stages.iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(&names[..2], &["sliding_window", "dual_resolution_fft"]);
+        assert_eq!(stages[0].frame_size, 4096);
+        assert_eq!(stages[1].frame_size, 2048);
+
+        config.dual_resolution = Some(DualResolutionConfig {
+            factor: 1,
+            crossover_hz: 200.0,
+        });
+        assert!(validate_config(config).is_err());
+    }
+
+    #[test]
+    fn explained_stages_follow_the_config() {
+        let names = |config: &VizPipelineConfig| {
+            explain_viz_pipeline(config, 44100)
+                .expect("should explain")
+                .into_iter()
+                .map(|stage| stage.name)
+                .collect::<Vec<_>>()
+        };
+
+        let mut config = fast_config();
+        assert_eq!(
+            names(&config),
+            vec![
+                "sliding_window",
+                "blackman_nuttall",
+                "fft",
+                "alpha0",
+                "smoothing0",
+                "binner",
+                "db",
+                "clamp",
+                "normalize_infs",
+                "smoothing1",
+                "constrain",
+                "alpha1",
+                "flatten_channels",
+                "discrete_levels",
+            ]
+        );
+
+        config.skip_smoothing0 = true;
+        config.pre_emphasis = 0.95;
+        config.agc = true;
+        config.amplitude_response = AmplitudeResponse::Linear(0.1);
+        config.envelope_follower = Some(EnvelopeFollowerConfig {
+            attack_ms: 10.0,
+            decay_ms: 300.0,
+        });
+        assert_eq!(
+            names(&config),
+            vec![
+                "sliding_window",
+                "pre_emphasis",
+                "blackman_nuttall",
+                "fft",
+                "alpha0",
+                "binner",
+                "clamp",
+                "normalize_infs",
+                "agc",
+                "smoothing1",
+                "constrain",
+                "alpha1",
+                "flatten_channels",
+                "envelope_follower",
+                "discrete_levels",
+            ]
+        );
+
+        // the frame sizes are the ones the real pipeline produces
+        config.window_samples = Some(4000);
+        config.fft_samples = Some(8192);
+        let stages = explain_viz_pipeline(&config, 44100).expect("should explain");
+        let fft = stages.iter().find(|s| s.name == "fft").expect("has fft");
+        assert_eq!(fft.frame_size, 4096);
+        let source = VecSamples::new(vec![Channeled::Mono(0.0); 44100], 44100);
+        let pipeline = create_viz_pipeline(source, config.clone(), VizPipelineTaps::default())
+            .expect("should build");
+        assert_eq!(
+            stages.last().expect("has stages").frame_size,
+            pipeline.full_frame_size()
+        );
+    }
+
+    #[test]
+    fn map_after_binner_is_sized_for_the_bins() {
+        let config = fast_config();
+        let binning = config.binning.clone();
+        let mut frames = create_spectrum_pipeline(
+            tone(1000.0, 44100, 22050),
+            &config,
+            VizPipelineTaps::default(),
+            QualitySwitch::fixed(false),
+        )
+        .expect("should build")
+        .compose(move |source| {
+            let config = bin_config(binning, source.full_frame_size(), source.sample_rate());
+            source.apply_mapper(Binner::new(config).expect("should bin"))
+        })
+        .map(flatten_channels);
+        assert_eq!(frames.full_frame_size(), config.binning.bins);
+
+        // every frame is written into the same buffer, it was allocated big enough up front
+        let mut buf_at = None;
+        let mut n = 0;
+        while let Some(frame) = frames.next_frame().expect("should run") {
+            assert_eq!(frame.len(), config.binning.bins);
+            let at = frame.as_ptr();
+            assert_eq!(*buf_at.get_or_insert(at), at, "reallocated at frame {}", n);
+            n += 1;
+        }
+        assert!(n > 1);
+    }
+
+    #[test]
+    fn env_overrides_config_fields() {
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let config = apply_env_overrides(
+            fast_config(),
+            vars(&[
+                ("VIZ_FPS", "30"),
+                ("VIZ_BINS", "64"),
+                ("VIZ_AGC", "true"),
+                ("VIZ_CLAMP_MODE", "{soft_knee: 0.1}"),
+                ("VIZ_UNKNOWN", "1"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .expect("should override");
+        assert_eq!(config.fps, 30);
+        assert_eq!(config.binning.bins, 64);
+        assert!(config.agc);
+        assert_eq!(config.clamp_mode, ClampMode::SoftKnee(0.1));
+        assert_eq!(config.alpha0, fast_config().alpha0);
+
+        let err = apply_env_overrides(fast_config(), vars(&[("VIZ_FPS", "fast")]))
+            .expect_err("should not parse");
+        assert!(err.to_string().contains("VIZ_FPS"), "{}", err);
+        // overrides are validated like the config file
+        assert!(apply_env_overrides(fast_config(), vars(&[("VIZ_FPS", "1")])).is_err());
+    }
+
+    #[test]
+    fn printed_default_config_parses_back() {
+        let mut out = Vec::new();
+        write_default_config(&mut out).expect("should write");
+        let text = String::from_utf8(out).expect("should be utf8");
+        assert!(text.lines().any(|line| line.starts_with("# ")));
+
+        let parsed: VizPipelineConfig = serde_yaml::from_str(&text).expect("should parse");
+        let parsed = validate_config(parsed).expect("should be valid");
+        assert_eq!(parsed, default_config());
+    }
+
+    #[test]
+    fn missing_render_block_uses_the_documented_defaults() {
+        let text = DEFAULT_CONFIG_YML
+            .split("\nrender:")
+            .next()
+            .expect("should have content");
+        assert!(!text.contains("drift_warn_frames"));
+
+        let parsed: VizPipelineConfig = serde_yaml::from_str(text).expect("should parse");
+        assert_eq!(parsed.render, VizRenderConfig::default());
+        assert_eq!(parsed.render.drift_warn_frames, 2);
+        assert_eq!(parsed.render, default_config().render);
+    }
+
+    #[test]
+    fn metering_outputs_the_unclamped_normalized_bars() {
+        // the tone jumps up two octaves half way through
+        let samples = sine_i16(1000.0, 44100, 22050)
+            .into_iter()
+            .chain(sine_i16(4000.0, 44100, 22050))
+            .map(|v| Channeled::Mono(SampleRaw::TwoBytes(v)))
+            .collect::<Vec<_>>();
+        let mut config = fast_config();
+        config.metering = true;
+        config.skip_smoothing0 = true;
+        // alpha is the weight of the previous frame, the smallest one is (almost) no smoothing
+        config.alpha0 = VizFloat::MIN_POSITIVE;
+        config.alpha1 = VizFloat::MIN_POSITIVE;
+        let metered = create_viz_pipeline(
+            VecSamples::new(samples.clone(), 44100),
+            config.clone(),
+            VizPipelineTaps::default(),
+        )
+        .expect("should build")
+        .collect()
+        .expect("should run");
+
+        // the binned spectrum in dB, only scaled between min_db and max_db
+        let binning = config.binning.clone();
+        let expected = create_spectrum_pipeline(
+            VecSamples::new(samples, 44100),
+            &config,
+            VizPipelineTaps::default(),
+            QualitySwitch::fixed(false),
+        )
+        .expect("should build")
+        .compose(move |source| {
+            let config = bin_config(binning, source.full_frame_size(), source.sample_rate());
+            source.apply_mapper(Binner::new(config).expect("should bin"))
+        })
+        .map(flatten_channels)
+        .collect()
+        .expect("should run");
+
+        assert_eq!(metered.len(), expected.len());
+        for (metered, expected) in metered.iter().zip(expected.iter()) {
+            for (m, e) in metered.iter().zip(expected.iter()) {
+                let db = magnitude_to_db(*e, config.db_floor());
+                let e = (db - config.min_db) / (config.max_db - config.min_db);
+                assert!((m - e).abs() < 1e-9, "{} != {}", m, e);
+            }
+        }
+
+        // the tone's bar is above max_db, which the display would clamp to 1.0
+        assert!(metered.iter().flatten().any(|v| *v > 1.0));
+
+        // frames after the jump follow the new tone instead of holding the first frame
+        let loudest = |frame: &Vec<VizFloat>| {
+            (0..frame.len())
+                .max_by(|a, b| frame[*a].partial_cmp(&frame[*b]).expect("not nan"))
+                .expect("not empty")
+        };
+        let first = loudest(metered.first().expect("should have frames"));
+        let last = loudest(metered.last().expect("should have frames"));
+        assert!(last > first, "{} <= {}", last, first);
+    }
+
+    #[test]
+    fn renderable_frames_match_the_pipeline() {
+        // 48kHz at 144fps is a fractional stride of 333.33 samples
+        for (sample_rate, fps, num_samples) in &[(44100, 150, 4410), (48000, 144, 4801)] {
+            let mut config = fast_config();
+            config.fps = *fps;
+            let source = VecSamples::new(vec![Channeled::Mono(0.0); *num_samples], *sample_rate);
+            let expected = total_renderable_frames(&source, &config);
+            let frames = create_viz_pipeline(source, config, VizPipelineTaps::default())
+                .expect("should build")
+                .collect()
+                .expect("should run");
+            assert_eq!(frames.len(), expected, "{}Hz at {}fps", sample_rate, fps);
+        }
+
+        let mut config = fast_config();
+        config.max_frames = Some(3);
+        let source = VecSamples::new(vec![Channeled::Mono(0.0); 4410], 44100);
+        assert_eq!(total_renderable_frames(&source, &config), 3);
+    }
+
+    #[test]
+    fn resampled_source_gets_the_bins_of_the_target_rate() {
+        let bars = |source: VecSamples<_>, config: VizPipelineConfig| {
+            let expected = total_renderable_frames(&source, &config);
+            let frames = create_viz_pipeline(source, config, VizPipelineTaps::default())
+                .expect("should build")
+                .collect()
+                .expect("should run");
+            assert_eq!(frames.len(), expected);
+            frames
+        };
+
+        let mut config = fast_config();
+        config.resample = Some(ResampleConfig {
+            rate: 44100,
+            quality: ResampleQuality::Sinc { taps: 32 },
+        });
+        let source = tone(1000.0, 48000, 24000);
+        assert_eq!(
+            viz_bin_frequencies(&source, &config).expect("should bin"),
+            viz_bin_frequencies(&tone(1000.0, 44100, 22050), &config).expect("should bin")
+        );
+        let resampled = bars(source, config.clone());
+        let native = bars(tone(1000.0, 44100, 22050), config.clone());
+        assert_eq!(resampled.len(), native.len());
+        let (resampled, native) = (&resampled[native.len() / 2], &native[native.len() / 2]);
+        for (k, (a, b)) in resampled.iter().zip(native.iter()).enumerate() {
+            assert!((a - b).abs() < 0.05, "bar {}: {} != {}", k, a, b);
+        }
+
+        let stages = explain_viz_pipeline(&config, 48000).expect("should explain");
+        assert_eq!(stages[0].name, "resample");
+        let stages = explain_viz_pipeline(&config, 44100).expect("should explain");
+        assert_ne!(stages[0].name, "resample");
+    }
+
+    #[test]
+    fn pipeline_runs_without_smoothing0() {
+        let mut config = fast_config();
+        config.skip_smoothing0 = true;
+        let frames = run_tone(1000.0, config.clone());
+
+        assert!(!frames.is_empty());
+        assert!(frames
+            .iter()
+            .all(|frame| frame.len() == config.binning.bins));
+    }
+
+    #[test]
+    fn sine_from_memory_peaks_in_its_bin() {
+        let samples = (0..22050)
+            .map(|i| {
+                let t = (i as f64) / 44100.0;
+                Channeled::Mono((t * 1000.0 * std::f64::consts::PI * 2.0).sin() * 0.5)
+            })
+            .collect::<Vec<_>>();
+        let mut config = fast_config();
+        config.normalize_overlap = true;
+        config.min_db = -60.0;
+        config.max_db = 0.0;
+        // 1kHz is in the 5th bar (800Hz - 1600Hz)
+        config.binning.edges = Some(vec![
+            50.0, 100.0, 200.0, 400.0, 800.0, 1600.0, 3200.0, 6400.0, 12800.0,
+        ]);
+        let frames = create_viz_pipeline_from_samples(samples, 44100, config)
+            .expect("should build")
+            .collect()
+            .expect("should run");
+
+        let frame = &frames[frames.len() / 2];
+        let loudest = (0..frame.len())
+            .max_by(|a, b| frame[*a].partial_cmp(&frame[*b]).unwrap())
+            .unwrap();
+        assert_eq!(loudest, 4, "{:?}", frame);
+    }
+
+    #[test]
+    fn file_shorter_than_the_window_gives_a_padded_frame() {
+        let run = |num_samples: usize, config: VizPipelineConfig| {
+            let path = WavBuilder::pcm(1, 44100, 16)
+                .samples_i16(&sine_i16(2000.0, 44100, num_samples))
+                .write("shorter-than-window");
+            let wav = WavFile::open(&path, 64).expect("should open");
+            std::fs::remove_file(path).ok();
+
+            let bins = config.binning.bins;
+            let frames = create_viz_pipeline(wav, config, VizPipelineTaps::default())
+                .expect("should build")
+                .collect()
+                .expect("should run");
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].len(), bins);
+            assert!(frames[0].iter().all(|v| v.is_finite()), "{:?}", frames[0]);
+            frames.into_iter().next().expect("has a frame")
+        };
+
+        let mut config = fast_config();
+        config.window_samples = Some(1024);
+        config.min_db = -80.0;
+        // the low bars are narrower than the bins of a 1024 sample FFT
+        config.binning.interpolate_low_bins = true;
+        let frame = run(100, config.clone());
+        // the window covers the samples there are, so the tone is clearly there
+        let loudest = frame.iter().cloned().fold(0.0, VizFloat::max);
+        assert!(loudest > 0.5, "{:?}", frame);
+
+        // a window of a single sample, and the windows of the dual resolution FFT
+        run(1, config.clone());
+        config.dual_resolution = Some(DualResolutionConfig {
+            factor: 4,
+            crossover_hz: 200.0,
+        });
+        run(1, config.clone());
+        run(100, config);
+    }
+
+    #[test]
+    fn pre_emphasis_boosts_high_frequencies() {
+        // equally loud 200Hz and 8kHz tones
+        let samples = (0..8820)
+            .map(|i| {
+                let t = (i as f64) / 44100.0;
+                let tone = move |hz: f64| (t * hz * std::f64::consts::PI * 2.0).sin() * 0.25;
+                Channeled::Mono(tone(200.0) + tone(8000.0))
+            })
+            .collect::<Vec<_>>();
+
+        // magnitude of the loudest FFT bin within 50Hz of a frequency, in the middle frame
+        let peak_near = |pre_emphasis, hz: f64| {
+            let mut config = fast_config();
+            config.skip_smoothing0 = true;
+            config.pre_emphasis = pre_emphasis;
+            let source = VecSamples::new(samples.clone(), 44100);
+            let bin_hz = 22050.0 / ((source.samples_from_dur(config.data_window()) / 2) as f64);
+            let frames = create_raw_spectrum_pipeline(source, config, VizPipelineTaps::default())
+                .expect("should build")
+                .collect()
+                .expect("should run");
+            let frame = &frames[frames.len() / 2];
+            let (from, to) = (
+                ((hz - 50.0) / bin_hz) as usize,
+                ((hz + 50.0) / bin_hz) as usize,
+            );
+            frame[from..to].iter().cloned().fold(0.0, f64::max)
+        };
+
+        let flat = peak_near(0.0, 8000.0) / peak_near(0.0, 200.0);
+        let emphasized = peak_near(0.95, 8000.0) / peak_near(0.95, 200.0);
+        assert!((flat - 1.0).abs() < 0.1, "flat ratio {}", flat);
+        assert!(emphasized > flat * 10.0, "emphasized ratio {}", emphasized);
+    }
+
+    #[test]
+    fn empty_input_is_an_error() {
+        let path = WavBuilder::pcm(2, 44100, 16).write("empty-data");
+        let wav = WavFile::open(&path, 64).expect("empty data is still a valid wav");
+        std::fs::remove_file(path).ok();
+
+        match create_viz_pipeline(wav, default_config(), VizPipelineTaps::default()) {
+            Ok(_) => panic!("should not build a pipeline without samples"),
+            Err(err) => assert_eq!(err.to_string(), "input has no audio samples"),
+        }
+    }
+
+    #[test]
+    fn boxed_pipeline_runs_on_another_thread() {
+        let config = fast_config();
+        let bins = config.binning.bins;
+        let frames = create_viz_pipeline(
+            tone(440.0, 44100, 11025),
+            config,
+            VizPipelineTaps::default(),
+        )
+        .expect("should build")
+        .boxed();
+
+        let handle =
+            std::thread::spawn(move || frames.into_owned_frames().collect::<Result<Vec<_>>>());
+        let frames = handle
+            .join()
+            .expect("thread should not panic")
+            .expect("should produce frames");
+
+        assert!(!frames.is_empty());
+        assert!(frames.iter().all(|frame| frame.len() == bins));
+    }
+
+    #[test]
+    fn overlap_normalization_scales_by_stride_over_size() {
+        const WINDOW: usize = 1024;
+        // exactly on the 20th bin of the FFT, so none of it leaks into the bins next to it
+        let freq = 20.0 * 44100.0 / (WINDOW as VizFloat);
+        let samples = (0..44100)
+            .map(|i| {
+                let t = (i as VizFloat) / 44100.0;
+                Channeled::Mono((t * freq * std::f64::consts::TAU).sin())
+            })
+            .collect::<Vec<_>>();
+        // a unit sine at the center of a bin has a magnitude of half the sum of the window
+        let window_sum = (0..WINDOW)
+            .map(|i| BlackmanNuttall::coefficient(i as VizFloat, WINDOW as VizFloat))
+            .sum::<VizFloat>();
+
+        // ~50% and ~87.5% overlap
+        for &fps in &[86, 344] {
+            let mut config = fast_config();
+            config.normalize_overlap = true;
+            config.window_samples = Some(WINDOW);
+            config.skip_smoothing0 = true;
+            config.fps = fps;
+            let frames = create_raw_spectrum_pipeline(
+                VecSamples::new(samples.clone(), 44100),
+                config,
+                VizPipelineTaps::default(),
+            )
+            .expect("should build")
+            .collect()
+            .expect("should run");
+
+            // the DC bin is skipped
+            let peak = frames[frames.len() / 2][19];
+            let stride = 44100.0 / (fps as VizFloat);
+            let expected = (window_sum / 2.0) * (stride / (WINDOW as VizFloat));
+            assert!(
+                (peak - expected).abs() < expected * 1e-4,
+                "{} fps: {} vs {}",
+                fps,
+                peak,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn save_and_load_preset() {
+        let dir = std::env::temp_dir().join(format!("vis-rs-test-{}-presets", std::process::id()));
+        let dir = dir.to_str().expect("utf8 temp dir");
+
+        let mut config = default_config();
+        config.clamp_mode = ClampMode::SoftKnee(0.2);
+        config.render.height_curve = HeightCurve::Sqrt;
+        config.render.overflow_color = Some([255, 0, 0]);
+        config.smoothing1_blend = Some(SmoothingBlend {
+            low: 0.25,
+            high: 1.0,
+        });
+        config.max_frames = Some(100);
+
+        save_preset(dir, "loud", &config).expect("should save");
+        assert_eq!(open_preset(dir, "loud").expect("should load"), config);
+        assert!(open_preset(dir, "missing").is_err());
+        assert!(preset_path(dir, "../config").is_err());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn debug_dump_writes_every_stage() {
+        let dir = std::env::temp_dir().join(format!("vis-rs-test-{}-dump", std::process::id()));
+        let samples = (0..22050)
+            .map(|i| Channeled::Mono(((i as f64) * 0.05).sin() * 0.5))
+            .collect::<Vec<_>>();
+        let mut config = fast_config();
+        config.debug_dump_dir = Some(dir.to_str().expect("utf8 temp dir").to_string());
+        let n_frames = create_viz_pipeline_from_samples(samples, 44100, config)
+            .expect("should build")
+            .collect()
+            .expect("should run")
+            .len();
+
+        let rows = |stage: &str| {
+            std::fs::read_to_string(dir.join(format!("{}.csv", stage)))
+                .expect("should have written the stage")
+                .lines()
+                .map(|line| line.split(',').count())
+                .collect::<Vec<_>>()
+        };
+        // a trailing partial frame can make it through the FFT but not all the way to the output
+        assert!(rows("fft").len() >= n_frames);
+        assert!(rows("spectrum").len() >= n_frames);
+        let binned = rows("binned");
+        assert_eq!(binned.len(), n_frames);
+        assert!(binned.iter().all(|n| *n == 49));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn debug_dump_subdir_keeps_dumps_apart() {
+        let mut config = fast_config();
+        assert_eq!(config.with_debug_dump_subdir("a").debug_dump_dir, None);
+
+        config.debug_dump_dir = Some("debug".to_string());
+        let a = config.with_debug_dump_subdir("a").debug_dump_dir;
+        let b = config.with_debug_dump_subdir("b").debug_dump_dir;
+        assert_eq!(a.map(PathBuf::from), Some(Path::new("debug").join("a")));
+        assert_eq!(b.map(PathBuf::from), Some(Path::new("debug").join("b")));
+    }
+}
@@ -20,6 +20,66 @@ impl WavStates {
         std::mem::swap(&mut next, self);
         next
     }
+
+    fn is_playing(&self) -> bool {
+        matches!(self, WavStates::Playing(_))
+    }
+
+    /// Stops playback and moves the position, returns whether it was playing (and should resume)
+    fn seek(&mut self, amount: Duration, direction: SeekDirection) -> Result<bool> {
+        let seek_to = Instant::now().add(amount);
+        let was_playing = self.is_playing();
+        self.stop();
+        if let WavStates::Ready(player) = self {
+            let sample_time =
+                Duration::from_nanos(1_000_000_000 / (player.source.sample_rate as u64));
+            let position = player.source.sample_position();
+            let from_start = player.source.dur_from_samples(position);
+            // skip_to_duration clamps at the end, and checked_sub at the start of the file
+            let target = match direction {
+                SeekDirection::Forward => from_start + seek_to.sub(Instant::now()),
+                SeekDirection::Backward => from_start.checked_sub(amount).unwrap_or_default(),
+            };
+            player.source.skip_to_duration(target)?;
+
+            let moved_to = player.source.sample_position();
+            if moved_to >= position {
+                let skip_time = sample_time.mul((moved_to - position) as u32);
+                player.at += skip_time;
+                player.file_at += skip_time;
+            } else {
+                let skip_time = sample_time.mul((position - moved_to) as u32);
+                player.at = player.at.checked_sub(skip_time).unwrap_or_default();
+                player.file_at = player.file_at.checked_sub(skip_time).unwrap_or_default();
+            }
+        } else {
+            panic!("state malfunction, stopped but not in ready state")
+        }
+
+        Ok(was_playing)
+    }
+
+    fn stop(&mut self) {
+        match self.take() {
+            WavStates::Empty => panic!("empty when can't be empty"),
+            WavStates::Ready(ready) => {
+                *self = WavStates::Ready(ready);
+            }
+            WavStates::Playing(playing) => {
+                playing.pause();
+                let mut inner = playing.close_and_get_callback().inner;
+                inner.at +=
+                    Instant::now().sub(inner.start_playing_at.take().expect("should exist"));
+                *self = WavStates::Ready(inner);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekDirection {
+    Forward,
+    Backward,
 }
 
 pub struct WavPlayer {
@@ -57,7 +117,11 @@ impl WavPlayer {
                             channels: Some(ready.source.num_channels as u8),
                             samples: None,
                         },
-                        move |_| WavCallback { inner: ready },
+                        // the device may have other channels than asked for (stereo only output)
+                        move |spec| WavCallback {
+                            inner: ready,
+                            device_channels: spec.channels as usize,
+                        },
                     )
                     .map_err(map_sdl_err)?;
                 dev.resume();
@@ -68,40 +132,17 @@ impl WavPlayer {
     }
 
     pub fn stop(&mut self) -> Result<()> {
-        match self.state.take() {
-            WavStates::Empty => panic!("empty when can't be empty"),
-            WavStates::Ready(ready) => {
-                self.state = WavStates::Ready(ready);
-            }
-            WavStates::Playing(playing) => {
-                playing.pause();
-                let mut inner = playing.close_and_get_callback().inner;
-                inner.at +=
-                    Instant::now().sub(inner.start_playing_at.take().expect("should exist"));
-                self.state = WavStates::Ready(inner);
-            }
-        }
-
+        self.state.stop();
         Ok(())
     }
 
-    pub fn seek(&mut self, amount: Duration) -> Result<()> {
-        let seek_to = Instant::now().add(amount);
-        self.stop()?;
-        if let WavStates::Ready(player) = &mut self.state {
-            let amount = seek_to.sub(Instant::now());
-            let skip_samples = player.source.samples_from_dur(amount);
-            let skip_time =
-                Duration::from_nanos(1_000_000_000 / (player.source.sample_rate as u64))
-                    .mul(skip_samples as u32);
-            player.source.seek_samples(skip_samples as isize)?;
-            player.at += skip_time;
-            player.file_at += skip_time;
+    /// Seeks, and resumes playback only if it was playing (a paused player stays paused)
+    pub fn seek(&mut self, amount: Duration, direction: SeekDirection) -> Result<()> {
+        if self.state.seek(amount, direction)? {
+            self.play()
         } else {
-            panic!("state malfunction, stopped but not in ready state")
+            Ok(())
         }
-
-        self.play()
     }
 }
 
@@ -114,42 +155,135 @@ struct WavPlayerInner {
 
 struct WavCallback {
     inner: WavPlayerInner,
+    device_channels: usize,
 }
 
 impl AudioCallback for WavCallback {
     type Channel = f32;
 
     fn callback(&mut self, data: &mut [Self::Channel]) {
-        let mut idx = 0;
-        while let Some(sample) = self.inner.source.next_sample().expect("no err") {
-            match sample {
-                Channeled::Mono(v) => {
+        let source = &mut self.inner.source;
+        let samples = std::iter::from_fn(move || {
+            source.next_sample().expect("no err").map(move |sample| {
+                sample.map(move |v| {
                     let v: VizFloat = v.into();
-                    let v = v as f32;
-                    data[idx] = v;
-                }
-                Channeled::Stereo(l, r) => {
-                    let l: VizFloat = l.into();
-                    let r: VizFloat = r.into();
-                    let l = l as f32;
-                    let r = r as f32;
-                    data[idx] = l;
-                    idx += 1;
-                    data[idx] = r;
-                }
-            }
+                    v as f32
+                })
+            })
+        });
+        let frames = fill_device_buffer(samples, data, self.device_channels);
+        if frames * self.device_channels == data.len() {
+            self.inner.file_at +=
+                Duration::from_nanos(1_000_000_000 / (self.inner.source.sample_rate as u64))
+                    .mul(frames as u32);
+        }
+    }
+}
 
-            idx += 1;
-            if idx == data.len() {
-                self.inner.file_at +=
-                    Duration::from_nanos(1_000_000_000 / (self.inner.source.sample_rate as u64))
-                        .mul(idx as u32);
-                return;
+/// Writes samples into `data`, interleaved with `device_channels` values per sample, and returns
+/// how many samples were written. Mono samples go to every channel of the device, stereo samples
+/// go to the first two (and are mixed down for a mono device), samples with more channels go to
+/// the device channels in order (and are mixed down to every channel if the device has fewer).
+fn fill_device_buffer<I>(samples: I, data: &mut [f32], device_channels: usize) -> usize
+where
+    I: Iterator<Item = Channeled<f32>>,
+{
+    let mut written = 0;
+    // the buffer goes first in the zip, so no sample is taken from the source once it is full
+    for (frame, sample) in data.chunks_exact_mut(device_channels).zip(samples) {
+        match sample {
+            Channeled::Mono(v) => frame.iter_mut().for_each(move |out| *out = v),
+            Channeled::Stereo(l, r) if frame.len() == 1 => frame[0] = (l + r) / 2.0,
+            Channeled::Stereo(l, r) => {
+                frame[0] = l;
+                frame[1] = r;
+                frame[2..].iter_mut().for_each(move |out| *out = 0.0);
+            }
+            Channeled::Multi(vs) if vs.len() <= frame.len() => {
+                let (channels, rest) = frame.split_at_mut(vs.len());
+                channels.copy_from_slice(&vs);
+                rest.iter_mut().for_each(move |out| *out = 0.0);
+            }
+            Channeled::Multi(vs) => {
+                let v = vs.iter().sum::<f32>() / (vs.len() as f32);
+                frame.iter_mut().for_each(move |out| *out = v);
             }
         }
+        written += 1;
     }
+
+    written
 }
 
 fn map_sdl_err(err: String) -> anyhow::Error {
     anyhow::anyhow!("sdl2: {}", err)
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::Samples;
+    use crate::player::{fill_device_buffer, SeekDirection, WavPlayerInner, WavStates};
+    use crate::wav::tests::WavBuilder;
+    use crate::wav::WavFile;
+    use std::time::Duration;
+
+    #[test]
+    fn mono_source_on_a_stereo_device_plays_on_both_channels() {
+        let samples = vec![Channeled::Mono(0.1), Channeled::Mono(0.2)];
+        let mut data = [9.0; 6];
+        let written = fill_device_buffer(samples.into_iter(), &mut data, 2);
+        assert_eq!(written, 2);
+        assert_eq!(data, [0.1, 0.1, 0.2, 0.2, 9.0, 9.0]);
+    }
+
+    #[test]
+    fn stereo_source_fills_the_device_channels() {
+        let samples = vec![Channeled::Stereo(0.1, 0.2), Channeled::Stereo(0.3, 0.4)];
+        let mut data = [9.0; 4];
+        assert_eq!(
+            fill_device_buffer(samples.clone().into_iter(), &mut data, 2),
+            2
+        );
+        assert_eq!(data, [0.1, 0.2, 0.3, 0.4]);
+
+        let mut data = [9.0; 2];
+        assert_eq!(fill_device_buffer(samples.into_iter(), &mut data, 1), 2);
+        assert!((data[0] - 0.15).abs() < 1e-6 && (data[1] - 0.35).abs() < 1e-6);
+    }
+
+    #[test]
+    fn full_buffer_leaves_the_rest_of_the_source() {
+        let mut samples = vec![Channeled::Mono(0.1), Channeled::Mono(0.2)].into_iter();
+        let mut data = [0.0; 2];
+        assert_eq!(fill_device_buffer(&mut samples, &mut data, 2), 1);
+        assert_eq!(samples.next(), Some(Channeled::Mono(0.2)));
+    }
+
+    #[test]
+    fn seek_while_paused_stays_paused() {
+        let path = WavBuilder::pcm(1, 1000, 16)
+            .samples_i16(&[0; 4000])
+            .write("seek-paused");
+        let source = WavFile::open(&path, 64).expect("should open");
+        std::fs::remove_file(path).ok();
+
+        let mut state = WavStates::Ready(WavPlayerInner {
+            source,
+            start_playing_at: None,
+            at: Duration::from_secs(0),
+            file_at: Duration::from_secs(0),
+        });
+        let resume = state
+            .seek(Duration::from_secs(2), SeekDirection::Forward)
+            .expect("should seek");
+        assert!(!resume);
+        match state {
+            WavStates::Ready(player) => {
+                assert_eq!(player.at, Duration::from_secs(2));
+                assert_eq!(player.source.sample_position(), 2000);
+            }
+            _ => panic!("should still be paused"),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/// The files to visualize, in order. Once past the last file the playlist is finished, unless it
+/// repeats, then it starts over at the first file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playlist {
+    files: Vec<String>,
+    at: usize,
+    repeat: bool,
+}
+
+impl Playlist {
+    pub fn new(files: Vec<String>, repeat: bool) -> Self {
+        Self {
+            files,
+            at: 0,
+            repeat,
+        }
+    }
+
+    /// the file to play now, None once the playlist is finished
+    pub fn current(&self) -> Option<&str> {
+        self.files.get(self.at).map(String::as_str)
+    }
+
+    /// moves on to the next file (when the current one ends, or is skipped), returns that file
+    pub fn advance(&mut self) -> Option<&str> {
+        if self.at < self.files.len() {
+            self.at += 1;
+            if self.at == self.files.len() && self.repeat {
+                self.at = 0;
+            }
+        }
+
+        self.current()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::playlist::Playlist;
+
+    fn files(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn advance_ends_after_last_file() {
+        let mut playlist = Playlist::new(files(&["a.wav", "b.wav"]), false);
+        assert_eq!(playlist.current(), Some("a.wav"));
+        assert_eq!(playlist.advance(), Some("b.wav"));
+        assert_eq!(playlist.advance(), None);
+        assert_eq!(playlist.current(), None);
+        assert_eq!(playlist.advance(), None);
+    }
+
+    #[test]
+    fn repeat_wraps_to_first_file() {
+        let mut playlist = Playlist::new(files(&["a.wav", "b.wav", "c.wav"]), true);
+        // skipping is the same as the file ending
+        assert_eq!(playlist.advance(), Some("b.wav"));
+        assert_eq!(playlist.advance(), Some("c.wav"));
+        assert_eq!(playlist.advance(), Some("a.wav"));
+
+        let mut single = Playlist::new(files(&["a.wav"]), true);
+        assert_eq!(single.advance(), Some("a.wav"));
+
+        let mut empty = Playlist::new(Vec::new(), true);
+        assert_eq!(empty.current(), None);
+        assert_eq!(empty.advance(), None);
+    }
+}
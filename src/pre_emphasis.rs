@@ -0,0 +1,92 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+
+/// First order high-pass on the time-domain samples of every frame, `y[n] = x[n] - a * x[n - 1]`.
+///
+/// Music has most of its energy in the low frequencies, pre-emphasis tilts the spectrum so the
+/// high bars aren't always small. The first sample of a frame is filtered against the last
+/// sample of the previous frame (with overlapping frames that's a later sample than the one right
+/// before the frame, the window brings the frame edges down to ~0 either way). The first sample of
+/// the first frame is filtered as if it was preceded by itself.
+pub struct PreEmphasis {
+    coefficient: VizFloat,
+    // last unfiltered sample of the previous frame
+    last: Option<Channeled<VizFloat>>,
+}
+
+impl PreEmphasis {
+    pub fn new(coefficient: VizFloat) -> Self {
+        Self {
+            coefficient,
+            last: None,
+        }
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for PreEmphasis {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let a = self.coefficient;
+        let prev_last = std::mem::replace(&mut self.last, input.last().cloned());
+        // back to front, so x[n - 1] is still unfiltered
+        for n in (1..input.len()).rev() {
+            let prev = input[n - 1].clone();
+            if let Some(pair) = input[n].as_mut_ref().zip(prev) {
+                pair.for_each(move |(v, prev)| *v -= a * prev);
+            }
+        }
+
+        if let Some(first) = input.first_mut() {
+            let prev = prev_last.unwrap_or_else(|| first.clone());
+            if let Some(pair) = first.as_mut_ref().zip(prev) {
+                pair.for_each(move |(v, prev)| *v -= a * prev);
+            }
+        }
+
+        Ok(Some(input))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+    use crate::pre_emphasis::PreEmphasis;
+
+    #[test]
+    fn filters_against_the_previous_sample() {
+        let mut pre_emphasis = PreEmphasis::new(0.5);
+        let mut frame = vec![
+            Channeled::Stereo(1.0, 2.0),
+            Channeled::Stereo(2.0, 2.0),
+            Channeled::Stereo(4.0, 0.0),
+        ];
+        let out = pre_emphasis
+            .map(&mut frame)
+            .expect("should map")
+            .expect("has frame");
+        assert_eq!(
+            out,
+            &[
+                Channeled::Stereo(0.5, 1.0),
+                Channeled::Stereo(1.5, 1.0),
+                Channeled::Stereo(3.0, -1.0)
+            ]
+        );
+
+        // the next frame starts from the last sample of this one
+        let mut frame = vec![Channeled::Stereo(2.0, 2.0), Channeled::Stereo(1.0, 1.0)];
+        let out = pre_emphasis
+            .map(&mut frame)
+            .expect("should map")
+            .expect("has frame");
+        assert_eq!(
+            out,
+            &[Channeled::Stereo(0.0, 2.0), Channeled::Stereo(0.0, 0.0)]
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::savitzky_golay::SavitzkyGolayMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Smoothing quality versus speed, for many bars and long windows on slow machines
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    #[default]
+    Full,
+    /// skip smoothing0 and halve the smoothing1 window
+    Fast,
+    /// full until frames take longer than their share of the frame time, then fast
+    Adaptive,
+}
+
+// frames are averaged over this many frames before deciding
+const WINDOW_FRAMES: u32 = 64;
+// fraction of the time between frames computing a frame may take, the rest is for drawing
+const BUDGET_FRACTION: VizFloat = 0.5;
+
+/// Whether the pipeline currently runs at fast quality, shared between the stages which depend on
+/// it and the governor which flips it
+#[derive(Debug, Clone)]
+pub struct QualitySwitch {
+    fast: Arc<AtomicBool>,
+}
+
+impl QualitySwitch {
+    pub fn fixed(fast: bool) -> Self {
+        Self {
+            fast: Arc::new(AtomicBool::new(fast)),
+        }
+    }
+
+    pub fn is_fast(&self) -> bool {
+        self.fast.load(Ordering::Relaxed)
+    }
+}
+
+/// Shared handle which receives the average frame time when the governor switches to fast quality,
+/// so the renderer can report it together with its other warnings
+#[derive(Debug, Clone, Default)]
+pub struct QualityTap {
+    switched: Arc<Mutex<Option<Duration>>>,
+}
+
+impl QualityTap {
+    #[cfg(any(feature = "gui", test))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the average frame time which made the governor switch, once
+    #[cfg(any(feature = "gui", test))]
+    pub fn take_switched(&self) -> Option<Duration> {
+        self.switched.lock().expect("quality tap poisoned").take()
+    }
+}
+
+/// Switches to fast quality when frames take longer to compute than the budget (half the time
+/// between frames at `fps`), averaged over a few frames so a single slow frame doesn't count.
+///
+/// Once fast it stays fast: fast frames are quicker by design, so switching back would only
+/// bounce between the two.
+#[derive(Debug)]
+pub struct QualityGovernor {
+    budget: Duration,
+    switch: QualitySwitch,
+    frames: u32,
+    elapsed: Duration,
+    tap: Option<QualityTap>,
+}
+
+impl QualityGovernor {
+    pub fn new(fps: u64) -> Self {
+        Self {
+            budget: Duration::from_secs_f64(BUDGET_FRACTION / (fps as VizFloat)),
+            switch: QualitySwitch::fixed(false),
+            frames: 0,
+            elapsed: Duration::from_secs(0),
+            tap: None,
+        }
+    }
+
+    /// the average frame time above which this switches to fast
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// reports the switch to fast quality to `tap`
+    pub fn with_tap(self, tap: Option<QualityTap>) -> Self {
+        Self { tap, ..self }
+    }
+
+    /// the governor (only when adaptive) and the switch for the stages
+    pub fn for_quality(quality: Quality, fps: u64) -> (Option<Self>, QualitySwitch) {
+        match quality {
+            Quality::Full => (None, QualitySwitch::fixed(false)),
+            Quality::Fast => (None, QualitySwitch::fixed(true)),
+            Quality::Adaptive => {
+                let governor = Self::new(fps);
+                let switch = governor.switch.clone();
+                (Some(governor), switch)
+            }
+        }
+    }
+
+    /// Records the time a frame took, the average frame time when this switches to fast
+    pub fn record(&mut self, frame_time: Duration) -> Option<Duration> {
+        if self.switch.is_fast() {
+            return None;
+        }
+
+        self.frames += 1;
+        self.elapsed += frame_time;
+        if self.frames < WINDOW_FRAMES {
+            return None;
+        }
+
+        let average = self.elapsed / self.frames;
+        self.frames = 0;
+        self.elapsed = Duration::from_secs(0);
+        if average > self.budget {
+            self.switch.fast.store(true, Ordering::Relaxed);
+            if let Some(tap) = &self.tap {
+                *tap.switched.lock().expect("quality tap poisoned") = Some(average);
+            }
+            Some(average)
+        } else {
+            None
+        }
+    }
+}
+
+/// Savitzky Golay smoothing at full quality, and another one (or none) at fast quality
+#[derive(Clone)]
+pub struct QualitySmoothing {
+    full: Option<SavitzkyGolayMapper>,
+    fast: Option<SavitzkyGolayMapper>,
+    switch: QualitySwitch,
+}
+
+impl QualitySmoothing {
+    pub fn new(
+        full: Option<SavitzkyGolayMapper>,
+        fast: Option<SavitzkyGolayMapper>,
+        switch: QualitySwitch,
+    ) -> Self {
+        Self { full, fast, switch }
+    }
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for QualitySmoothing {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let mapper = if self.switch.is_fast() {
+            &mut self.fast
+        } else {
+            &mut self.full
+        };
+        match mapper {
+            Some(mapper) => mapper.map(input),
+            None => Ok(Some(input)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::quality::{Quality, QualityGovernor, QualityTap, WINDOW_FRAMES};
+    use std::time::Duration;
+
+    #[test]
+    fn slow_frames_switch_to_fast() {
+        // 60fps leaves 8.3ms per frame
+        let (governor, switch) = QualityGovernor::for_quality(Quality::Adaptive, 60);
+        let tap = QualityTap::new();
+        let mut governor = governor
+            .expect("adaptive has a governor")
+            .with_tap(Some(tap.clone()));
+        let frames = |governor: &mut QualityGovernor, ms: u64, n: u32| {
+            (0..n)
+                .filter_map(|_| governor.record(Duration::from_millis(ms)))
+                .collect::<Vec<_>>()
+        };
+
+        // a few slow frames among fast ones average out
+        assert!(frames(&mut governor, 2, WINDOW_FRAMES - 4).is_empty());
+        assert!(frames(&mut governor, 20, 4).is_empty());
+        assert!(!switch.is_fast());
+        assert_eq!(tap.take_switched(), None);
+
+        // slow on average for a whole window
+        let switched = frames(&mut governor, 12, WINDOW_FRAMES);
+        assert_eq!(switched, vec![Duration::from_millis(12)]);
+        assert!(switch.is_fast());
+        assert_eq!(tap.take_switched(), Some(Duration::from_millis(12)));
+        assert_eq!(tap.take_switched(), None);
+
+        // and it stays fast
+        assert!(frames(&mut governor, 1, WINDOW_FRAMES * 2).is_empty());
+        assert!(switch.is_fast());
+
+        let (governor, switch) = QualityGovernor::for_quality(Quality::Fast, 60);
+        assert!(governor.is_none() && switch.is_fast());
+        let (governor, switch) = QualityGovernor::for_quality(Quality::Full, 60);
+        assert!(governor.is_none() && !switch.is_fast());
+    }
+}
@@ -0,0 +1,425 @@
+use crate::channeled::Channeled;
+use crate::framed::{AudioSource, SampleFormat, Sampled, Samples};
+use crate::util::{log_timed, VizFloat};
+use crate::window::{BlackmanNuttall, WindowingFunction};
+use anyhow::{anyhow, Result};
+use num_rational::Rational64;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::marker::PhantomData;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResampleConfig {
+    /// the sample rate every source is analyzed at, so files at different rates get the same bins
+    pub rate: usize,
+    #[serde(default)]
+    pub quality: ResampleQuality,
+}
+
+/// How output samples are computed from the input samples around them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResampleQuality {
+    /// weighted average of the two closest input samples, cheap but attenuates high frequencies
+    /// and aliases when downsampling
+    #[default]
+    Linear,
+    /// windowed sinc over `taps` input samples (even, at least 2), low-passed below the lower
+    /// of the two nyquist frequencies
+    Sinc { taps: usize },
+}
+
+/// Converts a source to another sample rate.
+///
+/// Output sample `k` is at input position `k * in_rate / out_rate`. With `up / down` being that
+/// ratio in lowest terms, there are only `up` distinct fractional positions (phases), so the filter
+/// coefficients for every phase are computed once in the constructor (a polyphase filter bank).
+/// Input samples before the start or past the end of the source count as silence. At the rate of
+/// the source the samples pass through as they are.
+///
+/// The source should be at its first sample when wrapped.
+pub struct Resample<S, I> {
+    source: S,
+    rate: usize,
+    up: usize,
+    down: usize,
+    // input samples on either side of the output position, taps are [ip - half + 1, ip + half]
+    half: usize,
+    // coefficients for each phase, 2 * half per phase
+    bank: Vec<Vec<VizFloat>>,
+    buf: VecDeque<Channeled<VizFloat>>,
+    // input index of buf[0]
+    buf_start: usize,
+    zero: Option<Channeled<VizFloat>>,
+    at: usize,
+    _inner_typ: PhantomData<I>,
+}
+
+impl<S, I> Resample<S, I>
+where
+    S: Samples<Channeled<VizFloat>, I>,
+{
+    pub fn new(source: S, rate: usize, quality: ResampleQuality) -> Result<Self> {
+        if rate == 0 || source.sample_rate() == 0 {
+            return Err(anyhow!(
+                "cannot resample from {}Hz to {}Hz",
+                source.sample_rate(),
+                rate
+            ));
+        }
+
+        let ratio = Rational64::new(rate as i64, source.sample_rate() as i64);
+        let up = *ratio.numer() as usize;
+        let down = *ratio.denom() as usize;
+        let (half, bank) = match quality {
+            _ if up == down => (1, Vec::new()),
+            ResampleQuality::Linear => (1, linear_bank(up)),
+            ResampleQuality::Sinc { taps } => {
+                if taps < 2 || taps % 2 != 0 {
+                    return Err(anyhow!(
+                        "sinc taps must be even and at least 2, got {}",
+                        taps
+                    ));
+                }
+
+                let half = taps / 2;
+                // when downsampling, everything above the output nyquist has to go
+                let cutoff = VizFloat::min(1.0, (up as VizFloat) / (down as VizFloat));
+                let bank = log_timed(
+                    format!("compute sinc filter bank, {} phases x {} taps", up, taps),
+                    || sinc_bank(up, half, cutoff),
+                );
+                (half, bank)
+            }
+        };
+
+        Ok(Self {
+            source,
+            rate,
+            up,
+            down,
+            half,
+            bank,
+            buf: VecDeque::with_capacity(half * 2 + 1),
+            buf_start: 0,
+            zero: None,
+            at: 0,
+            _inner_typ: PhantomData,
+        })
+    }
+
+    fn passes_through(&self) -> bool {
+        self.up == self.down
+    }
+
+    // input index of the first tap for output sample `at`, and the phase of that sample
+    fn position(&self, at: usize) -> (isize, usize) {
+        let pos = at * self.down;
+        let ip = pos / self.up;
+        ((ip as isize) - (self.half as isize) + 1, pos % self.up)
+    }
+
+    // the input sample at `idx`, None for positions outside of the source
+    fn input_at(&mut self, idx: isize) -> Result<Option<Channeled<VizFloat>>> {
+        if idx < 0 {
+            return Ok(None);
+        }
+
+        let idx = idx as usize;
+        while self.buf_start + self.buf.len() <= idx {
+            match self.source.next_sample()? {
+                Some(sample) => {
+                    if self.zero.is_none() {
+                        self.zero = Some(sample.as_ref().map(move |_| 0.0));
+                    }
+                    self.buf.push_back(sample);
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(self.buf.get(idx - self.buf_start).cloned())
+    }
+}
+
+impl<S, I> Samples<Channeled<VizFloat>, I> for Resample<S, I>
+where
+    S: Samples<Channeled<VizFloat>, I>,
+{
+    fn into_deep_inner(self) -> I {
+        self.source.into_deep_inner()
+    }
+
+    // seeks past either end stop at that end
+    fn seek_samples(&mut self, n: isize) -> Result<()> {
+        if self.passes_through() {
+            return self.source.seek_samples(n);
+        }
+
+        let new_at = (self.at as isize) + n;
+        self.at = if new_at < 0 {
+            0
+        } else {
+            std::cmp::min(new_at as usize, self.num_samples())
+        };
+
+        // the buffer still covers the new position when seeking forward by a little
+        let (lo, _) = self.position(self.at);
+        let lo = std::cmp::max(lo, 0) as usize;
+        let buf_end = self.buf_start + self.buf.len();
+        if lo < self.buf_start || lo > buf_end {
+            let target = std::cmp::min(lo, self.source.num_samples().saturating_sub(1));
+            self.source
+                .seek_samples((target as isize) - (buf_end as isize))?;
+            self.buf.clear();
+            self.buf_start = target;
+        }
+
+        Ok(())
+    }
+
+    fn next_sample(&mut self) -> Result<Option<Channeled<VizFloat>>> {
+        if self.passes_through() {
+            return self.source.next_sample();
+        }
+
+        if !self.has_more_samples() {
+            return Ok(None);
+        }
+
+        let (lo, phase) = self.position(self.at);
+        while (self.buf_start as isize) < lo && !self.buf.is_empty() {
+            self.buf.pop_front();
+            self.buf_start += 1;
+        }
+
+        let mut out = None;
+        for j in 0..(self.half * 2) {
+            let coefficient = self.bank[phase][j];
+            if let Some(sample) = self.input_at(lo + (j as isize))? {
+                let acc = out.get_or_insert_with(|| sample.as_ref().map(move |_| 0.0));
+                acc.as_mut_ref()
+                    .zip(sample)
+                    .ok_or_else(|| anyhow!("mono/stereo changed while resampling"))?
+                    .for_each(move |(acc, v)| *acc += v * coefficient);
+            }
+        }
+
+        self.at += 1;
+        Ok(out.or_else(|| self.zero.clone()))
+    }
+
+    fn num_samples_remain(&self) -> usize {
+        if self.passes_through() {
+            self.source.num_samples_remain()
+        } else {
+            self.num_samples() - self.at
+        }
+    }
+}
+
+impl<S, I> Sampled for Resample<S, I>
+where
+    S: Sampled,
+{
+    fn sample_rate(&self) -> usize {
+        self.rate
+    }
+
+    fn num_samples(&self) -> usize {
+        resampled_len(self.source.num_samples(), self.up, self.down)
+    }
+}
+
+/// The sample rate and number of samples a source has once resampled, without resampling it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResampledLength {
+    sample_rate: usize,
+    num_samples: usize,
+}
+
+impl ResampledLength {
+    pub fn new<S: Sampled>(source: &S, rate: usize) -> Self {
+        let from = source.sample_rate();
+        let num_samples = if from == 0 || rate == 0 {
+            source.num_samples()
+        } else {
+            let ratio = Rational64::new(rate as i64, from as i64);
+            let (up, down) = (*ratio.numer() as usize, *ratio.denom() as usize);
+            resampled_len(source.num_samples(), up, down)
+        };
+
+        Self {
+            sample_rate: rate,
+            num_samples,
+        }
+    }
+}
+
+impl Sampled for ResampledLength {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn num_samples(&self) -> usize {
+        self.num_samples
+    }
+}
+
+// ceil(n * up / down), the last output sample is still within the input
+fn resampled_len(n: usize, up: usize, down: usize) -> usize {
+    (n * up).div_ceil(down)
+}
+
+impl<S, I> AudioSource for Resample<S, I>
+where
+    S: AudioSource,
+{
+    fn num_channels(&self) -> usize {
+        self.source.num_channels()
+    }
+
+    fn sample_format(&self) -> SampleFormat {
+        SampleFormat::Float {
+            bits: (std::mem::size_of::<VizFloat>() * 8) as u16,
+        }
+    }
+}
+
+fn linear_bank(up: usize) -> Vec<Vec<VizFloat>> {
+    (0..up)
+        .map(move |phase| {
+            let frac = (phase as VizFloat) / (up as VizFloat);
+            vec![1.0 - frac, frac]
+        })
+        .collect()
+}
+
+// cutoff is relative to the input nyquist, each phase is normalized to a gain of 1 at DC
+fn sinc_bank(up: usize, half: usize, cutoff: VizFloat) -> Vec<Vec<VizFloat>> {
+    let window_len = (half * 2 + 1) as VizFloat;
+    (0..up)
+        .map(move |phase| {
+            let frac = (phase as VizFloat) / (up as VizFloat);
+            let mut coefficients = (0..(half * 2))
+                .map(move |j| {
+                    // distance from the output position to this tap, in input samples
+                    let d = ((j as VizFloat) - (half as VizFloat) + 1.0) - frac;
+                    let window = BlackmanNuttall::coefficient(d + (half as VizFloat), window_len);
+                    cutoff * sinc(cutoff * d) * window
+                })
+                .collect::<Vec<_>>();
+            let sum: VizFloat = coefficients.iter().sum();
+            coefficients.iter_mut().for_each(move |c| *c /= sum);
+            coefficients
+        })
+        .collect()
+}
+
+fn sinc(x: VizFloat) -> VizFloat {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::{Sampled, Samples};
+    use crate::resample::{Resample, ResampleQuality};
+    use crate::util::VizFloat;
+    use crate::vec_samples::VecSamples;
+    use std::f64::consts::PI;
+
+    fn tone(freq: VizFloat, rate: usize, n: usize) -> Vec<Channeled<VizFloat>> {
+        (0..n)
+            .map(move |i| {
+                let t = (i as VizFloat) / (rate as VizFloat);
+                Channeled::Mono(0.5 * (2.0 * PI * freq * t).sin())
+            })
+            .collect()
+    }
+
+    fn resample_all(
+        samples: Vec<Channeled<VizFloat>>,
+        from: usize,
+        to: usize,
+        quality: ResampleQuality,
+    ) -> Vec<VizFloat> {
+        let mut resampled = Resample::new(VecSamples::new(samples, from), to, quality)
+            .expect("should create resampler");
+        let mut out = Vec::new();
+        while let Some(sample) = resampled.next_sample().expect("should resample") {
+            out.push(sample.to_mono());
+        }
+        assert_eq!(out.len(), resampled.num_samples());
+        out
+    }
+
+    // RMS of the middle of the output, away from the edges where the input counts as silence
+    fn rms_middle(samples: &[VizFloat]) -> VizFloat {
+        let middle = &samples[(samples.len() / 4)..((samples.len() * 3) / 4)];
+        let sum: VizFloat = middle.iter().map(move |v| v * v).sum();
+        (sum / (middle.len() as VizFloat)).sqrt()
+    }
+
+    #[test]
+    fn sinc_preserves_tone_amplitude_better_than_linear() {
+        let expected = 0.5 / VizFloat::sqrt(2.0);
+        let samples = tone(8000.0, 44100, 4410);
+        let linear = resample_all(samples.clone(), 44100, 48000, ResampleQuality::Linear);
+        let sinc = resample_all(samples, 44100, 48000, ResampleQuality::Sinc { taps: 32 });
+        assert_eq!(linear.len(), 4800);
+
+        let linear_err = (rms_middle(&linear) - expected).abs();
+        let sinc_err = (rms_middle(&sinc) - expected).abs();
+        assert!(
+            sinc_err < linear_err,
+            "sinc error {} should be below linear error {}",
+            sinc_err,
+            linear_err
+        );
+        assert!(sinc_err < expected * 0.01, "sinc error {}", sinc_err);
+    }
+
+    #[test]
+    fn seek_matches_sequential() {
+        let samples = tone(440.0, 8000, 800);
+        let quality = ResampleQuality::Sinc { taps: 8 };
+        let sequential = resample_all(samples.clone(), 8000, 11025, quality);
+
+        let mut resampled =
+            Resample::new(VecSamples::new(samples, 8000), 11025, quality).expect("should create");
+        resampled.seek_samples(500).expect("should seek");
+        let sample = resampled
+            .next_sample()
+            .expect("should read")
+            .expect("exists");
+        assert_eq!(sample.to_mono(), sequential[500]);
+
+        resampled.seek_samples(-400).expect("should seek");
+        let sample = resampled
+            .next_sample()
+            .expect("should read")
+            .expect("exists");
+        assert_eq!(sample.to_mono(), sequential[101]);
+    }
+
+    #[test]
+    fn same_rate_passes_through() {
+        let samples = tone(440.0, 8000, 100);
+        let quality = ResampleQuality::Sinc { taps: 8 };
+        let out = resample_all(samples.clone(), 8000, 8000, quality);
+        let expected = samples.iter().map(|v| v.to_mono()).collect::<Vec<_>>();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn rejects_bad_taps() {
+        let source = VecSamples::new(tone(440.0, 8000, 10), 8000);
+        assert!(Resample::new(source, 16000, ResampleQuality::Sinc { taps: 7 }).is_err());
+    }
+}
@@ -71,11 +71,10 @@
 use crate::channeled::Channeled;
 use crate::framed::FramedMapper;
 use crate::util::{log_timed, VizFloat};
-use anyhow::Result;
-use itertools::Itertools;
+use anyhow::{anyhow, Result};
 use num_rational::Rational64;
 use rayon::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::iter::{FusedIterator, TrustedLen};
 
 // thanks to: https://github.com/arntanguy/gram_savitzky_golay/tree/master/src
@@ -127,13 +126,13 @@ fn weight(i: Rational64, t: Rational64, m: Rational64, n: Rational64, s: Rationa
 fn weights(m: i64, t: Rational64, n: Rational64, s: Rational64) -> Vec<(VizFloat, VizFloat)> {
     (0..((2 * m) + 1))
         .into_par_iter()
-        .map(move |i| weight(((i - m) as i64).into(), t, (m as i64).into(), n, s))
+        .map(move |i| weight((i - m).into(), t, m.into(), n, s))
         .map(move |f| f.reduced())
         .map(move |f| (*f.numer() as VizFloat, *f.denom() as VizFloat))
         .collect::<Vec<_>>()
 }
 
-#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Deserialize, Serialize)]
 pub struct SavitzkyGolayConfig {
     /// The number of coefficients to compute (the number of nearby points to convolve when computing any given point)
     pub window_size: u64,
@@ -143,11 +142,94 @@ pub struct SavitzkyGolayConfig {
     pub order: u64,
 }
 
+/// How much of the smoothed value is kept for each point, interpolated linearly from `low` at the
+/// first point to `high` at the last (0.0 keeps the unsmoothed value, 1.0 the fully smoothed one).
+/// Noisy high frequency bars can be smoothed more than the bass this way, even though the window
+/// size of the smoother is the same for every point.
+#[derive(PartialEq, Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct SmoothingBlend {
+    pub low: VizFloat,
+    pub high: VizFloat,
+}
+
+impl SmoothingBlend {
+    /// the blend factor of each of `size` points
+    pub fn factors(&self, size: usize) -> Vec<VizFloat> {
+        let last = std::cmp::max(size, 2) - 1;
+        (0..size)
+            .map(move |i| {
+                let t = (i as VizFloat) / (last as VizFloat);
+                self.low + ((self.high - self.low) * t)
+            })
+            .collect()
+    }
+}
+
 impl SavitzkyGolayConfig {
     pub fn into_mapper(self, size: usize) -> SavitzkyGolayMapper {
         SavitzkyGolayMapper::new(size, self)
     }
 
+    /// The same config with an odd window size of about `fraction` of `points` (at least 3, and
+    /// smaller than `points` when possible). The degree is lowered to fit inside the window.
+    ///
+    /// Fewer than 3 points don't fit any window, the config is returned unchanged.
+    pub fn with_auto_window(self, points: usize, fraction: VizFloat) -> Self {
+        if points < 3 {
+            return self;
+        }
+
+        let largest = std::cmp::max(3, points.saturating_sub(1)) as u64;
+        let largest = if largest % 2 == 0 {
+            largest - 1
+        } else {
+            largest
+        };
+        let window_size = ((points as VizFloat) * fraction).round() as u64;
+        let window_size = (window_size | 1).clamp(3, largest);
+        Self {
+            window_size,
+            degree: std::cmp::min(self.degree, window_size - 1),
+            ..self
+        }
+    }
+
+    /// The same config with half the window (odd, at least 3) for fast quality, the degree and
+    /// order are lowered to fit inside the smaller window
+    pub fn reduced(self) -> Self {
+        let window_size = ((self.window_size / 2) | 1).max(3);
+        let degree = std::cmp::min(self.degree, window_size - 1);
+        Self {
+            window_size,
+            degree,
+            order: std::cmp::min(self.order, degree),
+        }
+    }
+
+    /// like `into_mapper`, blending the smoothed output with the input per point
+    pub fn into_blended_mapper(
+        self,
+        size: usize,
+        blend: Option<SmoothingBlend>,
+    ) -> SavitzkyGolayMapper {
+        let mut mapper = self.into_mapper(size);
+        mapper.blend = blend.map(move |blend| blend.factors(size));
+        mapper
+    }
+
+    /// `compute_coefficients` with every (numerator, denominator) divided out, one row per offset
+    /// of the smoothed point from the middle of the window (every row sums to 1)
+    pub fn coefficients_as_f64(&self) -> Vec<Vec<f64>> {
+        self.compute_coefficients()
+            .into_iter()
+            .map(move |row| {
+                row.into_iter()
+                    .map(move |(numer, denom)| numer / denom)
+                    .collect()
+            })
+            .collect()
+    }
+
     pub fn compute_coefficients(&self) -> Vec<Vec<(VizFloat, VizFloat)>> {
         if self.window_size % 2 == 0 || self.window_size < 3 {
             panic!("invalid window size {}", self.window_size)
@@ -173,19 +255,24 @@ impl SavitzkyGolayConfig {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SavitzkyGolayMapper {
-    buf: Vec<Channeled<VizFloat>>,
+    // one plane per channel, so the convolution runs over plain float slices
+    planes: Vec<Vec<VizFloat>>,
     cap: usize,
-    coefficients: Vec<Vec<(VizFloat, VizFloat)>>,
+    // computed as rationals for precision, but divided out once so map only multiplies floats
+    coefficients: Vec<Vec<VizFloat>>,
+    // per point blend factors between the input and the smoothed output
+    blend: Option<Vec<VizFloat>>,
 }
 
 impl SavitzkyGolayMapper {
     fn new(size: usize, config: SavitzkyGolayConfig) -> Self {
         Self {
-            buf: Vec::with_capacity(size),
+            planes: vec![Vec::with_capacity(size), Vec::with_capacity(size)],
             cap: size,
-            coefficients: config.compute_coefficients(),
+            coefficients: config.coefficients_as_f64(),
+            blend: None,
         }
     }
 }
@@ -198,8 +285,21 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for SavitzkyGolayMap
         let coefficients = self.coefficients.as_slice();
         let half_size = coefficients.len() / 2;
 
-        self.buf.clear();
-        self.buf.extend_from_slice(input);
+        let cap = self.cap;
+        let num_channels = input.first().map_or(1, move |v| v.num_channels());
+        let planes = &mut self.planes;
+        planes.resize_with(num_channels, move || Vec::with_capacity(cap));
+        planes.iter_mut().for_each(move |plane| plane.clear());
+        for v in input.iter() {
+            if v.num_channels() != num_channels {
+                return Err(anyhow!("mixed mono and stereo data in one frame"));
+            }
+
+            for (plane, v) in planes.iter_mut().zip(v.channels()) {
+                plane.push(*v);
+            }
+        }
+        let planes = planes.as_slice();
 
         // convolution!
         // slides a window of fixed size along the input data
@@ -207,37 +307,60 @@ impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for SavitzkyGolayMap
         // each window has a point of interest which is at some offset from center of window
         // input data for each window is window.start..window.end and point of interest is at
         // window.start + window.offset + half_size
+        let blend = self.blend.as_deref();
         SlidingWindow::new(coefficients.len(), input.len())
-            .map(|win| {
-                (
-                    &self.buf[win.start..win.end],
-                    &coefficients[(win.offset + (half_size as isize)) as usize],
-                )
-            })
-            .zip(input.iter_mut())
-            .for_each(move |((data, coefficients), v)| {
-                *v = data
-                    .iter()
-                    .zip(coefficients.iter())
-                    .map(move |(v, cf)| v.map(move |v| multiply_rational_float(cf, v)))
-                    .fold1(move |sum, next| {
-                        sum.zip(next)
-                            .expect("mixed mono/stereo?")
-                            .map(move |(s, n)| s + n)
-                    })
-                    .expect("empty data?")
+            .zip(input.iter_mut().enumerate())
+            .for_each(move |(win, (i, v))| {
+                let row = coefficients[(win.offset + (half_size as isize)) as usize].as_slice();
+                // the planes still hold the unsmoothed input
+                let f = blend.and_then(move |blend| blend.get(i).copied());
+                let smooth = move |plane: &[VizFloat]| {
+                    let smoothed = dot(&plane[win.start..win.end], row);
+                    match f {
+                        Some(f) => plane[i] + (f * (smoothed - plane[i])),
+                        None => smoothed,
+                    }
+                };
+                match v {
+                    Channeled::Mono(v) => *v = smooth(&planes[0]),
+                    Channeled::Stereo(l, r) => {
+                        *l = smooth(&planes[0]);
+                        *r = smooth(&planes[1]);
+                    }
+                    Channeled::Multi(vs) => vs
+                        .iter_mut()
+                        .zip(planes.iter())
+                        .for_each(move |(v, plane)| *v = smooth(plane)),
+                }
             });
 
         Ok(Some(input))
     }
 }
 
-fn multiply_rational_float((numer, denom): &(VizFloat, VizFloat), float: VizFloat) -> VizFloat {
-    (float * *numer) / *denom
+// straight indexed loop with independent partial sums, so the compiler can vectorize it
+fn dot(data: &[VizFloat], coefficients: &[VizFloat]) -> VizFloat {
+    let n = std::cmp::min(data.len(), coefficients.len());
+    let (data, coefficients) = (&data[..n], &coefficients[..n]);
+    let mut sums = [0.0; 4];
+    let chunks = n / 4;
+    for i in 0..chunks {
+        for j in 0..4 {
+            sums[j] += data[(i * 4) + j] * coefficients[(i * 4) + j];
+        }
+    }
+
+    let mut sum = (sums[0] + sums[1]) + (sums[2] + sums[3]);
+    for i in (chunks * 4)..n {
+        sum += data[i] * coefficients[i];
+    }
+
+    sum
 }
 
+/// The window of input points used to smooth each of `size` points, see the module docs
 #[derive(Clone, Copy, PartialEq, Debug)]
-struct SlidingWindow {
+pub(crate) struct SlidingWindow {
     // configurable inputs
     window: usize,
     size: usize,
@@ -249,15 +372,17 @@ struct SlidingWindow {
     at: usize,
 }
 
+/// Input points `start..end` are convolved with coefficient row `offset` (relative to the middle
+/// row) to smooth a point
 #[derive(Clone, Copy, PartialEq, Debug)]
-struct WindowPointer {
-    start: usize,
-    end: usize,
-    offset: isize,
+pub(crate) struct WindowPointer {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) offset: isize,
 }
 
 impl SlidingWindow {
-    fn new(window: usize, size: usize) -> Self {
+    pub(crate) fn new(window: usize, size: usize) -> Self {
         Self {
             window,
             size,
@@ -300,7 +425,8 @@ impl Iterator for SlidingWindow {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.size, Some(self.size))
+        let remain = self.size - self.at;
+        (remain, Some(remain))
     }
 }
 
@@ -309,3 +435,257 @@ unsafe impl TrustedLen for SlidingWindow {}
 impl ExactSizeIterator for SlidingWindow {}
 
 impl FusedIterator for SlidingWindow {}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+    use crate::savitzky_golay::{
+        SavitzkyGolayConfig, SlidingWindow, SmoothingBlend, WindowPointer,
+    };
+    use crate::util::timed;
+
+    fn stereo_input(n: usize) -> Vec<Channeled<f64>> {
+        (0..n)
+            .map(|i| Channeled::Stereo(((i as f64) * 0.7).sin(), ((i * i) % 5) as f64))
+            .collect()
+    }
+
+    // the convolution as it was before the per-channel planes, one Channeled value at a time
+    fn reference(coefficients: &[Vec<f64>], input: &[Channeled<f64>]) -> Vec<Channeled<f64>> {
+        let half_size = coefficients.len() / 2;
+        SlidingWindow::new(coefficients.len(), input.len())
+            .map(|win| {
+                let row = &coefficients[(win.offset + (half_size as isize)) as usize];
+                input[win.start..win.end]
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(v, cf)| v.as_ref().map(move |v| v * cf))
+                    .fold(None, |sum: Option<Channeled<f64>>, next| match sum {
+                        Some(sum) => Some(sum.zip(next).unwrap().map(|(s, n)| s + n)),
+                        None => Some(next),
+                    })
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn assert_close(a: &[Channeled<f64>], b: &[Channeled<f64>]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            let close = a
+                .as_ref()
+                .zip(b.as_ref())
+                .expect("same channels")
+                .map(|(a, b)| (a - b).abs() < 1e-9)
+                .and();
+            assert!(close, "{:?} vs {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn stereo_planes_match_reference() {
+        let config = SavitzkyGolayConfig {
+            window_size: 9,
+            degree: 4,
+            order: 0,
+        };
+        let input = stereo_input(64);
+        let mut frame = input.clone();
+        let mut mapper = config.into_mapper(input.len());
+        let out = mapper.map(&mut frame).expect("ok").expect("some").to_vec();
+        assert_close(&out, &reference(&config.coefficients_as_f64(), &input));
+    }
+
+    #[test]
+    fn blend_factors_match_endpoints() {
+        let blend = SmoothingBlend {
+            low: 0.2,
+            high: 0.9,
+        };
+        let factors = blend.factors(49);
+        assert_eq!(factors.len(), 49);
+        assert_eq!(factors[0], 0.2);
+        assert!((factors[48] - 0.9).abs() < 1e-12);
+        assert!((factors[24] - 0.55).abs() < 1e-12);
+
+        // no blending at the low end and full smoothing at the high end
+        let config = SavitzkyGolayConfig {
+            window_size: 5,
+            degree: 2,
+            order: 0,
+        };
+        let input = stereo_input(16);
+        let blend = SmoothingBlend {
+            low: 0.0,
+            high: 1.0,
+        };
+        let mut frame = input.clone();
+        let mut mapper = config.into_blended_mapper(input.len(), Some(blend));
+        let out = mapper.map(&mut frame).expect("ok").expect("some").to_vec();
+        let smoothed = reference(&config.coefficients_as_f64(), &input);
+        assert_close(&out[..1], &input[..1]);
+        assert_close(&out[15..], &smoothed[15..]);
+    }
+
+    // cargo test --release -- --ignored sg_convolution_timing --nocapture
+    #[test]
+    #[ignore]
+    fn sg_convolution_timing() {
+        let config = SavitzkyGolayConfig {
+            window_size: 49,
+            degree: 9,
+            order: 0,
+        };
+        let input = stereo_input(2048);
+        let coefficients = config.coefficients_as_f64();
+        let mut mapper = config.into_mapper(input.len());
+        let mut frame = input.clone();
+        let (planes, _) = timed(|| {
+            for _ in 0..100 {
+                frame.clone_from_slice(&input);
+                mapper.map(&mut frame).expect("ok");
+            }
+        });
+        let (channeled, _) = timed(|| {
+            for _ in 0..100 {
+                reference(&coefficients, &input);
+            }
+        });
+        println!("100 frames: planes {:?}, channeled {:?}", planes, channeled);
+    }
+
+    #[test]
+    fn float_coefficients_match_rationals() {
+        let config = SavitzkyGolayConfig {
+            window_size: 7,
+            degree: 3,
+            order: 0,
+        };
+        let input = (0..32)
+            .map(|i| Channeled::Mono(((i as f64) * 0.7).sin() + ((i * i) % 5) as f64))
+            .collect::<Vec<_>>();
+
+        // the same convolution, dividing by the rational denominator on every multiply
+        let rationals = config.compute_coefficients();
+        let half_size = rationals.len() / 2;
+        let expected = SlidingWindow::new(rationals.len(), input.len())
+            .map(|win| {
+                let row = &rationals[(win.offset + (half_size as isize)) as usize];
+                input[win.start..win.end]
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(v, (numer, denom))| match v {
+                        Channeled::Mono(v) => (v * numer) / denom,
+                        _ => unreachable!(),
+                    })
+                    .sum::<f64>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut mapper = config.into_mapper(input.len());
+        let mut frame = input.clone();
+        let out = mapper.map(&mut frame).expect("ok").expect("some");
+        for (out, expected) in out.iter().zip(expected.iter()) {
+            match out {
+                Channeled::Mono(v) => assert!((v - expected).abs() < 1e-9, "{} vs {}", v, expected),
+                _ => panic!("should stay mono"),
+            }
+        }
+    }
+
+    #[test]
+    fn auto_window_is_odd_fraction_of_points() {
+        let config = SavitzkyGolayConfig {
+            window_size: 49,
+            degree: 9,
+            order: 0,
+        };
+        let auto = config.with_auto_window(32, 0.1);
+        assert_eq!(auto.window_size, 3);
+        assert_eq!(auto.degree, 2);
+
+        assert_eq!(config.with_auto_window(49, 0.1).window_size, 5);
+        assert_eq!(config.with_auto_window(200, 0.1).window_size, 21);
+        assert_eq!(config.with_auto_window(200, 0.1).degree, 9);
+        // never the whole frame, never below 3
+        assert_eq!(config.with_auto_window(10, 1.0).window_size, 9);
+        assert_eq!(config.with_auto_window(9, 1.0).window_size, 7);
+        assert_eq!(config.with_auto_window(4, 0.1).window_size, 3);
+        assert_eq!(config.with_auto_window(2, 0.1), config);
+        assert_eq!(config.with_auto_window(0, 0.1), config);
+
+        // half the window, still odd and with room for the degree
+        let reduced = config.with_auto_window(200, 0.1).reduced();
+        assert_eq!(reduced.window_size, 11);
+        assert!(reduced.degree < reduced.window_size);
+        assert_eq!(config.with_auto_window(32, 0.1).reduced().window_size, 3);
+    }
+
+    #[test]
+    fn sliding_window_has_exact_len() {
+        let mut windows = SlidingWindow::new(5, 12);
+        assert_eq!(windows.size_hint(), (12, Some(12)));
+        assert_eq!(windows.len(), 12);
+
+        windows.next().expect("has a window");
+        assert_eq!(windows.size_hint(), (11, Some(11)));
+        assert_eq!(windows.by_ref().count(), 11);
+        assert_eq!(windows.size_hint(), (0, Some(0)));
+        assert_eq!(windows.next(), None);
+    }
+
+    #[test]
+    fn sliding_window_pointers_stay_in_bounds() {
+        let windows = SlidingWindow::new(5, 12).collect::<Vec<_>>();
+        assert_eq!(windows.len(), 12);
+        assert_eq!(
+            windows[0],
+            WindowPointer {
+                start: 0,
+                end: 5,
+                offset: -2
+            }
+        );
+        assert_eq!(
+            windows[6],
+            WindowPointer {
+                start: 4,
+                end: 9,
+                offset: 0
+            }
+        );
+        assert_eq!(
+            windows[11],
+            WindowPointer {
+                start: 7,
+                end: 12,
+                offset: 2
+            }
+        );
+
+        // every point is inside its window, at the offset from the middle
+        for (i, win) in windows.iter().enumerate() {
+            assert_eq!(win.end - win.start, 5);
+            assert_eq!((win.start as isize) + 2 + win.offset, i as isize);
+        }
+    }
+
+    #[test]
+    fn coefficient_rows_sum_to_one() {
+        for (window_size, degree) in &[(5, 2), (9, 4), (21, 6)] {
+            let config = SavitzkyGolayConfig {
+                window_size: *window_size,
+                degree: *degree,
+                order: 0,
+            };
+            let rows = config.coefficients_as_f64();
+            assert_eq!(rows.len(), *window_size as usize);
+            for row in rows {
+                assert_eq!(row.len(), *window_size as usize);
+                let sum: f64 = row.iter().sum();
+                assert!((sum - 1.0).abs() < 1e-9, "{:?} row sums to {}", config, sum);
+            }
+        }
+    }
+}
@@ -2,14 +2,25 @@ use crate::delegate_impls;
 use crate::framed::{Framed, Samples};
 use crate::util::try_use_iter;
 use anyhow::Result;
+use num_rational::Rational64;
 use serde::export::PhantomData;
 
+/// Maps a frame index to the number of samples between that frame and the next one
+pub type StrideSchedule = Box<dyn Fn(usize) -> usize + Send>;
+
 pub struct SlidingFrame<S, T, I> {
     source: S,
     buf: Vec<T>,
     cur_buf: Vec<T>,
     size: usize,
+    // stride after the frame at frame_idx
     stride: usize,
+    schedule: Option<StrideSchedule>,
+    // exact (average) stride when it isn't a whole number of samples, frame i starts at
+    // floor(i * stride)
+    fractional_stride: Option<Rational64>,
+    // index of the frame in buf, or of the next frame if buf is empty
+    frame_idx: usize,
     _inner_typ: PhantomData<I>,
 }
 
@@ -17,43 +28,96 @@ impl<S, T, I> SlidingFrame<S, T, I>
 where
     S: Samples<T, I>,
 {
-    pub fn new(source: S, size: usize, mut stride: usize) -> Self {
-        if stride == 0 {
-            stride = 1;
-        }
+    #[cfg(test)]
+    pub fn new(source: S, size: usize, stride: usize) -> Self {
+        Self::create(source, size, std::cmp::max(stride, 1), None)
+    }
+
+    /// Like `new`, but the stride after frame `i` is `schedule(i)` instead of a fixed value.
+    ///
+    /// The frame counts (`num_frames` etc.) and `seek_frame` assume the stride of the current
+    /// frame holds for the rest of the source, so they are estimates when the schedule varies.
+    pub fn with_schedule<F>(source: S, size: usize, schedule: F) -> Self
+    where
+        F: Fn(usize) -> usize + Send + 'static,
+    {
+        let stride = std::cmp::max(schedule(0), 1);
+        Self::create(source, size, stride, Some(Box::new(schedule)))
+    }
+
+    /// Like `new`, for strides which aren't a whole number of samples (e.g. 48000Hz at 144fps).
+    ///
+    /// The stride alternates between its floor and ceil (like Bresenham's line algorithm), so frame
+    /// `i` always starts at `floor(i * stride)` and the frames never drift from the audio.
+    pub fn with_fractional_stride(source: S, size: usize, stride: Rational64) -> Self {
+        let stride = std::cmp::max(stride, Rational64::from(1));
+        let mut out = Self::with_schedule(source, size, move |idx| {
+            frame_start(stride, idx + 1) - frame_start(stride, idx)
+        });
+        out.fractional_stride = Some(stride);
+        out
+    }
+
+    fn create(source: S, size: usize, stride: usize, schedule: Option<StrideSchedule>) -> Self {
         Self {
             source,
             buf: Vec::with_capacity(size),
             cur_buf: Vec::with_capacity(size),
             size,
             stride,
+            schedule,
+            fractional_stride: None,
+            frame_idx: 0,
             _inner_typ: PhantomData,
         }
     }
+
+    fn set_frame_idx(&mut self, idx: usize) {
+        self.frame_idx = idx;
+        if let Some(schedule) = &self.schedule {
+            self.stride = std::cmp::max(schedule(idx), 1);
+        }
+    }
 }
 
 impl<S, T, I> Framed<T, I> for SlidingFrame<S, T, I>
 where
     S: Samples<T, I>,
-    T: Copy,
+    T: Clone,
 {
     fn into_deep_inner(self) -> I {
         self.source.into_deep_inner()
     }
 
     fn seek_frame(&mut self, n: isize) -> Result<()> {
-        if n < 0 {
-            let buf_len = self.buf.len();
-            let to_remove = std::cmp::min(buf_len, -n as usize);
-            let first_remove = buf_len - to_remove;
-            self.buf.drain(first_remove..buf_len);
-        } else if n > 0 {
-            let buf_len = self.buf.len();
-            let to_remove = std::cmp::min(buf_len, n as usize);
-            self.buf.drain(0..to_remove);
-        }
+        // without seeking, the next frame starts one stride after the frame in buf (or at the
+        // current position if there is no frame yet), so skip n strides from there
+        let stride = self.stride as isize;
+        let source_at = (self.source.num_samples() - self.source.num_samples_remain()) as isize;
+        let next_start = if self.buf.is_empty() {
+            source_at
+        } else {
+            source_at - (self.buf.len() as isize) + stride
+        };
+        let seek_to = std::cmp::max(0, next_start + (n * stride));
+        let next_idx = if self.buf.is_empty() {
+            self.frame_idx
+        } else {
+            self.frame_idx + 1
+        } as isize;
+        let (seek_to, next_idx) = match self.fractional_stride {
+            // frame starts are known exactly, no need to count strides
+            Some(stride) => {
+                let next_idx = std::cmp::max(0, next_idx + n) as usize;
+                (frame_start(stride, next_idx) as isize, next_idx)
+            }
+            None if seek_to == 0 => (0, 0),
+            None => (seek_to, std::cmp::max(0, next_idx + n) as usize),
+        };
 
-        self.source.seek_samples(n)?;
+        self.buf.clear();
+        self.source.seek_samples(seek_to - source_at)?;
+        self.set_frame_idx(next_idx);
         Ok(())
     }
 
@@ -65,6 +129,7 @@ where
             }
 
             self.buf.drain(0..self.stride);
+            self.set_frame_idx(self.frame_idx + 1);
         }
 
         self.ensure_buf_filled()?;
@@ -79,11 +144,23 @@ where
     }
 
     fn num_frames(&self) -> usize {
-        self.source.num_samples() / self.stride
+        // same count as num_frames_remain from the start of the source
+        self.source.num_samples().div_ceil(self.stride)
     }
 
     fn num_frames_remain(&self) -> usize {
-        self.source.num_samples_remain() / self.stride
+        // samples from the start of the next frame on, the frame in buf has been returned already
+        let remain = self.source.num_samples_remain();
+        let available = if self.buf.is_empty() {
+            remain
+        } else if self.buf.len() < self.stride {
+            return 0;
+        } else {
+            self.buf.len() - self.stride + remain
+        };
+
+        // every stride with at least one sample in it starts a frame (the last ones may be short)
+        available.div_ceil(self.stride)
     }
 
     fn num_full_frames(&self) -> usize {
@@ -103,10 +180,15 @@ where
 
 delegate_impls!(SlidingFrame<S, T, I>, S, source);
 
+/// The first sample of frame `idx` when frames start every `stride` samples
+pub(crate) fn frame_start(stride: Rational64, idx: usize) -> usize {
+    (stride * Rational64::from(idx as i64)).floor().to_integer() as usize
+}
+
 impl<S, T, I> SlidingFrame<S, T, I>
 where
     S: Samples<T, I>,
-    T: Copy,
+    T: Clone,
 {
     fn ensure_buf_filled(&mut self) -> Result<()> {
         let source = &mut self.source;
@@ -127,3 +209,94 @@ where
         )
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::framed::Framed;
+    use crate::sliding::SlidingFrame;
+    use crate::vec_samples::VecSamples;
+    use num_rational::Rational64;
+
+    fn first(frames: &mut SlidingFrame<VecSamples<usize>, usize, VecSamples<usize>>) -> usize {
+        frames
+            .next_frame()
+            .expect("should read")
+            .expect("should have a frame")[0]
+    }
+
+    #[test]
+    fn seek_frame_moves_by_strides() {
+        let mut frames = SlidingFrame::new(VecSamples::new((0..100).collect(), 1), 10, 5);
+        assert_eq!(first(&mut frames), 0);
+
+        // skip two frames forward
+        frames.seek_frame(2).expect("should seek");
+        assert_eq!(first(&mut frames), 15);
+
+        // and three back from the frame after that
+        frames.seek_frame(-3).expect("should seek");
+        assert_eq!(first(&mut frames), 5);
+        assert_eq!(first(&mut frames), 10);
+
+        // seeking before the start stops at the first frame
+        frames.seek_frame(-100).expect("should seek");
+        assert_eq!(first(&mut frames), 0);
+    }
+
+    #[test]
+    fn num_frames_remain_counts_down_per_frame() {
+        let mut frames = SlidingFrame::new(VecSamples::new((0..103).collect(), 1), 10, 5);
+        let mut remain = frames.num_frames_remain();
+        assert_eq!(remain, 21);
+        assert_eq!(frames.num_frames(), 21);
+        while frames.next_frame().expect("should read").is_some() {
+            assert_eq!(frames.num_frames_remain(), remain - 1);
+            remain -= 1;
+        }
+        assert_eq!(remain, 0);
+
+        frames.seek_frame(-4).expect("should seek");
+        assert_eq!(frames.num_frames_remain(), 4);
+    }
+
+    #[test]
+    fn schedule_doubles_stride() {
+        // stride 5 for the first three frames, 10 after that
+        let schedule = |idx: usize| if idx < 3 { 5 } else { 10 };
+        let mut frames =
+            SlidingFrame::with_schedule(VecSamples::new((0..100).collect(), 1), 10, schedule);
+        let starts = (0..7).map(|_| first(&mut frames)).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0, 5, 10, 15, 25, 35, 45]);
+
+        // seeking back to the start restarts the schedule
+        frames.seek_frame(-100).expect("should seek");
+        assert_eq!(first(&mut frames), 0);
+        assert_eq!(first(&mut frames), 5);
+    }
+
+    #[test]
+    fn fractional_stride_does_not_drift() {
+        // 48000Hz at 144fps is 333.33... samples per frame
+        let stride = Rational64::new(48000, 144);
+        let mut frames = SlidingFrame::with_fractional_stride(
+            VecSamples::new((0..200_000).collect(), 48000),
+            1024,
+            stride,
+        );
+        let starts = (0..300).map(|_| first(&mut frames)).collect::<Vec<_>>();
+        for (i, start) in starts.iter().enumerate() {
+            assert_eq!(*start, ((i as i64) * 1000 / 3) as usize);
+        }
+        assert!(starts
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] == 333 || pair[1] - pair[0] == 334));
+
+        // the average stride is exact (within rounding of the last start)
+        let average = (starts[299] as f64) / 299.0;
+        assert!((average - (1000.0 / 3.0)).abs() < 1.0 / 299.0);
+
+        // seeking lands on exact frame starts too
+        frames.seek_frame(-200).expect("should seek");
+        assert_eq!(first(&mut frames), 100 * 1000 / 3);
+    }
+}
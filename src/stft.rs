@@ -0,0 +1,123 @@
+use crate::channeled::Channeled;
+use crate::fft::FramedFft;
+use crate::framed::Framed;
+use crate::sliding::SlidingFrame;
+use crate::util::{fft_bin_hz, VizFloat};
+use crate::vec_samples::VecSamples;
+use crate::window::{BlackmanNuttall, Hann, MemoizedWindowingMapper, WindowingFunction};
+use anyhow::{anyhow, Result};
+use num_rational::Rational64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowKind {
+    Rectangular,
+    Hann,
+    BlackmanNuttall,
+}
+
+impl WindowKind {
+    fn mapper(self, size: usize) -> Option<MemoizedWindowingMapper> {
+        use WindowKind::*;
+        match self {
+            Rectangular => None,
+            Hann => Some(self::Hann::mapper(size)),
+            BlackmanNuttall => Some(self::BlackmanNuttall::mapper(size)),
+        }
+    }
+}
+
+/// Short-time Fourier transform of mono samples, built from the same stages as the visualizer
+/// pipeline (`SlidingFrame` -> window -> `FramedFft`) without any of the viz config.
+///
+/// Every frame has `window_size / 2` magnitudes, the DC bin is skipped (see `bin_frequency`).
+pub struct Stft {
+    sample_rate: usize,
+    window_size: usize,
+    hop: usize,
+    window_kind: WindowKind,
+}
+
+impl Stft {
+    pub fn new(
+        sample_rate: usize,
+        window_size: usize,
+        hop: usize,
+        window_kind: WindowKind,
+    ) -> Result<Self> {
+        if sample_rate == 0 {
+            return Err(anyhow!("sample rate must be > 0"));
+        }
+
+        if window_size < 2 {
+            return Err(anyhow!("window size must be >= 2, got {}", window_size));
+        }
+
+        if hop == 0 {
+            return Err(anyhow!("hop must be > 0"));
+        }
+
+        Ok(Self {
+            sample_rate,
+            window_size,
+            hop,
+            window_kind,
+        })
+    }
+
+    /// center frequency (Hz) of the magnitude at `idx` in an output frame
+    pub fn bin_frequency(&self, idx: usize) -> VizFloat {
+        fft_bin_hz(idx + 1, self.window_size, self.sample_rate)
+    }
+
+    /// magnitude frames of `samples`, one every `hop` samples
+    pub fn process(&self, samples: &[VizFloat]) -> Result<Vec<Vec<VizFloat>>> {
+        let source = VecSamples::new(
+            samples.iter().map(|v| Channeled::Mono(*v)).collect(),
+            self.sample_rate,
+        );
+        let window_kind = self.window_kind;
+        // a whole number of samples, so every frame starts exactly `hop` after the one before
+        let hop = Rational64::from(self.hop as i64);
+        let mut frames = SlidingFrame::with_fractional_stride(source, self.window_size, hop)
+            .lift(move |size| window_kind.mapper(size))
+            .try_lift(FramedFft::new)?;
+
+        let mut out = Vec::new();
+        while let Some(frame) = frames.next_frame()? {
+            out.push(frame.iter().map(move |v| v.to_mono()).collect());
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::stft::{Stft, WindowKind};
+    use crate::util::VizFloat;
+
+    #[test]
+    fn stft_of_sine_peaks_in_its_bin() {
+        const SAMPLE_RATE: usize = 8000;
+        const FREQ: VizFloat = 1000.0;
+
+        let samples = (0..4000)
+            .map(|i| {
+                let t = (i as VizFloat) / (SAMPLE_RATE as VizFloat);
+                (t * FREQ * std::f64::consts::PI * 2.0).sin()
+            })
+            .collect::<Vec<_>>();
+        let stft = Stft::new(SAMPLE_RATE, 256, 128, WindowKind::Hann).expect("valid stft");
+        let frames = stft.process(&samples).expect("should transform");
+
+        assert!(frames.len() > 10);
+        for frame in frames.iter().filter(|frame| frame.len() == 128) {
+            let (peak_idx, _) = frame
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).expect("no NaN"))
+                .expect("frame is not empty");
+            assert_eq!(stft.bin_frequency(peak_idx), FREQ);
+        }
+    }
+}
@@ -1,13 +1,17 @@
 use crate::delegate_impls;
 use crate::framed::Framed;
-use crate::util::timed;
+use crate::quality::QualityGovernor;
+use crate::util::{timed, VizFloat};
 use anyhow::Result;
 use std::marker::PhantomData;
+use std::time::Instant;
 
 pub struct FramedTimed<S, T, I> {
     source: S,
     every_nth: usize,
     counter: usize,
+    throughput: Throughput,
+    governor: Option<QualityGovernor>,
 
     _in_typ: PhantomData<T>,
     _inner_typ: PhantomData<I>,
@@ -22,10 +26,62 @@ where
             source,
             every_nth,
             counter: 0,
+            throughput: Throughput::default(),
+            governor: None,
             _in_typ: PhantomData,
             _inner_typ: PhantomData,
         }
     }
+
+    /// frames per second of wall time since the first frame after creation or the last reset
+    /// (seeking resets too), `None` until a frame is done
+    // for benchmarking when embedding, the binary prints it with the frame times instead
+    #[allow(dead_code)]
+    pub fn frames_per_second(&self) -> Option<VizFloat> {
+        self.throughput.frames_per_second(Instant::now())
+    }
+
+    pub fn reset_throughput(&mut self) {
+        self.throughput.reset()
+    }
+
+    /// feeds every frame time to the governor (adaptive quality)
+    pub fn with_governor(self, governor: Option<QualityGovernor>) -> Self {
+        Self { governor, ..self }
+    }
+}
+
+/// Counts frames and the wall time since the first of them started (since the start or the last
+/// reset), so time spent between frames (e.g. drawing them) counts too
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Throughput {
+    frames: usize,
+    since: Option<Instant>,
+}
+
+impl Throughput {
+    /// a frame started at `now`, only the first one after a reset starts the clock
+    pub fn start(&mut self, now: Instant) {
+        self.since.get_or_insert(now);
+    }
+
+    pub fn record(&mut self) {
+        self.frames += 1;
+    }
+
+    /// `None` until a frame is recorded
+    pub fn frames_per_second(&self, now: Instant) -> Option<VizFloat> {
+        let secs = now.saturating_duration_since(self.since?).as_secs_f64();
+        if self.frames > 0 && secs > 0.0 {
+            Some((self.frames as VizFloat) / secs)
+        } else {
+            None
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
 }
 
 impl<S, T, I> Framed<T, I> for FramedTimed<S, T, I>
@@ -37,21 +93,26 @@ where
     }
 
     fn seek_frame(&mut self, n: isize) -> Result<()> {
+        self.reset_throughput();
         self.source.seek_frame(n)
     }
 
     fn next_frame(&mut self) -> Result<Option<&mut [T]>> {
+        self.throughput.start(Instant::now());
         let source = &mut self.source;
-        let result = if self.counter % self.every_nth == 0 {
-            let (dur, out) = timed(move || source.next_frame());
-            if let Ok(Some(_)) = &out {
-                println!("frame computed in {:?}", dur);
+        let (dur, result) = timed(move || source.next_frame());
+        if let Ok(Some(_)) = &result {
+            self.throughput.record();
+            if let Some(governor) = self.governor.as_mut() {
+                governor.record(dur);
             }
-
-            out
-        } else {
-            source.next_frame()
-        };
+            if self.counter % self.every_nth == 0 {
+                match self.throughput.frames_per_second(Instant::now()) {
+                    Some(fps) => println!("frame computed in {:?} ({:.1} frames/s)", dur, fps),
+                    None => println!("frame computed in {:?}", dur),
+                }
+            }
+        }
 
         self.counter += 1;
         result
@@ -75,3 +136,50 @@ where
 }
 
 delegate_impls!(FramedTimed<S, T, I>, S, source);
+
+#[cfg(test)]
+pub mod tests {
+    use crate::framed::Framed;
+    use crate::sliding::SlidingFrame;
+    use crate::timer::{FramedTimed, Throughput};
+    use crate::vec_samples::VecSamples;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn throughput_frames_per_second() {
+        let start = Instant::now();
+        let mut throughput = Throughput::default();
+        assert_eq!(throughput.frames_per_second(start), None);
+
+        // 50 frames in 250ms, only the first start counts
+        for i in 0..50 {
+            throughput.start(start + Duration::from_millis(5 * i));
+            throughput.record();
+        }
+        let end = start + Duration::from_millis(250);
+        assert_eq!(throughput.frames_per_second(end), Some(200.0));
+
+        throughput.reset();
+        assert_eq!(throughput.frames_per_second(end), None);
+    }
+
+    #[test]
+    fn timed_frames_per_second_counts_wall_time() {
+        let source = SlidingFrame::new(VecSamples::new((0..100).collect::<Vec<i64>>(), 1), 10, 10);
+        let mut timed = FramedTimed::new(source, 1000);
+        assert_eq!(timed.frames_per_second(), None);
+
+        // the frames themselves are instant, the time between them isn't
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert!(timed.next_frame().expect("ok").is_some());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let fps = timed.frames_per_second().expect("frames were timed");
+        assert!(fps >= 10.0 / start.elapsed().as_secs_f64());
+        assert!(fps <= 10.0 / 0.05);
+
+        timed.seek_frame(-10).expect("should seek");
+        assert_eq!(timed.frames_per_second(), None);
+    }
+}
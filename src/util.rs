@@ -1,4 +1,5 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::io::Write;
 use std::iter::FusedIterator;
 use std::ops::Sub;
 use std::time::{Duration, Instant};
@@ -7,28 +8,6 @@ pub type VizFloat = f64;
 pub type VizComplex = fftw::types::c64;
 pub type VizFftPlan = fftw::plan::R2CPlan64;
 
-pub fn slice_copy_from<T, I>(slice: &mut [T], mut iter: I) -> &mut [T]
-where
-    I: Iterator<Item = T>,
-{
-    let n = slice.len();
-    let mut idx = 0;
-
-    loop {
-        if idx >= n {
-            return &mut slice[..idx];
-        }
-
-        if let Some(v) = iter.next() {
-            slice[idx] = v;
-        } else {
-            return &mut slice[..idx];
-        }
-
-        idx += 1;
-    }
-}
-
 pub fn try_use_iter<I, T, F>(source: I, mut consumer: F) -> Result<()>
 where
     I: Iterator<Item = Result<T>>,
@@ -75,6 +54,47 @@ where
     }
 }
 
+/// Center frequency (Hz) of `bin` of an FFT over `fft_size` samples. Bins count from DC: bin 0 is
+/// DC (0Hz) and bin `fft_size / 2` is Nyquist.
+///
+/// The FFT mappers skip DC, so the magnitude at index `i` of their output is bin `i + 1`.
+pub fn fft_bin_hz(bin: usize, fft_size: usize, sample_rate: usize) -> VizFloat {
+    ((bin as VizFloat) * (sample_rate as VizFloat)) / (fft_size as VizFloat)
+}
+
+/// Converts a linear magnitude to decibels (`20 * log10(mag)`), never going below `floor_db`.
+///
+/// Zero, negative, and NaN magnitudes map to `floor_db` instead of `-inf`/NaN.
+pub fn magnitude_to_db(mag: VizFloat, floor_db: VizFloat) -> VizFloat {
+    if mag > 0.0 {
+        let db = 20.0 * mag.log10();
+        if db > floor_db {
+            db
+        } else {
+            floor_db
+        }
+    } else {
+        floor_db
+    }
+}
+
+/// Writes the values of a frame as one line of comma separated values
+pub fn write_csv_row<W>(out: &mut W, frame: &[VizFloat]) -> Result<()>
+where
+    W: Write,
+{
+    for (idx, v) in frame.iter().enumerate() {
+        if idx != 0 {
+            out.write_all(b",")?;
+        }
+
+        write!(out, "{}", v)?;
+    }
+
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
 pub fn log_timed<F, R>(name: String, f: F) -> R
 where
     F: FnOnce() -> R,
@@ -95,4 +115,103 @@ where
     (time_taken, result)
 }
 
+/// Parses a timestamp given as seconds (`90`, `90.5`) or as minutes and seconds (`1:30`,
+/// `1:30.5`, the seconds below 60)
+pub fn parse_timestamp(timestamp: &str) -> Result<Duration> {
+    let invalid = || {
+        anyhow!(
+            "invalid timestamp {:?}, use seconds (90) or minutes:seconds (1:30 or 1:30.5)",
+            timestamp
+        )
+    };
+    let (minutes, seconds) = match timestamp.find(':') {
+        Some(idx) => (Some(&timestamp[..idx]), &timestamp[idx + 1..]),
+        None => (None, timestamp),
+    };
+
+    // digits with at most one '.', so no signs, exponents, inf or NaN get through parse()
+    let is_decimal = move |s: &str| {
+        !s.is_empty()
+            && s.chars().filter(move |c| *c == '.').count() <= 1
+            && s.chars().all(move |c| c.is_ascii_digit() || c == '.')
+            && s.chars().any(move |c| c.is_ascii_digit())
+    };
+    if !is_decimal(seconds) {
+        return Err(invalid());
+    }
+    let seconds: f64 = seconds.parse().map_err(move |_| invalid())?;
+
+    let minutes = match minutes {
+        Some(minutes) => {
+            if minutes.is_empty() || !minutes.chars().all(move |c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            if seconds >= 60.0 {
+                return Err(invalid());
+            }
+            minutes.parse::<u64>().map_err(move |_| invalid())?
+        }
+        None => 0,
+    };
+
+    let total = (minutes as f64) * 60.0 + seconds;
+    if !total.is_finite() || total > (u64::MAX as f64) {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(total))
+}
+
 impl<I, R> FusedIterator for TryUseValueIter<I> where I: Iterator<Item = Result<R>> + FusedIterator {}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::util::{fft_bin_hz, magnitude_to_db, parse_timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn fft_bin_hz_counts_from_dc() {
+        assert_eq!(fft_bin_hz(0, 4096, 44100), 0.0);
+        assert_eq!(fft_bin_hz(1, 4096, 44100), 44100.0 / 4096.0);
+        assert_eq!(fft_bin_hz(1024, 4096, 48000), 12000.0);
+        // the last bin of a real FFT is Nyquist
+        assert_eq!(fft_bin_hz(2048, 4096, 44100), 22050.0);
+    }
+
+    #[test]
+    fn magnitude_to_db_handles_zero() {
+        assert_eq!(magnitude_to_db(0.0, -120.0), -120.0);
+        assert_eq!(magnitude_to_db(-1.0, -120.0), -120.0);
+        assert_eq!(magnitude_to_db(f64::NAN, -120.0), -120.0);
+        assert_eq!(magnitude_to_db(1e-30, -120.0), -120.0);
+    }
+
+    #[test]
+    fn magnitude_to_db_unity_is_zero() {
+        assert_eq!(magnitude_to_db(1.0, -120.0), 0.0);
+        assert!((magnitude_to_db(10.0, -120.0) - 20.0).abs() < 1e-9);
+        assert!((magnitude_to_db(0.1, -120.0) + 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_timestamp_formats() {
+        let parse = |s| parse_timestamp(s).expect("should parse");
+        assert_eq!(parse("90"), Duration::from_secs(90));
+        assert_eq!(parse("90.5"), Duration::from_millis(90_500));
+        assert_eq!(parse("1:30"), Duration::from_secs(90));
+        assert_eq!(parse("1:30.5"), Duration::from_millis(90_500));
+        assert_eq!(parse("0:05"), Duration::from_secs(5));
+        assert_eq!(parse("75:00"), Duration::from_secs(4500));
+    }
+
+    #[test]
+    fn parse_timestamp_rejects_malformed() {
+        for bad in &[
+            "", "-5", "1:", ":30", "1:75", "1:30:00", "a", "1.5:30", "1e3", "inf", "1:-5", ".",
+        ] {
+            match parse_timestamp(bad) {
+                Ok(dur) => panic!("{:?} should not parse, got {:?}", bad, dur),
+                Err(err) => assert!(err.to_string().contains("invalid timestamp"), "{}", err),
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::framed::{Sampled, Samples};
+use anyhow::Result;
+
+/// In-memory source of samples, for audio which is already decoded (and for tests)
+#[derive(Debug, Clone)]
+pub struct VecSamples<T> {
+    samples: Vec<T>,
+    sample_rate: usize,
+    at: usize,
+}
+
+impl<T> VecSamples<T> {
+    pub fn new(samples: Vec<T>, sample_rate: usize) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            at: 0,
+        }
+    }
+}
+
+impl<T> Samples<T, VecSamples<T>> for VecSamples<T>
+where
+    T: Clone,
+{
+    fn into_deep_inner(self) -> VecSamples<T> {
+        self
+    }
+
+    // seeks past either end stop at that end
+    fn seek_samples(&mut self, n: isize) -> Result<()> {
+        let new_at = (self.at as isize) + n;
+        self.at = if new_at < 0 {
+            0
+        } else {
+            std::cmp::min(new_at as usize, self.samples.len())
+        };
+
+        Ok(())
+    }
+
+    fn next_sample(&mut self) -> Result<Option<T>> {
+        let out = self.samples.get(self.at).cloned();
+        if out.is_some() {
+            self.at += 1;
+        }
+
+        Ok(out)
+    }
+
+    fn num_samples_remain(&self) -> usize {
+        self.samples.len() - self.at
+    }
+}
+
+impl<T> Sampled for VecSamples<T> {
+    fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    fn num_samples(&self) -> usize {
+        self.samples.len()
+    }
+}
@@ -1,43 +1,202 @@
+use crate::boxed::BoxedFramed;
+use crate::cli::CliArgs;
+use crate::clipping::{ClipDetect, ClipStats};
+use crate::correlation::CorrelationTap;
+use crate::diff::FramedDiff;
+use crate::error::VizError;
 use crate::framed::Framed;
-use crate::pipeline::{create_viz_pipeline, open_config_or_default, VizPipelineConfig};
-use crate::player::WavPlayer;
+use crate::interpolate::{FrameFeed, RenderClock};
+use crate::pipeline::{
+    create_viz_pipeline, normalize_between, total_renderable_frames, AmplitudeResponse, ClampMode,
+    HeightCurve, VizPipelineConfig, VizPipelineTaps,
+};
+use crate::player::{SeekDirection, WavPlayer};
+use crate::playlist::Playlist;
+use crate::quality::{Quality, QualityTap};
 use crate::util::{log_timed, VizFloat};
-use crate::wav::WavFile;
+use crate::wav::{open_audio, WavFile};
+use crate::waveform::{waveform_points, WaveformTap};
 use anyhow::Result;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, WindowCanvas};
+use sdl2::{EventPump, Sdl};
 use std::ops::{Add, Sub};
 use std::time::{Duration, Instant};
 
-pub fn visualize(file: &str) -> Result<()> {
+// shift + left/right seeks this far
+const LARGE_SEEK_SECONDS: u64 = 60;
+const DISPLAY_GAMMA_STEP: VizFloat = 0.1;
+const DISPLAY_GAMMA_MIN: VizFloat = 0.1;
+const DISPLAY_GAMMA_MAX: VizFloat = 5.0;
+const BIN_MARGIN: u32 = 3;
+const BAR_COLOR: Color = Color::GREEN;
+// --diff mode, the first file is louder (positive) or quieter (negative) than the second
+const DIFF_POSITIVE_COLOR: Color = Color::GREEN;
+const DIFF_NEGATIVE_COLOR: Color = Color::RED;
+// bars this close to 1.0 count as full height
+const OVERFLOW_EPSILON: VizFloat = 1e-6;
+// bars never go below this, and the canvas must be at least twice as tall to draw anything
+const MIN_BAR_HEIGHT: u32 = 4;
+// while rendering keeps drifting, summarize it this often (in frames)
+const DRIFT_SUMMARY_FRAMES: usize = 300;
+const DB_SCALE_COLOR: Color = Color::RGB(160, 160, 160);
+// the dB scale picks the smallest of these steps which needs at most MAX_DB_TICKS ticks
+const DB_TICK_STEPS: [VizFloat; 10] = [1.0, 2.0, 3.0, 5.0, 6.0, 10.0, 20.0, 30.0, 50.0, 100.0];
+const MAX_DB_TICKS: usize = 8;
+const DB_TICK_WIDTH: u32 = 6;
+// pixels per dot of the label glyphs
+const GLYPH_SCALE: u32 = 2;
+// 3x5 dot glyphs for the dB labels, a row per byte (the low 3 bits, left to right)
+const GLYPH_MINUS: [u8; 5] = [0b000, 0b000, 0b111, 0b000, 0b000];
+const GLYPH_DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// How visualizing a file ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlaybackEnd {
+    /// the end of the file (or the frame limit) was reached
+    Finished,
+    /// skipped to the next file
+    Skipped,
+    /// the window was closed
+    Quit,
+}
+
+/// Plays and visualizes every file in order, in one window
+pub fn visualize(files: &[String], args: &CliArgs) -> Result<()> {
     let sdl_context = sdl2::init().map_err(map_sdl_err)?;
     let video_subsystem = sdl_context.video().map_err(map_sdl_err)?;
-    let window = video_subsystem
-        .window("vis-rs", 1280, 720)
-        .position_centered()
-        .build()?;
+    // a failed canvas build consumes the window, so the fallback opens a new one
+    let open_window = || {
+        video_subsystem
+            .window("vis-rs", 1280, 720)
+            .position_centered()
+            .resizable()
+            .build()
+    };
 
-    let mut canvas = window.into_canvas().accelerated().build()?;
+    let mut canvas = build_canvas(
+        || Ok(open_window()?.into_canvas().accelerated().build()?),
+        || Ok(open_window()?.into_canvas().software().build()?),
+    )?;
+    canvas.set_draw_color(Color::BLACK);
     canvas.clear();
     canvas.present();
 
-    let (mut frames, config, wav_src) = log_timed(
+    let mut event_pump = sdl_context.event_pump().map_err(map_sdl_err)?;
+    let config = args.load_config()?;
+    let mut playlist = Playlist::new(files.to_vec(), args.repeat);
+    // --seek only applies to the first file
+    let mut start_at = args.seek;
+    while let Some(file) = playlist.current() {
+        let end = visualize_file(
+            file,
+            args,
+            &config,
+            start_at.take(),
+            &sdl_context,
+            &mut canvas,
+            &mut event_pump,
+        )?;
+        match end {
+            PlaybackEnd::Finished => {}
+            PlaybackEnd::Skipped => println!("skip {}", file),
+            PlaybackEnd::Quit => break,
+        }
+
+        playlist.advance();
+    }
+
+    Ok(())
+}
+
+/// Builds the canvas with `accelerated`, or with `software` (and a warning) when there is no usable
+/// GPU, e.g. in VMs or over remote desktop
+fn build_canvas<C>(
+    accelerated: impl FnOnce() -> Result<C>,
+    software: impl FnOnce() -> Result<C>,
+) -> Result<C> {
+    match accelerated() {
+        Ok(canvas) => Ok(canvas),
+        Err(err) => {
+            eprintln!(
+                "[warn] accelerated rendering is unavailable ({:#}), falling back to software",
+                err
+            );
+            software()
+        }
+    }
+}
+
+fn visualize_file(
+    file: &str,
+    args: &CliArgs,
+    config: &VizPipelineConfig,
+    start_at: Option<Duration>,
+    sdl_context: &Sdl,
+    canvas: &mut WindowCanvas,
+    event_pump: &mut EventPump,
+) -> Result<PlaybackEnd> {
+    let diff = args.diff.is_some();
+    let (mut frames, wav_src, taps, clipping) = log_timed(
         format!("setup visualizer math pipeline for {}", file),
-        || create_data_src(file),
+        || create_data_src(file, args, config),
     )?;
+    let frame_for_offset = config.window_duration(&wav_src) / 2;
+    let sync_offset_ms = config.sync_offset_ms;
+    println!(
+        "{}: {} frames",
+        file,
+        total_renderable_frames(&wav_src, config)
+    );
     let mut wav_player = WavPlayer::new(sdl_context.audio().map_err(map_sdl_err)?, wav_src);
-
-    let mut event_pump = sdl_context.event_pump().map_err(map_sdl_err)?;
+    clear_canvas(canvas, u8::MAX)?;
 
     wav_player.play()?;
     let mut paused = false;
+    let mut display_gamma: VizFloat = 1.0;
+    let fade_alpha = fade_alpha(config.render.persistence);
+    let height_curve = config.render.height_curve;
+    let bar_order = config.render.bar_order;
+    let mut ordered = Vec::new();
+    let mut drift_warnings = DriftWarnings::new(config.render.drift_warn_frames);
+    if fade_alpha < u8::MAX {
+        // the fade is drawn as a translucent rect over the previous frame
+        canvas.set_blend_mode(BlendMode::Blend);
+    }
+    let overflow_color = config
+        .render
+        .overflow_color
+        .map(move |[r, g, b]| Color::RGB(r, g, b));
     let mut last_frame_for_ts: Option<Instant> = None;
-    let frame_delta = Duration::new(0, (1_000_000_000u64 / config.fps) as u32);
-    let frame_for_offset = config.data_window() / 2;
-    loop {
+    let data_delta = Duration::new(0, (1_000_000_000u64 / config.fps) as u32);
+    let render_fps = config.render_fps.unwrap_or(config.fps);
+    let frame_delta = Duration::new(0, (1_000_000_000u64 / render_fps) as u32);
+    let mut feed = FrameFeed::new(RenderClock::new(config.fps, render_fps), config.max_frames);
+    if let Some(start_at) = start_at {
+        // a whole number of data frames, like seeking with the arrow keys
+        let frames_seek = start_at.div_duration_f64(data_delta).floor() as u32;
+        wav_player.seek(data_delta * frames_seek, SeekDirection::Forward)?;
+        frames.seek_frame(frames_seek as isize)?;
+        feed.seek(frames_seek as isize);
+    }
+    let mut loop_region = LoopRegion::default();
+    let db_scale = DbScale::from_config(config);
+    let mut end = PlaybackEnd::Finished;
+    'frames: loop {
         let now = Instant::now();
 
         for event in event_pump.poll_iter() {
@@ -46,19 +205,74 @@ pub fn visualize(file: &str) -> Result<()> {
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => return Ok(()),
+                } => {
+                    wav_player.stop()?;
+                    end = PlaybackEnd::Quit;
+                    break 'frames;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => {
+                    wav_player.stop()?;
+                    end = PlaybackEnd::Skipped;
+                    break 'frames;
+                }
                 Event::KeyDown {
-                    keycode: Some(Keycode::Right),
+                    keycode: Some(keycode @ Keycode::Right),
+                    keymod,
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(keycode @ Keycode::Left),
+                    keymod,
                     ..
                 } => {
-                    let mut amount_seek = Duration::from_secs(10);
-                    let frames_seek = amount_seek.div_duration_f64(frame_delta).floor() as u32;
-                    amount_seek = frame_delta * frames_seek;
+                    let direction = if keycode == Keycode::Right {
+                        SeekDirection::Forward
+                    } else {
+                        SeekDirection::Backward
+                    };
+                    let mut amount_seek = seek_amount(keymod, config.seek_seconds);
+                    let frames_seek = amount_seek.div_duration_f64(data_delta).floor() as u32;
+                    amount_seek = data_delta * frames_seek;
 
-                    wav_player.seek(amount_seek)?;
-                    frames.seek_frame(frames_seek as isize)?;
+                    wav_player.seek(amount_seek, direction)?;
+                    let frames_seek = match direction {
+                        SeekDirection::Forward => frames_seek as isize,
+                        SeekDirection::Backward => -(frames_seek as isize),
+                    };
+                    frames.seek_frame(frames_seek)?;
+                    feed.seek(frames_seek);
                     last_frame_for_ts = Some(now.sub(frame_delta));
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::A),
+                    ..
+                } => {
+                    let at = feed.data_frames();
+                    loop_region.set_a(at);
+                    println!("loop in at frame {}", at);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    ..
+                } => {
+                    let at = feed.data_frames();
+                    if loop_region.set_b(at) {
+                        println!("loop out at frame {}", at);
+                    } else {
+                        println!("loop out must be after loop in, ignored frame {}", at);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::L),
+                    ..
+                } => match loop_region.toggle() {
+                    Some(true) => println!("looping"),
+                    Some(false) => println!("not looping"),
+                    None => println!("set loop in (A) and loop out (B) first"),
+                },
                 Event::KeyDown {
                     keycode: Some(Keycode::Space),
                     ..
@@ -71,6 +285,32 @@ pub fn visualize(file: &str) -> Result<()> {
 
                     paused = !paused;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::LeftBracket),
+                    ..
+                } => {
+                    display_gamma = adjust_display_gamma(display_gamma, -DISPLAY_GAMMA_STEP);
+                    println!("display gamma = {:.1}", display_gamma);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::RightBracket),
+                    ..
+                } => {
+                    display_gamma = adjust_display_gamma(display_gamma, DISPLAY_GAMMA_STEP);
+                    println!("display gamma = {:.1}", display_gamma);
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    // the viewport would stay at the old size, and with persistence the old
+                    // frames would be stretched, so start over on a clean canvas. bar_layout
+                    // picks up the new output size on the next frame.
+                    canvas.set_viewport(None);
+                    clear_canvas(canvas, u8::MAX)?;
+                    let (width, height) = canvas.output_size().map_err(map_sdl_err)?;
+                    println!("window resized to {}x{}", width, height);
+                }
                 _ => {}
             }
         }
@@ -99,61 +339,175 @@ pub fn visualize(file: &str) -> Result<()> {
                 0
             };
 
-            if status.abs() > 1 {
-                println!("status = {}", status);
+            if let Some(summary) = drift_warnings.record(status) {
+                println!("{}", summary);
+            }
+            if let Some(average) = taps.quality.as_ref().and_then(QualityTap::take_switched) {
+                println!(
+                    "frames take {:?} to compute on average, switched to fast quality",
+                    average
+                );
             }
             if status > 0 {
                 std::thread::sleep(frame_delta);
             } else {
                 last_frame_for_ts = Some(cur_frame_for);
                 if !paused {
-                    if let Some(frame) = frames.next_frame()? {
-                        if status == 0 {
-                            draw_frame(&mut canvas, frame)?;
+                    // back to loop in at loop out, like seeking with the arrow keys
+                    if let Some(frames_seek) = loop_region.jump_back(feed.data_frames()) {
+                        let amount_seek = data_delta * (frames_seek.unsigned_abs() as u32);
+                        wav_player.seek(amount_seek, SeekDirection::Backward)?;
+                        frames.seek_frame(frames_seek)?;
+                        feed.seek(frames_seek);
+                        last_frame_for_ts = Some(now.sub(frame_delta));
+                    }
+
+                    // pull the data frames needed for this render frame
+                    match feed.advance(&mut frames)? {
+                        Some(frame) => bar_order.arrange(frame, &mut ordered),
+                        None => {
+                            wav_player.stop()?;
+                            break 'frames;
                         }
-                    } else {
-                        wav_player.stop()?;
-                        return Ok(());
+                    }
+
+                    if status == 0 {
+                        let frame = ordered.as_slice();
+                        clear_canvas(canvas, fade_alpha)?;
+                        if diff {
+                            draw_diff_frame(canvas, frame, display_gamma, height_curve)?;
+                        } else if let Some(waveform) = &taps.waveform {
+                            waveform.with_latest(|samples| {
+                                draw_frame(
+                                    canvas,
+                                    frame,
+                                    display_gamma,
+                                    height_curve,
+                                    overflow_color,
+                                    Some(samples),
+                                )
+                            })?;
+                        } else {
+                            draw_frame(
+                                canvas,
+                                frame,
+                                display_gamma,
+                                height_curve,
+                                overflow_color,
+                                None,
+                            )?;
+                        }
+
+                        if let (Some(scale), false) = (&db_scale, diff) {
+                            draw_db_scale(canvas, scale, display_gamma, height_curve)?;
+                        }
+                        if let Some(correlation) = &taps.correlation {
+                            draw_correlation_meter(canvas, correlation.latest())?;
+                        }
+                        canvas.present();
                     }
                 }
             }
         } else {
-            last_frame_for_ts = Some(now.add(frame_for_offset));
+            last_frame_for_ts = Some(first_frame_at(now, frame_for_offset, sync_offset_ms));
         }
     }
+
+    clipping.report();
+    Ok(end)
 }
 
-fn create_data_src(file: &str) -> Result<(impl Framed<VizFloat, WavFile>, VizPipelineConfig, WavFile)> {
+fn create_data_src(
+    file: &str,
+    args: &CliArgs,
+    config: &VizPipelineConfig,
+) -> Result<(BoxedFramed<VizFloat>, WavFile, VizPipelineTaps, ClipStats)> {
     const BUF_SIZE: usize = 32768;
 
-    let config = open_config_or_default()?;
-    let frame_src = create_viz_pipeline(WavFile::open(file, BUF_SIZE)?, config)?;
-    Ok((frame_src, config, WavFile::open(file, BUF_SIZE)?))
+    let taps = VizPipelineTaps {
+        waveform: if config.render.waveform {
+            Some(WaveformTap::new())
+        } else {
+            None
+        },
+        correlation: if config.render.correlation_meter {
+            Some(CorrelationTap::new())
+        } else {
+            None
+        },
+        quality: if config.quality == Quality::Adaptive {
+            Some(QualityTap::new())
+        } else {
+            None
+        },
+        ..VizPipelineTaps::default()
+    };
+    // with --diff each pipeline writes its debug dump into a directory of its own
+    let pipeline_config = move |name| match &args.diff {
+        Some(_) => config.with_debug_dump_subdir(name),
+        None => config.clone(),
+    };
+    let source = ClipDetect::new(open_audio(file, BUF_SIZE, args.raw_format, args.strict)?);
+    let clipping = source.stats();
+    let frame_src = create_viz_pipeline(source, pipeline_config("a"), taps.clone())?;
+    let frame_src = if let Some(other) = &args.diff {
+        let other = open_audio(other.as_str(), BUF_SIZE, args.raw_format, args.strict)?;
+        let other = create_viz_pipeline(other, pipeline_config("b"), VizPipelineTaps::default())?;
+        FramedDiff::new(frame_src, other).boxed()
+    } else {
+        frame_src.boxed()
+    };
+    Ok((
+        frame_src,
+        open_audio(file, BUF_SIZE, args.raw_format, args.strict)?,
+        taps,
+        clipping,
+    ))
 }
 
-fn draw_frame(canvas: &mut WindowCanvas, frame: &[VizFloat]) -> Result<()> {
-    const BIN_MARGIN: u32 = 3;
+fn draw_frame(
+    canvas: &mut WindowCanvas,
+    frame: &[VizFloat],
+    display_gamma: VizFloat,
+    height_curve: HeightCurve,
+    overflow_color: Option<Color>,
+    waveform: Option<&[VizFloat]>,
+) -> Result<()> {
+    const WAVEFORM_COLOR: Color = Color::RGB(60, 60, 60);
 
-    canvas.set_draw_color(Color::BLACK);
-    canvas.clear();
     let (width, height) = canvas.output_size().map_err(map_sdl_err)?;
-    canvas.set_draw_color(Color::GREEN);
+    let layout = match bar_layout(width, height, frame.len() as u32) {
+        Some(layout) => layout,
+        // too small (or minimized) to draw anything useful
+        None => return Ok(()),
+    };
 
-    let avail_height = height - (BIN_MARGIN * 2);
-    let n_bins = frame.len() as u32;
-    let total_margin_used = (n_bins + 1) * BIN_MARGIN;
-    let width_per_bin = (width - total_margin_used) / n_bins;
+    if let Some(samples) = waveform {
+        let points = waveform_points(samples, width, height)
+            .into_iter()
+            .map(move |(x, y)| Point::new(x, y))
+            .collect::<Vec<_>>();
+        canvas.set_draw_color(WAVEFORM_COLOR);
+        canvas.draw_lines(points.as_slice()).map_err(map_sdl_err)?;
+    }
+
+    let BarLayout {
+        avail_height,
+        width_per_bin,
+    } = layout;
     let mut cur_x = BIN_MARGIN;
-    for i in 0..n_bins {
+    for i in 0..(frame.len() as u32) {
         let lx = cur_x;
         let rx = lx + width_per_bin;
         cur_x = rx + BIN_MARGIN;
 
-        let v = frame[i as usize];
+        // metering mode doesn't clamp the bars, the window can only show 0..1
+        let value = frame[i as usize].clamp(0.0, 1.0);
+        canvas.set_draw_color(bar_color(value, overflow_color));
+        let v = height_curve.apply(value.powf(display_gamma));
         let mut ty = ((1.0 - v) * (avail_height as VizFloat)) as u32;
-        const MIN_HEIGHT: u32 = 4;
-        if ty < MIN_HEIGHT {
-            ty = MIN_HEIGHT
+        if ty < MIN_BAR_HEIGHT {
+            ty = MIN_BAR_HEIGHT
         }
 
         let by = avail_height;
@@ -173,10 +527,615 @@ fn draw_frame(canvas: &mut WindowCanvas, frame: &[VizFloat]) -> Result<()> {
         canvas.fill_rect(rect).map_err(map_sdl_err)?;
     }
 
-    canvas.present();
     Ok(())
 }
 
+/// The dB values marked along the left edge, and how they map to bar values
+#[derive(Debug, Clone, PartialEq)]
+struct DbScale {
+    min_db: VizFloat,
+    max_db: VizFloat,
+    clamp_mode: ClampMode,
+    ticks: Vec<VizFloat>,
+}
+
+impl DbScale {
+    /// None when the scale is off, or the bars aren't in dB. With auto_db the scale shows the
+    /// configured min_db..max_db, not the learned range.
+    fn from_config(config: &VizPipelineConfig) -> Option<Self> {
+        match config.amplitude_response {
+            AmplitudeResponse::Db if config.render.db_scale => Some(Self {
+                min_db: config.min_db,
+                max_db: config.max_db,
+                clamp_mode: config.clamp_mode,
+                ticks: db_ticks(config.min_db, config.max_db),
+            }),
+            _ => None,
+        }
+    }
+
+    /// where a bar of `db` ends, the same mapping as the pipeline's clamp and `draw_frame`
+    fn tick_y(
+        &self,
+        db: VizFloat,
+        display_gamma: VizFloat,
+        height_curve: HeightCurve,
+        avail_height: u32,
+    ) -> u32 {
+        let mut value = db;
+        normalize_between(&mut value, self.min_db, self.max_db, self.clamp_mode);
+        let v = height_curve.apply(value.powf(display_gamma));
+        ((1.0 - v) * (avail_height as VizFloat)) as u32
+    }
+}
+
+/// Whole multiples of an even step between min_db and max_db (at most MAX_DB_TICKS of them)
+fn db_ticks(min_db: VizFloat, max_db: VizFloat) -> Vec<VizFloat> {
+    let ticks = move |step: VizFloat| {
+        let first = (min_db / step).ceil() as i64;
+        let last = (max_db / step).floor() as i64;
+        (first..=last).map(move |n| (n as VizFloat) * step)
+    };
+
+    let step = DB_TICK_STEPS
+        .iter()
+        .copied()
+        .find(move |step| ticks(*step).count() <= MAX_DB_TICKS)
+        .unwrap_or(DB_TICK_STEPS[DB_TICK_STEPS.len() - 1]);
+    ticks(step).collect()
+}
+
+fn draw_db_scale(
+    canvas: &mut WindowCanvas,
+    scale: &DbScale,
+    display_gamma: VizFloat,
+    height_curve: HeightCurve,
+) -> Result<()> {
+    let (_, height) = canvas.output_size().map_err(map_sdl_err)?;
+    let avail_height = match height.checked_sub(BIN_MARGIN * 2) {
+        Some(avail_height) if avail_height >= MIN_BAR_HEIGHT * 2 => avail_height,
+        _ => return Ok(()),
+    };
+
+    canvas.set_draw_color(DB_SCALE_COLOR);
+    for db in &scale.ticks {
+        let y = scale.tick_y(*db, display_gamma, height_curve, avail_height) as i32;
+        canvas
+            .fill_rect(Rect::new(0, y, DB_TICK_WIDTH, 1))
+            .map_err(map_sdl_err)?;
+
+        // the label is centered on the tick, right of it
+        let label = format!("{}", db.round() as i64);
+        let top = y - ((5 * GLYPH_SCALE) / 2) as i32;
+        let mut x = (DB_TICK_WIDTH + 2) as i32;
+        for c in label.chars() {
+            let glyph = match c {
+                '-' => GLYPH_MINUS,
+                c => match c.to_digit(10) {
+                    Some(digit) => GLYPH_DIGITS[digit as usize],
+                    None => continue,
+                },
+            };
+            let dots = glyph_dots(glyph, x, top);
+            canvas.fill_rects(dots.as_slice()).map_err(map_sdl_err)?;
+            x += (4 * GLYPH_SCALE) as i32;
+        }
+    }
+
+    Ok(())
+}
+
+// the rects to fill for a glyph with its top left corner at x, y
+fn glyph_dots(glyph: [u8; 5], x: i32, y: i32) -> Vec<Rect> {
+    let dot = GLYPH_SCALE as i32;
+    (0..5)
+        .flat_map(move |row| {
+            (0..3)
+                .filter(move |col| glyph[row] & (0b100 >> col) != 0)
+                .map(move |col| {
+                    Rect::new(
+                        x + (col * dot),
+                        y + (row as i32 * dot),
+                        GLYPH_SCALE,
+                        GLYPH_SCALE,
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Draws the stereo correlation (-1..1) as a horizontal meter along the top of the window, filled
+/// from the center towards -1 (left) or +1 (right)
+fn draw_correlation_meter(canvas: &mut WindowCanvas, correlation: VizFloat) -> Result<()> {
+    const METER_HEIGHT: u32 = 6;
+    const METER_TRACK_COLOR: Color = Color::RGB(60, 60, 60);
+
+    let (width, _) = canvas.output_size().map_err(map_sdl_err)?;
+    let track_width = width / 2;
+    if track_width < 2 {
+        return Ok(());
+    }
+
+    let left = (width / 4) as i32;
+    let center = left + ((track_width / 2) as i32);
+    let y = BIN_MARGIN as i32;
+    canvas.set_draw_color(METER_TRACK_COLOR);
+    canvas
+        .fill_rect(Rect::new(left, y, track_width, METER_HEIGHT))
+        .map_err(map_sdl_err)?;
+
+    let fill = (correlation.clamp(-1.0, 1.0) * ((track_width / 2) as VizFloat)).round() as i32;
+    let (x, fill_width) = if fill >= 0 {
+        (center, fill)
+    } else {
+        (center + fill, -fill)
+    };
+    // in phase is fine (green), out of phase will cancel out in mono (red)
+    canvas.set_draw_color(diff_color(correlation));
+    canvas
+        .fill_rect(Rect::new(
+            x,
+            y,
+            std::cmp::max(fill_width, 1) as u32,
+            METER_HEIGHT,
+        ))
+        .map_err(map_sdl_err)
+}
+
+/// Space available to the bars of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BarLayout {
+    avail_height: u32,
+    width_per_bin: u32,
+}
+
+/// Fits `n_bins` bars (with margins) into the canvas, `None` when the canvas is too small for
+/// bars of at least 1 pixel width and a usable height
+fn bar_layout(width: u32, height: u32, n_bins: u32) -> Option<BarLayout> {
+    if n_bins == 0 {
+        return None;
+    }
+
+    let avail_height = height.checked_sub(BIN_MARGIN * 2)?;
+    if avail_height < MIN_BAR_HEIGHT * 2 {
+        return None;
+    }
+
+    let total_margin_used = (n_bins + 1).checked_mul(BIN_MARGIN)?;
+    let width_per_bin = width.checked_sub(total_margin_used)? / n_bins;
+    if width_per_bin == 0 {
+        return None;
+    }
+
+    Some(BarLayout {
+        avail_height,
+        width_per_bin,
+    })
+}
+
+/// Clears the canvas, or with persistence only fades the previous frame towards black
+fn clear_canvas(canvas: &mut WindowCanvas, fade_alpha: u8) -> Result<()> {
+    if fade_alpha == u8::MAX {
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        Ok(())
+    } else {
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, fade_alpha));
+        canvas.fill_rect(None).map_err(map_sdl_err)
+    }
+}
+
+/// Opacity of the black rect drawn over the previous frame, never fully transparent so old frames
+/// eventually disappear
+fn fade_alpha(persistence: VizFloat) -> u8 {
+    let alpha = ((1.0 - persistence) * 255.0).round();
+    alpha.clamp(1.0, 255.0) as u8
+}
+
+/// Draws a `--diff` frame (values in -1..1), positive bars grow up from the center line and
+/// negative bars grow down
+fn draw_diff_frame(
+    canvas: &mut WindowCanvas,
+    frame: &[VizFloat],
+    display_gamma: VizFloat,
+    height_curve: HeightCurve,
+) -> Result<()> {
+    let (width, height) = canvas.output_size().map_err(map_sdl_err)?;
+    let width_per_bin = match bar_layout(width, height, frame.len() as u32) {
+        Some(layout) => layout.width_per_bin,
+        None => return Ok(()),
+    };
+
+    let center = (height / 2) as i32;
+    let half_height = ((height / 2) - BIN_MARGIN) as VizFloat;
+    let mut cur_x = BIN_MARGIN;
+    for value in frame.iter().copied() {
+        let x = cur_x as i32;
+        cur_x += width_per_bin + BIN_MARGIN;
+
+        let v = height_curve.apply(value.abs().powf(display_gamma));
+        let bar_height = std::cmp::max((v * half_height) as u32, 1);
+        let y = if value >= 0.0 {
+            center - (bar_height as i32)
+        } else {
+            center
+        };
+        canvas.set_draw_color(diff_color(value));
+        let rect = Rect::new(x, y, width_per_bin, bar_height);
+        canvas.fill_rect(rect).map_err(map_sdl_err)?;
+    }
+
+    Ok(())
+}
+
+fn diff_color(value: VizFloat) -> Color {
+    if value >= 0.0 {
+        DIFF_POSITIVE_COLOR
+    } else {
+        DIFF_NEGATIVE_COLOR
+    }
+}
+
+/// Bars at full height use the overflow color (if one is configured)
+fn bar_color(value: VizFloat, overflow_color: Option<Color>) -> Color {
+    match overflow_color {
+        Some(color) if value >= 1.0 - OVERFLOW_EPSILON => color,
+        _ => BAR_COLOR,
+    }
+}
+
+/// A/B repeat: playback jumps back to loop in (A) when it reaches loop out (B) while looping.
+/// Points are data frame positions (see `RenderClock::data_frames`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct LoopRegion {
+    a: Option<u64>,
+    b: Option<u64>,
+    enabled: bool,
+}
+
+impl LoopRegion {
+    /// sets loop in, a loop out before it is cleared (and looping stops)
+    fn set_a(&mut self, at: u64) {
+        self.a = Some(at);
+        if self.b.map(move |b| b <= at).unwrap_or(false) {
+            self.b = None;
+            self.enabled = false;
+        }
+    }
+
+    /// sets loop out, false (and nothing changes) when it isn't after loop in
+    fn set_b(&mut self, at: u64) -> bool {
+        if self.a.map(move |a| at <= a).unwrap_or(false) {
+            return false;
+        }
+
+        self.b = Some(at);
+        true
+    }
+
+    /// turns looping on or off, None when loop in or loop out is missing
+    fn toggle(&mut self) -> Option<bool> {
+        if self.a.is_none() || self.b.is_none() {
+            return None;
+        }
+
+        self.enabled = !self.enabled;
+        Some(self.enabled)
+    }
+
+    /// the frames to seek (negative) at this position to get back to loop in, None to keep playing
+    fn jump_back(&self, at: u64) -> Option<isize> {
+        match (self.enabled, self.a, self.b) {
+            (true, Some(a), Some(b)) if at >= b => Some((a as isize) - (at as isize)),
+            _ => None,
+        }
+    }
+}
+
+/// Collapses a run of frames where rendering is out of step with the audio into one summary line,
+/// plus one every `DRIFT_SUMMARY_FRAMES` while the run lasts
+struct DriftWarnings {
+    threshold: u32,
+    run: usize,
+    worst: i32,
+}
+
+impl DriftWarnings {
+    fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            run: 0,
+            worst: 0,
+        }
+    }
+
+    /// records how many frames ahead (positive) or behind (negative) this frame is, returns a
+    /// summary once a run of drifting frames ends, or every `DRIFT_SUMMARY_FRAMES` while it lasts
+    fn record(&mut self, status: i32) -> Option<String> {
+        if status.unsigned_abs() > self.threshold {
+            self.run += 1;
+            if status.abs() > self.worst.abs() {
+                self.worst = status;
+            }
+
+            if self.run < DRIFT_SUMMARY_FRAMES {
+                return None;
+            }
+
+            return Some(self.summary("is"));
+        }
+
+        if self.run == 0 {
+            return None;
+        }
+
+        Some(self.summary("was"))
+    }
+
+    /// describes the current run and starts a new one
+    fn summary(&mut self, tense: &str) -> String {
+        let summary = format!(
+            "render {} {} by up to {} frames for {} frames",
+            tense,
+            if self.worst > 0 { "ahead" } else { "behind" },
+            self.worst.abs(),
+            self.run
+        );
+        self.run = 0;
+        self.worst = 0;
+        summary
+    }
+}
+
+/// When the first frame is shown: half a frame after playback starts (the frame is centered on the
+/// audio playing at that moment), moved by the configured sync offset
+fn first_frame_at(now: Instant, frame_for_offset: Duration, sync_offset_ms: VizFloat) -> Instant {
+    let sync_offset = Duration::from_secs_f64(sync_offset_ms.abs() / 1000.0);
+    let first_frame = now.add(frame_for_offset);
+    if sync_offset_ms < 0.0 {
+        first_frame.sub(sync_offset)
+    } else {
+        first_frame.add(sync_offset)
+    }
+}
+
+/// How far an arrow key press seeks, holding shift makes a larger jump
+fn seek_amount(keymod: Mod, seek_seconds: u64) -> Duration {
+    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        Duration::from_secs(LARGE_SEEK_SECONDS)
+    } else {
+        Duration::from_secs(seek_seconds)
+    }
+}
+
+/// Moves the render-time gamma (applied to bar heights) by `delta`, staying within a sane range
+fn adjust_display_gamma(current: VizFloat, delta: VizFloat) -> VizFloat {
+    let next = ((current + delta) * 10.0).round() / 10.0;
+    next.clamp(DISPLAY_GAMMA_MIN, DISPLAY_GAMMA_MAX)
+}
+
 fn map_sdl_err(err: String) -> anyhow::Error {
-    anyhow::anyhow!("sdl2: {}", err)
+    VizError::Sdl(err).into()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::pipeline::{ClampMode, HeightCurve};
+    use crate::viz::{
+        adjust_display_gamma, bar_color, bar_layout, build_canvas, db_ticks, fade_alpha,
+        first_frame_at, seek_amount, BarLayout, DbScale, DriftWarnings, LoopRegion, BAR_COLOR,
+        DISPLAY_GAMMA_MAX, DISPLAY_GAMMA_MIN, DRIFT_SUMMARY_FRAMES, LARGE_SEEK_SECONDS,
+    };
+    use anyhow::anyhow;
+    use sdl2::keyboard::Mod;
+    use sdl2::pixels::Color;
+    use std::cell::Cell;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn db_ticks_are_even_steps_inside_the_range() {
+        assert_eq!(
+            db_ticks(-29.0, -8.5),
+            vec![-27.0, -24.0, -21.0, -18.0, -15.0, -12.0, -9.0]
+        );
+        assert_eq!(
+            db_ticks(-60.0, 0.0),
+            vec![-60.0, -50.0, -40.0, -30.0, -20.0, -10.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn db_tick_is_where_a_bar_of_that_db_ends() {
+        let scale = DbScale {
+            min_db: -30.0,
+            max_db: -10.0,
+            clamp_mode: ClampMode::Hard,
+            ticks: db_ticks(-30.0, -10.0),
+        };
+        assert_eq!(scale.tick_y(-30.0, 1.0, HeightCurve::Linear, 100), 100);
+        assert_eq!(scale.tick_y(-20.0, 1.0, HeightCurve::Linear, 100), 50);
+        assert_eq!(scale.tick_y(-10.0, 1.0, HeightCurve::Linear, 100), 0);
+        // the height curve and display gamma move the ticks along with the bars
+        assert_eq!(scale.tick_y(-20.0, 1.0, HeightCurve::Sqrt, 100), 29);
+        assert_eq!(scale.tick_y(-20.0, 2.0, HeightCurve::Linear, 100), 75);
+    }
+
+    #[test]
+    fn loop_jumps_back_at_loop_out() {
+        let mut region = LoopRegion::default();
+        assert_eq!(region.toggle(), None);
+        region.set_a(100);
+        assert!(!region.set_b(100));
+        assert!(region.set_b(250));
+
+        // not looping yet
+        assert_eq!(region.jump_back(250), None);
+        assert_eq!(region.toggle(), Some(true));
+        assert_eq!(region.jump_back(249), None);
+        assert_eq!(region.jump_back(250), Some(-150));
+        // seeking past loop out still goes back
+        assert_eq!(region.jump_back(400), Some(-300));
+
+        assert_eq!(region.toggle(), Some(false));
+        assert_eq!(region.jump_back(250), None);
+
+        // a new loop in after loop out clears it
+        region.set_a(300);
+        assert_eq!(region.toggle(), None);
+        assert_eq!(region.jump_back(400), None);
+    }
+
+    #[test]
+    fn sync_offset_moves_the_first_frame() {
+        let now = Instant::now();
+        let half_frame = Duration::from_millis(49);
+        assert_eq!(first_frame_at(now, half_frame, 0.0), now + half_frame);
+        assert_eq!(
+            first_frame_at(now, half_frame, 25.0),
+            now + half_frame + Duration::from_millis(25)
+        );
+        assert_eq!(
+            first_frame_at(now, half_frame, -25.0),
+            now + half_frame - Duration::from_millis(25)
+        );
+    }
+
+    #[test]
+    fn canvas_falls_back_to_software() {
+        let software_used = Cell::new(false);
+        let software = || {
+            software_used.set(true);
+            Ok("software")
+        };
+        assert_eq!(
+            build_canvas(|| Ok("accelerated"), software).expect("ok"),
+            "accelerated"
+        );
+        assert!(!software_used.get());
+
+        let canvas = build_canvas(|| Err(anyhow!("no renderer")), software).expect("ok");
+        assert_eq!(canvas, "software");
+        assert!(software_used.get());
+
+        assert!(build_canvas::<()>(
+            || Err(anyhow!("no renderer")),
+            || Err(anyhow!("no software renderer"))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn display_gamma_is_clamped() {
+        assert_eq!(adjust_display_gamma(1.0, 0.1), 1.1);
+        assert_eq!(adjust_display_gamma(1.0, -0.1), 0.9);
+        assert_eq!(
+            adjust_display_gamma(DISPLAY_GAMMA_MIN, -0.1),
+            DISPLAY_GAMMA_MIN
+        );
+        assert_eq!(
+            adjust_display_gamma(DISPLAY_GAMMA_MAX, 0.1),
+            DISPLAY_GAMMA_MAX
+        );
+
+        let mut gamma = 1.0;
+        for _ in 0..100 {
+            gamma = adjust_display_gamma(gamma, -0.1);
+        }
+        assert_eq!(gamma, DISPLAY_GAMMA_MIN);
+    }
+
+    #[test]
+    fn overflow_color_only_at_full_height() {
+        let red = Some(Color::RGB(255, 0, 0));
+        assert_eq!(bar_color(1.0, red), Color::RGB(255, 0, 0));
+        assert_eq!(bar_color(0.99, red), BAR_COLOR);
+        assert_eq!(bar_color(1.0, None), BAR_COLOR);
+    }
+
+    #[test]
+    fn bar_layout_of_tiny_canvas() {
+        // 49 bars need more margin than a 10x10 canvas has
+        assert_eq!(bar_layout(10, 10, 49), None);
+        assert_eq!(bar_layout(0, 0, 49), None);
+        assert_eq!(bar_layout(1280, 720, 0), None);
+        // wide enough, but no height left after the margins
+        assert_eq!(bar_layout(1280, 10, 49), None);
+
+        assert_eq!(
+            bar_layout(1280, 720, 49),
+            Some(BarLayout {
+                avail_height: 714,
+                width_per_bin: 23,
+            })
+        );
+    }
+
+    #[test]
+    fn bar_layout_follows_resize() {
+        let before = bar_layout(1280, 720, 49).expect("should fit");
+        let after = bar_layout(640, 360, 49).expect("should fit");
+        assert_eq!(after.width_per_bin, 10);
+        assert_eq!(after.avail_height, 354);
+        assert!(after.width_per_bin < before.width_per_bin);
+
+        let after = bar_layout(2560, 1440, 49).expect("should fit");
+        assert_eq!(after.width_per_bin, 49);
+        assert_eq!(after.avail_height, 1434);
+    }
+
+    #[test]
+    fn fade_alpha_from_persistence() {
+        assert_eq!(fade_alpha(0.0), 255);
+        assert_eq!(fade_alpha(0.5), 128);
+        assert_eq!(fade_alpha(0.8), 51);
+        assert_eq!(fade_alpha(0.9999), 1);
+    }
+
+    #[test]
+    fn drift_warnings_are_summarized() {
+        let mut warnings = DriftWarnings::new(1);
+        let lines = [0, -2, -2, -3, -2, 0, 1, 0]
+            .iter()
+            .filter_map(|status| warnings.record(*status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec!["render was behind by up to 3 frames for 4 frames".to_string()]
+        );
+
+        // a long run is summarized while it lasts, and the rest once it ends
+        let mut warnings = DriftWarnings::new(1);
+        let lines = std::iter::repeat_n(3, DRIFT_SUMMARY_FRAMES + 5)
+            .chain(std::iter::once(0))
+            .filter_map(|status| warnings.record(status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                format!(
+                    "render is ahead by up to 3 frames for {} frames",
+                    DRIFT_SUMMARY_FRAMES
+                ),
+                "render was ahead by up to 3 frames for 5 frames".to_string(),
+            ]
+        );
+
+        // off by two is within the default threshold
+        let mut warnings = DriftWarnings::new(2);
+        assert!([2, 2, 0]
+            .iter()
+            .all(|status| warnings.record(*status).is_none()));
+    }
+
+    #[test]
+    fn seek_amount_depends_on_shift() {
+        let small = Duration::from_secs(5);
+        let large = Duration::from_secs(LARGE_SEEK_SECONDS);
+        assert_eq!(seek_amount(Mod::NOMOD, 5), small);
+        assert_eq!(seek_amount(Mod::NUMMOD, 5), small);
+        assert_eq!(seek_amount(Mod::LCTRLMOD, 5), small);
+        assert_eq!(seek_amount(Mod::LSHIFTMOD, 5), large);
+        assert_eq!(seek_amount(Mod::RSHIFTMOD, 5), large);
+        assert_eq!(seek_amount(Mod::LSHIFTMOD | Mod::NUMMOD, 5), large);
+    }
 }
@@ -1,7 +1,8 @@
-// supports only: PCM, 8 or 16 bits per sample
+// supports only: PCM, 8, 16 or 24 bits per sample, and 32 bit IEEE float
 
 use crate::channeled::Channeled;
-use crate::framed::{AudioSource, Sampled, Samples};
+use crate::error::VizError;
+use crate::framed::{AudioSource, SampleFormat, Sampled, Samples};
 use anyhow::*;
 use std::cmp;
 use std::convert::TryInto;
@@ -11,10 +12,14 @@ use std::path::Path;
 use std::str::from_utf8;
 use crate::util::VizFloat;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SampleRaw {
     OneByte(u8),
     TwoBytes(i16),
+    /// 24 bit sample, sign extended
+    ThreeBytes(i32),
+    /// IEEE float sample, already -1.0..1.0
+    Float(f32),
 }
 
 impl Default for SampleRaw {
@@ -23,17 +28,36 @@ impl Default for SampleRaw {
     }
 }
 
+impl SampleRaw {
+    /// true if the sample is at the minimum or maximum value of its format (clipped)
+    pub fn is_full_scale(&self) -> bool {
+        use SampleRaw::*;
+
+        match *self {
+            OneByte(b) => b == u8::MIN || b == u8::MAX,
+            TwoBytes(b) => b == i16::MIN || b == i16::MAX,
+            ThreeBytes(b) => b == I24_MIN || b == I24_MAX,
+            Float(v) => v.abs() >= 1.0,
+        }
+    }
+}
+
 impl Into<VizFloat> for SampleRaw {
     fn into(self) -> VizFloat {
         use SampleRaw::*;
 
         match self {
             OneByte(b) => ((b as VizFloat / 255.0) * 2.0) - 1.0,
-            TwoBytes(b) => ((b as VizFloat) / 65535.0) * 2.0,
+            TwoBytes(b) => (b as VizFloat) / 32768.0,
+            ThreeBytes(b) => (b as VizFloat) / 8_388_608.0,
+            Float(v) => v as VizFloat,
         }
     }
 }
 
+const I24_MIN: i32 = -(1 << 23);
+const I24_MAX: i32 = (1 << 23) - 1;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ByteOrdering {
     LittleEndian,
@@ -80,6 +104,38 @@ impl ByteOrdering {
         Ok((u16 as i16, rest))
     }
 
+    // a 24 bit sample, sign extended into an i32
+    fn i24_from<'a>(&self, buf: &'a [u8]) -> Result<(i32, &'a [u8])> {
+        if buf.len() < 3 {
+            return Err(anyhow!("EOF"));
+        }
+
+        let (data, rest) = buf.split_at(3);
+        use ByteOrdering::*;
+        // the sample goes into the upper three bytes, the arithmetic shift sign extends it
+        let shifted = match self {
+            LittleEndian => i32::from_le_bytes([0, data[0], data[1], data[2]]),
+            BigEndian => i32::from_be_bytes([data[0], data[1], data[2], 0]),
+        };
+
+        Ok((shifted >> 8, rest))
+    }
+
+    fn f32_from<'a>(&self, buf: &'a [u8]) -> Result<(f32, &'a [u8])> {
+        if buf.len() < 4 {
+            return Err(anyhow!("EOF"));
+        }
+
+        let (data, rest) = buf.split_at(4);
+        use ByteOrdering::*;
+        let v = match self {
+            LittleEndian => f32::from_le_bytes(data.try_into().expect("should work")),
+            BigEndian => f32::from_be_bytes(data.try_into().expect("should work")),
+        };
+
+        Ok((v, rest))
+    }
+
     fn read_n<'a, R>(&self, reader: &mut R, buf: &'a mut [u8], n: usize) -> Result<&'a [u8]>
     where
         R: Read,
@@ -94,9 +150,21 @@ impl ByteOrdering {
     }
 }
 
+// size of the fmt chunk fields we read, any declared size beyond this is extension data
+const FMT_PCM_LEN: usize = 16;
+// the fmt fields plus cbSize and the 22 bytes of the WAVE_FORMAT_EXTENSIBLE extension
+const FMT_EXTENSIBLE_LEN: usize = 40;
+const WAVE_FORMAT_PCM: u16 = 0x01;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x03;
+// required for more than two channels, the actual format is in the extension's sub format GUID
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+// the sub format GUIDs are the format id followed by these bytes (KSDATAFORMAT_SUBTYPE_*)
+const SUB_FORMAT_GUID_TAIL: [u8; 8] = [0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71];
+
 #[derive(Debug)]
 pub struct WavFile {
     pub ordering: ByteOrdering,
+    pub format_tag: u16,
     pub sample_rate: u32,
     pub num_channels: u16,
     pub bits_per_sample: u16,
@@ -105,17 +173,84 @@ pub struct WavFile {
     pub block_align: u16,
 
     f: BufReader<File>,
+    // only for seeking to a sample
+    #[cfg(any(feature = "gui", test))]
     data_starts_at: u64,
 
     sample_at: usize,
 }
 
+/// Format of a headerless PCM file (little endian, interleaved channels), which can't be read
+/// from the file itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawPcmFormat {
+    pub sample_rate: u32,
+    pub num_channels: u16,
+    pub bits_per_sample: u16,
+}
+
+/// true if the path has an extension we treat as headerless PCM (`.raw` or `.pcm`)
+pub fn is_raw_pcm_path<P>(at: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    match at.as_ref().extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("raw") || ext.eq_ignore_ascii_case("pcm"),
+        None => false,
+    }
+}
+
+/// Opens `at` as headerless PCM if it has a `.raw`/`.pcm` extension (`raw` must then describe the
+/// format), otherwise as a WAV file (with `strict`, see `WavFile::open_strict`)
+pub fn open_audio<P>(
+    at: P,
+    buf_size: usize,
+    raw: Option<RawPcmFormat>,
+    strict: bool,
+) -> Result<WavFile>
+where
+    P: AsRef<Path>,
+{
+    let at = at.as_ref();
+    if is_raw_pcm_path(at) {
+        let format = raw.ok_or_else(|| {
+            anyhow!(
+                "{} is headerless PCM, specify --sample-rate, --channels and --bits",
+                at.display()
+            )
+        })?;
+        WavFile::open_raw(at, buf_size, format)
+    } else if strict {
+        WavFile::open_strict(at, buf_size)
+    } else {
+        WavFile::open(at, buf_size)
+    }
+}
+
 impl WavFile {
+    /// Opens a WAV file, a RIFF size or data chunk size which doesn't match the file length (a
+    /// truncated file) is a warning and only the samples actually in the file are read
     pub fn open<P>(at: P, buf_size: usize) -> Result<WavFile>
     where
         P: AsRef<Path>,
     {
-        let f = File::open(at)?;
+        Self::open_checked(at, buf_size, false)
+    }
+
+    /// Like `open`, but a file length which doesn't match the sizes in the file is an error
+    pub fn open_strict<P>(at: P, buf_size: usize) -> Result<WavFile>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_checked(at, buf_size, true)
+    }
+
+    fn open_checked<P>(at: P, buf_size: usize, strict: bool) -> Result<WavFile>
+    where
+        P: AsRef<Path>,
+    {
+        let f = open_file(at)?;
+        let file_len = f.metadata()?.len();
         let mut f = BufReader::with_capacity(buf_size, f);
         let mut buf = [0u8; 8];
 
@@ -123,45 +258,155 @@ impl WavFile {
             "RIFF" => ByteOrdering::LittleEndian,
             "RIFX" => ByteOrdering::BigEndian,
             other => {
-                return Err(anyhow!("invalid chunk id {}", other));
+                return Err(VizError::UnsupportedFormat(format!(
+                    "not a WAV file, starts with {:?}",
+                    other
+                ))
+                .into());
             }
         };
-        // skip chunk size
-        f.seek(SeekFrom::Current(4))?;
-        check_str_tag(&mut f, "WAVE", &mut buf[..])?;
-        seek_to_chunk(&mut f, &ordering, "fmt ", &mut buf[..])?;
-
-        match ordering.read_u16(&mut f, &mut buf[..])? {
-            0x01 => {}
-            other => {
-                return Err(anyhow!("not PCM audio data, got format id {}", other));
+        // the RIFF size counts everything after the id and the size itself
+        let riff_end = (ordering.read_u32(&mut f, &mut buf[..])? as u64) + 8;
+        if riff_end != file_len {
+            let msg = format!(
+                "RIFF size says the file is {} bytes, but it is {} bytes",
+                riff_end, file_len
+            );
+            if strict {
+                return Err(anyhow!(msg));
             }
+
+            eprintln!("[warn] {}", msg);
+        }
+        // never scan for chunks past the end of the file (streaming writers may leave the RIFF
+        // size at 0, in which case only the file length counts)
+        let end = if riff_end > 12 {
+            cmp::min(riff_end, file_len)
+        } else {
+            file_len
+        };
+        check_str_tag(&mut f, "WAVE", &mut buf[..])?;
+        let fmt_len = seek_to_chunk(&mut f, &ordering, "fmt ", &mut buf[..], end)?;
+        if fmt_len < FMT_PCM_LEN {
+            return Err(anyhow!(
+                "fmt chunk too short, need at least {} bytes, got {}",
+                FMT_PCM_LEN,
+                fmt_len
+            ));
         }
 
+        let format_tag = ordering.read_u16(&mut f, &mut buf[..])?;
         let num_channels = ordering.read_u16(&mut f, &mut buf[..])?;
         let sample_rate = ordering.read_u32(&mut f, &mut buf[..])?;
         let _ = ordering.read_u32(&mut f, &mut buf[..])?;
         let block_align = ordering.read_u16(&mut f, &mut buf[..])?;
         let bits_per_sample = ordering.read_u16(&mut f, &mut buf[..])?;
+        let (format_tag, fmt_read) = if format_tag == WAVE_FORMAT_EXTENSIBLE {
+            if fmt_len < FMT_EXTENSIBLE_LEN {
+                return Err(anyhow!(
+                    "extensible fmt chunk too short, need at least {} bytes, got {}",
+                    FMT_EXTENSIBLE_LEN,
+                    fmt_len
+                ));
+            }
 
-        let len = seek_to_chunk(&mut f, &ordering, "data", &mut buf[..])?;
-        let num_samples = len / (block_align as usize);
+            (read_sub_format(&mut f, &ordering)?, FMT_EXTENSIBLE_LEN)
+        } else {
+            (format_tag, FMT_PCM_LEN)
+        };
+        if format_tag != WAVE_FORMAT_PCM && format_tag != WAVE_FORMAT_IEEE_FLOAT {
+            return Err(VizError::UnsupportedFormat(format!(
+                "not PCM or IEEE float audio data, got format id {}",
+                format_tag
+            ))
+            .into());
+        }
+
+        check_supported(format_tag, num_channels, bits_per_sample)?;
+        // skip the rest of any extension (cbSize and friends) and the pad byte so we start
+        // scanning at the next chunk id
+        let skip = fmt_len - fmt_read + (fmt_len & 1);
+        f.seek(SeekFrom::Current(skip as i64))?;
+
+        let len = seek_to_chunk(&mut f, &ordering, "data", &mut buf[..], end)?;
         let data_starts_at = f.seek(SeekFrom::Current(0))?;
+        let available = (file_len - data_starts_at) as usize;
+        let len = if len > available {
+            let msg = format!(
+                "data chunk is {} bytes, but only {} bytes are left in the file (truncated?)",
+                len, available
+            );
+            if strict {
+                return Err(anyhow!(msg));
+            }
+
+            eprintln!("[warn] {}", msg);
+            available
+        } else {
+            len
+        };
+        if block_align == 0 {
+            return Err(anyhow!("block align must not be 0"));
+        }
+        let num_samples = len / (block_align as usize);
 
         Ok(Self {
             ordering,
+            format_tag,
             sample_rate,
             num_channels,
             bits_per_sample,
             num_samples,
             block_align,
             f,
+            #[cfg(any(feature = "gui", test))]
             data_starts_at,
             sample_at: 0,
         })
     }
 
+    /// Opens a headerless PCM file, all of the file is sample data in the given `format`
+    pub fn open_raw<P>(at: P, buf_size: usize, format: RawPcmFormat) -> Result<WavFile>
+    where
+        P: AsRef<Path>,
+    {
+        let RawPcmFormat {
+            sample_rate,
+            num_channels,
+            bits_per_sample,
+        } = format;
+        check_supported(WAVE_FORMAT_PCM, num_channels, bits_per_sample)?;
+        if sample_rate == 0 {
+            return Err(anyhow!("sample rate must be positive"));
+        }
+
+        let f = open_file(at)?;
+        let len = f.metadata()?.len() as usize;
+        let block_align = num_channels * (bits_per_sample / 8);
+
+        Ok(Self {
+            ordering: ByteOrdering::LittleEndian,
+            format_tag: WAVE_FORMAT_PCM,
+            sample_rate,
+            num_channels,
+            bits_per_sample,
+            num_samples: len / (block_align as usize),
+            block_align,
+            f: BufReader::with_capacity(buf_size, f),
+            #[cfg(any(feature = "gui", test))]
+            data_starts_at: 0,
+            sample_at: 0,
+        })
+    }
+
     fn read_one_channel_sample(&mut self) -> Result<SampleRaw> {
+        if self.format_tag == WAVE_FORMAT_IEEE_FLOAT {
+            let mut buf = [0u8; 4];
+            self.f.read_exact(&mut buf[..])?;
+            let (raw_sample, _) = self.ordering.f32_from(&buf[..4])?;
+            return Ok(SampleRaw::Float(raw_sample));
+        }
+
         match self.bits_per_sample {
             8 => {
                 let mut buf = [0u8; 1];
@@ -176,9 +421,16 @@ impl WavFile {
                 let sample = SampleRaw::TwoBytes(raw_sample);
                 Ok(sample)
             }
+            24 => {
+                let mut buf = [0u8; 3];
+                self.f.read_exact(&mut buf[..])?;
+                let (raw_sample, _) = self.ordering.i24_from(&buf[..3])?;
+                let sample = SampleRaw::ThreeBytes(raw_sample);
+                Ok(sample)
+            }
             other => {
                 return Err(anyhow!(
-                    "bits per sample must be 8 or 16, no support for other formats (got {})!",
+                    "bits per sample must be 8, 16 or 24, no support for other formats (got {})!",
                     other
                 ));
             }
@@ -206,6 +458,22 @@ impl Samples<Channeled<SampleRaw>, WavFile> for WavFile {
         Ok(())
     }
 
+    #[cfg(any(feature = "gui", test))]
+    fn sample_position(&self) -> usize {
+        self.sample_at
+    }
+
+    // straight to the byte offset of the sample
+    #[cfg(any(feature = "gui", test))]
+    fn seek_to_sample(&mut self, abs: usize) -> Result<()> {
+        let abs = std::cmp::min(abs, self.num_samples);
+        let byte_offset = (abs as u64) * (self.block_align as u64);
+        self.f
+            .seek(SeekFrom::Start(self.data_starts_at + byte_offset))?;
+        self.sample_at = abs;
+        Ok(())
+    }
+
     fn next_sample(&mut self) -> Result<Option<Channeled<SampleRaw>>, Error> {
         if !self.has_more_samples() {
             return Ok(None);
@@ -217,9 +485,11 @@ impl Samples<Channeled<SampleRaw>, WavFile> for WavFile {
                 self.read_one_channel_sample()?,
                 self.read_one_channel_sample()?,
             ),
-            other => {
-                return Err(anyhow!("bad number of channels (unsupported): {}", other));
-            }
+            n => Channeled::Multi(
+                (0..n)
+                    .map(|_| self.read_one_channel_sample())
+                    .collect::<Result<_>>()?,
+            ),
         };
 
         self.sample_at += 1;
@@ -238,7 +508,7 @@ impl Sampled for WavFile {
     }
 
     fn num_samples(&self) -> usize {
-        self.num_samples as usize
+        self.num_samples
     }
 }
 
@@ -246,25 +516,118 @@ impl AudioSource for WavFile {
     fn num_channels(&self) -> usize {
         self.num_channels as usize
     }
+
+    fn sample_format(&self) -> SampleFormat {
+        let bits = self.bits_per_sample;
+        match self.format_tag {
+            WAVE_FORMAT_IEEE_FLOAT => SampleFormat::Float { bits },
+            _ => SampleFormat::Pcm { bits },
+        }
+    }
+}
+
+fn open_file<P>(at: P) -> Result<File>
+where
+    P: AsRef<Path>,
+{
+    let at = at.as_ref();
+    File::open(at).with_context(|| format!("could not open {}", at.display()))
 }
 
+// reads the WAVE_FORMAT_EXTENSIBLE extension after the common fmt fields, returns the format id
+// of its sub format GUID
+fn read_sub_format<R>(reader: &mut R, ordering: &ByteOrdering) -> Result<u16>
+where
+    R: Read,
+{
+    let mut buf = [0u8; 8];
+    // cbSize, the valid bits (always within bits_per_sample) and the speaker positions
+    let _ = ordering.read_u16(reader, &mut buf[..])?;
+    let _ = ordering.read_u16(reader, &mut buf[..])?;
+    let _ = ordering.read_u32(reader, &mut buf[..])?;
+
+    let format_id = ordering.read_u32(reader, &mut buf[..])?;
+    let data2 = ordering.read_u16(reader, &mut buf[..])?;
+    let data3 = ordering.read_u16(reader, &mut buf[..])?;
+    reader.read_exact(&mut buf[..])?;
+    if data2 != 0x0000 || data3 != 0x0010 || buf != SUB_FORMAT_GUID_TAIL || format_id > 0xFFFF {
+        return Err(VizError::UnsupportedFormat(
+            "extensible WAV with a sub format which is not PCM or IEEE float".to_string(),
+        )
+        .into());
+    }
+
+    Ok(format_id as u16)
+}
+
+fn check_supported(format_tag: u16, num_channels: u16, bits_per_sample: u16) -> Result<()> {
+    if format_tag == WAVE_FORMAT_IEEE_FLOAT && bits_per_sample != 32 {
+        return Err(VizError::UnsupportedFormat(format!(
+            "IEEE float samples must be 32 bits, got {}",
+            bits_per_sample
+        ))
+        .into());
+    }
+
+    if format_tag == WAVE_FORMAT_PCM
+        && bits_per_sample != 8
+        && bits_per_sample != 16
+        && bits_per_sample != 24
+    {
+        return Err(VizError::UnsupportedFormat(format!(
+            "bits per sample must be 8, 16 or 24, got {}",
+            bits_per_sample
+        ))
+        .into());
+    }
+
+    // Channeled::to_mono counts the channels in a u8, and SDL opens at most 255 channels too
+    if num_channels == 0 || num_channels > (u8::MAX as u16) {
+        return Err(VizError::UnsupportedFormat(format!(
+            "channels must be 1 to 255, got {}",
+            num_channels
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+// scans chunks until `end` (a byte offset), a chunk size pointing past it means the file is corrupt
 fn seek_to_chunk<R>(
     reader: &mut R,
     ordering: &ByteOrdering,
     id: &str,
     buf: &mut [u8],
+    end: u64,
 ) -> Result<usize>
 where
     R: Read + Seek,
 {
     loop {
+        let at = reader.stream_position()?;
+        if at + 8 > end {
+            return Err(anyhow!("no {} chunk before the end of the file", id.trim()));
+        }
+
         let chunk_id = read_str_exact(reader, &mut buf[..id.len()])?;
         if chunk_id == id {
             return Ok(ordering.read_u32(reader, &mut buf[..])? as usize);
         }
 
-        let seek_by = SeekFrom::Current((ordering.read_u32(reader, &mut buf[..])?) as i64);
-        reader.seek(seek_by)?;
+        let chunk_id = chunk_id.to_string();
+        let chunk_len = ordering.read_u32(reader, &mut buf[..])? as u64;
+        if at + 8 + chunk_len > end {
+            return Err(anyhow!(
+                "chunk {:?} at byte {} is {} bytes long, past the end of the file",
+                chunk_id,
+                at,
+                chunk_len
+            ));
+        }
+
+        // chunks are word aligned, an odd sized chunk is followed by a pad byte
+        reader.seek(SeekFrom::Current((chunk_len + (chunk_len & 1)) as i64))?;
     }
 }
 
@@ -304,8 +667,152 @@ where
 
 #[cfg(test)]
 pub mod tests {
-    use crate::framed::{Sampled, Samples};
-    use crate::wav::WavFile;
+    use crate::channeled::Channeled;
+    use crate::framed::{AudioSource, SampleFormat, Sampled, Samples};
+    use crate::wav::{
+        open_audio, RawPcmFormat, SampleRaw, WavFile, SUB_FORMAT_GUID_TAIL, WAVE_FORMAT_EXTENSIBLE,
+    };
+    use std::path::PathBuf;
+
+    /// Builds the bytes of a small WAV file for tests
+    #[derive(Clone)]
+    pub struct WavBuilder {
+        pub format_tag: u16,
+        pub num_channels: u16,
+        pub sample_rate: u32,
+        pub bits_per_sample: u16,
+        pub fmt_extra: Vec<u8>,
+        pub data: Vec<u8>,
+        pub big_endian: bool,
+    }
+
+    impl WavBuilder {
+        pub fn pcm(num_channels: u16, sample_rate: u32, bits_per_sample: u16) -> Self {
+            Self {
+                format_tag: 0x01,
+                num_channels,
+                sample_rate,
+                bits_per_sample,
+                fmt_extra: Vec::new(),
+                data: Vec::new(),
+                big_endian: false,
+            }
+        }
+
+        /// a big endian (RIFX) file, `data` is written as given
+        pub fn big_endian(self) -> Self {
+            Self {
+                big_endian: true,
+                ..self
+            }
+        }
+
+        pub fn fmt_extra(mut self, extra: &[u8]) -> Self {
+            self.fmt_extra = extra.to_vec();
+            self
+        }
+
+        pub fn data(mut self, data: Vec<u8>) -> Self {
+            self.data = data;
+            self
+        }
+
+        pub fn float(num_channels: u16, sample_rate: u32) -> Self {
+            Self {
+                format_tag: 0x03,
+                ..Self::pcm(num_channels, sample_rate, 32)
+            }
+        }
+
+        /// the same format as a WAVE_FORMAT_EXTENSIBLE header, with `channel_mask` as the speaker
+        /// positions
+        pub fn extensible(self, channel_mask: u32) -> Self {
+            let mut extra = Vec::new();
+            extra.extend_from_slice(&22u16.to_le_bytes());
+            extra.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+            extra.extend_from_slice(&channel_mask.to_le_bytes());
+            extra.extend_from_slice(&(self.format_tag as u32).to_le_bytes());
+            extra.extend_from_slice(&[0x00, 0x00, 0x10, 0x00]);
+            extra.extend_from_slice(&SUB_FORMAT_GUID_TAIL);
+            Self {
+                format_tag: WAVE_FORMAT_EXTENSIBLE,
+                ..self.fmt_extra(&extra)
+            }
+        }
+
+        pub fn samples_f32(self, samples: &[f32]) -> Self {
+            self.data(
+                samples
+                    .iter()
+                    .flat_map(|s| s.to_le_bytes().to_vec())
+                    .collect(),
+            )
+        }
+
+        pub fn samples_i16(self, samples: &[i16]) -> Self {
+            self.data(
+                samples
+                    .iter()
+                    .flat_map(|s| s.to_le_bytes().to_vec())
+                    .collect(),
+            )
+        }
+
+        pub fn bytes(&self) -> Vec<u8> {
+            let u16_bytes = |v: u16| {
+                if self.big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                }
+            };
+            let u32_bytes = |v: u32| {
+                if self.big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                }
+            };
+            let block_align = self.num_channels * (self.bits_per_sample / 8);
+            let mut fmt = Vec::new();
+            fmt.extend_from_slice(&u16_bytes(self.format_tag));
+            fmt.extend_from_slice(&u16_bytes(self.num_channels));
+            fmt.extend_from_slice(&u32_bytes(self.sample_rate));
+            fmt.extend_from_slice(&u32_bytes(self.sample_rate * block_align as u32));
+            fmt.extend_from_slice(&u16_bytes(block_align));
+            fmt.extend_from_slice(&u16_bytes(self.bits_per_sample));
+            fmt.extend_from_slice(&self.fmt_extra);
+
+            let mut body = b"WAVE".to_vec();
+            for (id, data) in &[(b"fmt ", &fmt), (b"data", &self.data)] {
+                body.extend_from_slice(*id);
+                body.extend_from_slice(&u32_bytes(data.len() as u32));
+                body.extend_from_slice(data);
+            }
+
+            let mut out = if self.big_endian {
+                b"RIFX".to_vec()
+            } else {
+                b"RIFF".to_vec()
+            };
+            out.extend_from_slice(&u32_bytes(body.len() as u32));
+            out.extend(body);
+            out
+        }
+
+        /// writes the file to a temp location unique to this test process
+        pub fn write(&self, name: &str) -> PathBuf {
+            write_bytes(&format!("{}.wav", name), &self.bytes())
+        }
+    }
+
+    /// writes `bytes` to a temp location unique to this test process
+    pub fn write_bytes(name: &str, bytes: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("vis-rs-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).expect("should write test file");
+        path
+    }
 
     #[test]
     fn open_wav_file() {
@@ -328,4 +835,345 @@ pub mod tests {
         }
         println!("done!");
     }
+
+    #[test]
+    fn open_fmt_chunk_with_extension() {
+        // cbSize = 0, but the 2 extra bytes would otherwise be read as part of the next chunk id
+        let path = WavBuilder::pcm(1, 8000, 16)
+            .fmt_extra(&[0, 0])
+            .samples_i16(&[1, -2, 3])
+            .write("fmt-ext");
+        let mut file = WavFile::open(&path, 64).expect("should open");
+        assert_eq!(file.num_samples(), 3);
+        assert_eq!(file.sample_rate(), 8000);
+        let mut samples = Vec::new();
+        while let Some(sample) = file.next_sample().expect("should read") {
+            samples.push(sample);
+        }
+
+        assert_eq!(
+            samples,
+            vec![
+                Channeled::Mono(SampleRaw::TwoBytes(1)),
+                Channeled::Mono(SampleRaw::TwoBytes(-2)),
+                Channeled::Mono(SampleRaw::TwoBytes(3)),
+            ]
+        );
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn seek_to_absolute_sample() {
+        let samples = (0..64).map(|v| v * 100).collect::<Vec<i16>>();
+        let path = WavBuilder::pcm(2, 8000, 16)
+            .samples_i16(&samples)
+            .write("seek-abs");
+        let mut file = WavFile::open(&path, 16).expect("should open");
+        let mut sequential = Vec::new();
+        while let Some(sample) = file.next_sample().expect("should read") {
+            sequential.push(sample);
+        }
+        assert_eq!(sequential.len(), 32);
+
+        file.seek_to_sample(20).expect("should seek");
+        assert_eq!(file.sample_position(), 20);
+        assert_eq!(
+            file.next_sample().expect("should read"),
+            Some(sequential[20].clone())
+        );
+
+        file.seek_to_sample(3).expect("should seek");
+        assert_eq!(
+            file.next_sample().expect("should read"),
+            Some(sequential[3].clone())
+        );
+        assert_eq!(file.sample_position(), 4);
+
+        // past the end is clamped to the end
+        file.seek_to_sample(1000).expect("should seek");
+        assert_eq!(file.sample_position(), 32);
+        assert_eq!(file.next_sample().expect("should read"), None);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn reports_sample_format() {
+        let path = WavBuilder::pcm(1, 8000, 8)
+            .data(vec![128, 0, 255])
+            .write("format-8");
+        let file = WavFile::open(&path, 64).expect("should open");
+        assert_eq!(file.sample_format(), SampleFormat::Pcm { bits: 8 });
+        std::fs::remove_file(path).ok();
+
+        let path = WavBuilder::pcm(2, 44100, 16)
+            .samples_i16(&[1, -1])
+            .write("format-16");
+        let file = WavFile::open(&path, 64).expect("should open");
+        assert_eq!(file.sample_format(), SampleFormat::Pcm { bits: 16 });
+        assert_eq!(file.sample_format().to_string(), "16 bit PCM");
+        std::fs::remove_file(path).ok();
+
+        let path = WavBuilder::float(1, 48000)
+            .samples_f32(&[0.25, -1.0])
+            .write("format-float");
+        let mut file = WavFile::open(&path, 64).expect("should open");
+        assert_eq!(file.sample_format(), SampleFormat::Float { bits: 32 });
+        assert_eq!(file.sample_format().to_string(), "32 bit float");
+        assert_eq!(
+            file.next_sample().expect("should read"),
+            Some(Channeled::Mono(SampleRaw::Float(0.25)))
+        );
+        std::fs::remove_file(path).ok();
+
+        // float samples are always 32 bits
+        let mut builder = WavBuilder::float(1, 48000).samples_f32(&[0.25]);
+        builder.bits_per_sample = 16;
+        let path = builder.write("format-float-16");
+        assert!(WavFile::open(&path, 64).is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn two_byte_samples_are_centered() {
+        let min: f64 = SampleRaw::TwoBytes(-32768).into();
+        let zero: f64 = SampleRaw::TwoBytes(0).into();
+        let max: f64 = SampleRaw::TwoBytes(i16::MAX).into();
+        assert_eq!(min, -1.0);
+        assert_eq!(zero, 0.0);
+        assert_eq!(max, 32767.0 / 32768.0);
+    }
+
+    #[test]
+    fn decode_four_channel_samples() {
+        let path = WavBuilder::pcm(4, 8000, 16)
+            .samples_i16(&[1, 2, 3, 4, -8, 0, 8, 16384])
+            .write("quad");
+        let mut file = WavFile::open(&path, 64).expect("should open");
+        std::fs::remove_file(path).ok();
+        assert_eq!(file.num_channels(), 4);
+        assert_eq!(file.num_samples(), 2);
+
+        let first = file.next_sample().expect("should read");
+        assert_eq!(
+            first,
+            Some(Channeled::Multi(
+                [1, 2, 3, 4]
+                    .iter()
+                    .map(|v| SampleRaw::TwoBytes(*v))
+                    .collect()
+            ))
+        );
+        let second = file.next_sample().expect("should read").expect("some");
+        let mono: f64 = second.map(|v| -> f64 { v.into() }).to_mono();
+        assert_eq!(mono, 0.125);
+        assert_eq!(file.next_sample().expect("should read"), None);
+    }
+
+    #[test]
+    fn decode_extensible_quad_samples() {
+        // front left/right and back left/right
+        let path = WavBuilder::pcm(4, 8000, 16)
+            .samples_i16(&[1, 2, 3, 4, -8, 0, 8, 16384])
+            .extensible(0x33)
+            .write("quad-extensible");
+        let mut file = WavFile::open(&path, 64).expect("should open");
+        std::fs::remove_file(path).ok();
+        assert_eq!(file.num_channels(), 4);
+        assert_eq!(file.num_samples(), 2);
+        assert_eq!(file.sample_format(), SampleFormat::Pcm { bits: 16 });
+        assert_eq!(
+            file.next_sample().expect("should read"),
+            Some(Channeled::Multi(
+                [1, 2, 3, 4]
+                    .iter()
+                    .map(|v| SampleRaw::TwoBytes(*v))
+                    .collect()
+            ))
+        );
+
+        let path = WavBuilder::float(1, 8000)
+            .samples_f32(&[0.5])
+            .extensible(0x4)
+            .write("float-extensible");
+        let mut file = WavFile::open(&path, 64).expect("should open");
+        std::fs::remove_file(path).ok();
+        assert_eq!(file.sample_format(), SampleFormat::Float { bits: 32 });
+        assert_eq!(
+            file.next_sample().expect("should read"),
+            Some(Channeled::Mono(SampleRaw::Float(0.5)))
+        );
+
+        // any other sub format (here ADPCM) is not supported
+        let mut builder = WavBuilder::pcm(4, 8000, 16).samples_i16(&[0; 4]);
+        builder.format_tag = 0x02;
+        let path = builder.extensible(0x33).write("adpcm-extensible");
+        let err = WavFile::open(&path, 64).expect_err("should not open");
+        std::fs::remove_file(path).ok();
+        assert!(err.to_string().contains("format id 2"), "{}", err);
+    }
+
+    #[test]
+    fn decode_24_bit_samples() {
+        let values = [0, 1, -1, 4_000_000, -4_000_000, (1 << 23) - 1, -(1 << 23)];
+        let expected = values
+            .iter()
+            .map(|v| Channeled::Mono(SampleRaw::ThreeBytes(*v)))
+            .collect::<Vec<_>>();
+        let read_all = |mut file: WavFile| {
+            assert_eq!(file.sample_format(), SampleFormat::Pcm { bits: 24 });
+            let mut samples = Vec::new();
+            while let Some(sample) = file.next_sample().expect("should read") {
+                samples.push(sample);
+            }
+            samples
+        };
+
+        let path = WavBuilder::pcm(1, 8000, 24)
+            .data(
+                values
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes()[..3].to_vec())
+                    .collect(),
+            )
+            .write("pcm-24");
+        let samples = read_all(WavFile::open(&path, 64).expect("should open"));
+        std::fs::remove_file(path).ok();
+        assert_eq!(samples, expected);
+
+        // the same samples in a big endian (RIFX) file
+        let path = WavBuilder::pcm(1, 8000, 24)
+            .big_endian()
+            .data(
+                values
+                    .iter()
+                    .flat_map(|v| v.to_be_bytes()[1..].to_vec())
+                    .collect(),
+            )
+            .write("pcm-24-be");
+        let samples = read_all(WavFile::open(&path, 64).expect("should open"));
+        std::fs::remove_file(path).ok();
+        assert_eq!(samples, expected);
+
+        // full scale is -1.0..1.0
+        for sample in samples {
+            let v = sample.map(|v| -> f64 { v.into() }).to_mono();
+            assert!((-1.0..1.0).contains(&v), "{}", v);
+        }
+        let min: f64 = SampleRaw::ThreeBytes(-(1 << 23)).into();
+        assert_eq!(min, -1.0);
+        assert!(SampleRaw::ThreeBytes(-(1 << 23)).is_full_scale());
+    }
+
+    #[test]
+    fn decode_raw_pcm_mono() {
+        let data = [0i16, 1000, -1000, i16::MAX, i16::MIN]
+            .iter()
+            .flat_map(|s| s.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        let path = write_bytes("raw-mono.raw", &data);
+
+        assert!(open_audio(&path, 64, None, false).is_err());
+        let format = RawPcmFormat {
+            sample_rate: 8000,
+            num_channels: 1,
+            bits_per_sample: 16,
+        };
+        let mut file = open_audio(&path, 64, Some(format), false).expect("should open");
+        assert_eq!(file.num_samples(), 5);
+        assert_eq!(file.sample_rate(), 8000);
+        assert_eq!(file.sample_format(), SampleFormat::Pcm { bits: 16 });
+
+        let mut samples = Vec::new();
+        while let Some(sample) = file.next_sample().expect("should read") {
+            samples.push(sample);
+        }
+        assert_eq!(
+            samples,
+            vec![
+                Channeled::Mono(SampleRaw::TwoBytes(0)),
+                Channeled::Mono(SampleRaw::TwoBytes(1000)),
+                Channeled::Mono(SampleRaw::TwoBytes(-1000)),
+                Channeled::Mono(SampleRaw::TwoBytes(i16::MAX)),
+                Channeled::Mono(SampleRaw::TwoBytes(i16::MIN)),
+            ]
+        );
+
+        file.seek_to_sample(2).expect("should seek");
+        assert_eq!(
+            file.next_sample().expect("should read"),
+            Some(Channeled::Mono(SampleRaw::TwoBytes(-1000)))
+        );
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn riff_size_past_end_of_file() {
+        let mut bytes = WavBuilder::pcm(1, 8000, 16)
+            .samples_i16(&[1, 2, 3, 4])
+            .bytes();
+        let riff_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        bytes[4..8].copy_from_slice(&(riff_size + 1000).to_le_bytes());
+        // the data chunk claims more samples than there are too
+        let data_len_at = bytes.len() - 8 - 4;
+        bytes[data_len_at..(data_len_at + 4)].copy_from_slice(&1008u32.to_le_bytes());
+        let path = write_bytes("riff-size.wav", &bytes);
+
+        assert!(WavFile::open_strict(&path, 64).is_err());
+        assert!(open_audio(&path, 64, None, true).is_err());
+        let mut file = WavFile::open(&path, 64).expect("should open with a warning");
+        assert_eq!(file.num_samples(), 4);
+        let mut n = 0;
+        while file.next_sample().expect("should read").is_some() {
+            n += 1;
+        }
+        assert_eq!(n, 4);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn corrupt_chunk_size_is_an_error() {
+        // an unknown chunk before fmt whose size points far past the end of the file
+        let wav = WavBuilder::pcm(1, 8000, 16).samples_i16(&[1, 2]).bytes();
+        let mut bytes = wav[..12].to_vec();
+        bytes.extend_from_slice(b"junk");
+        bytes.extend_from_slice(&0x7fff_0000u32.to_le_bytes());
+        bytes.extend_from_slice(&wav[12..]);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        let path = write_bytes("bad-chunk.wav", &bytes);
+
+        assert!(WavFile::open(&path, 64).is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn odd_sized_chunk_is_padded() {
+        // an odd sized LIST chunk (and its pad byte) between fmt and data
+        let wav = WavBuilder::pcm(1, 8000, 16)
+            .samples_i16(&[1, -2, 3])
+            .bytes();
+        let mut bytes = wav[..36].to_vec();
+        bytes.extend_from_slice(b"LIST");
+        bytes.extend_from_slice(&9u32.to_le_bytes());
+        bytes.extend_from_slice(b"INFOabcde\0");
+        bytes.extend_from_slice(&wav[36..]);
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        let path = write_bytes("odd-chunk.wav", &bytes);
+
+        let mut file = WavFile::open(&path, 64).expect("should open");
+        let mut samples = Vec::new();
+        while let Some(sample) = file.next_sample().expect("should read") {
+            samples.push(sample);
+        }
+        std::fs::remove_file(path).ok();
+        assert_eq!(
+            samples,
+            vec![
+                Channeled::Mono(SampleRaw::TwoBytes(1)),
+                Channeled::Mono(SampleRaw::TwoBytes(-2)),
+                Channeled::Mono(SampleRaw::TwoBytes(3)),
+            ]
+        );
+    }
 }
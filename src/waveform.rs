@@ -0,0 +1,114 @@
+use crate::channeled::Channeled;
+use crate::framed::FramedMapper;
+use crate::util::VizFloat;
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// Shared handle to the most recent time-domain frame seen by a `WaveformTapMapper`.
+///
+/// The pipeline owns the mapper, the renderer keeps a clone of the tap and reads the latest frame
+/// after every `next_frame` call.
+#[derive(Clone, Default)]
+pub struct WaveformTap {
+    latest: Arc<Mutex<Vec<VizFloat>>>,
+}
+
+impl WaveformTap {
+    #[cfg(any(feature = "gui", test))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mapper(&self) -> WaveformTapMapper {
+        WaveformTapMapper {
+            latest: self.latest.clone(),
+        }
+    }
+
+    #[cfg(any(feature = "gui", test))]
+    pub fn with_latest<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&[VizFloat]) -> R,
+    {
+        let latest = self.latest.lock().expect("waveform tap poisoned");
+        f(latest.as_slice())
+    }
+}
+
+pub struct WaveformTapMapper {
+    latest: Arc<Mutex<Vec<VizFloat>>>,
+}
+
+impl FramedMapper<Channeled<VizFloat>, Channeled<VizFloat>> for WaveformTapMapper {
+    fn map<'a>(
+        &'a mut self,
+        input: &'a mut [Channeled<VizFloat>],
+    ) -> Result<Option<&'a mut [Channeled<VizFloat>]>> {
+        let mut latest = self.latest.lock().expect("waveform tap poisoned");
+        latest.clear();
+        latest.extend(input.iter().map(move |c| c.to_mono()));
+
+        Ok(Some(input))
+    }
+}
+
+/// Maps a frame of samples (expected in -1..1) to a polyline spanning `width` x `height`, with 0.0
+/// on the vertical center. At most one point per horizontal pixel is produced.
+#[cfg(any(feature = "gui", test))]
+pub fn waveform_points(samples: &[VizFloat], width: u32, height: u32) -> Vec<(i32, i32)> {
+    if samples.is_empty() || width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let n_points = std::cmp::min(samples.len(), width as usize);
+    let half_height = (height as VizFloat) / 2.0;
+    let max_x = (width - 1) as VizFloat;
+    let x_step = if n_points > 1 {
+        max_x / ((n_points - 1) as VizFloat)
+    } else {
+        0.0
+    };
+
+    (0..n_points)
+        .map(move |i| {
+            let v = samples[(i * samples.len()) / n_points].clamp(-1.0, 1.0);
+            let x = ((i as VizFloat) * x_step).round() as i32;
+            let y = (half_height - (v * half_height)).round() as i32;
+            (x, y.min((height - 1) as i32))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::channeled::Channeled;
+    use crate::framed::FramedMapper;
+    use crate::waveform::{waveform_points, WaveformTap};
+
+    #[test]
+    fn waveform_points_span_canvas() {
+        let points = waveform_points(&[0.0, 1.0, 0.0, -1.0, 0.0], 101, 201);
+        assert_eq!(
+            points,
+            vec![(0, 101), (25, 0), (50, 101), (75, 200), (100, 101)]
+        );
+    }
+
+    #[test]
+    fn waveform_points_downsample_to_width() {
+        let samples = vec![0.5; 1000];
+        let points = waveform_points(&samples, 10, 100);
+        assert_eq!(points.len(), 10);
+        assert_eq!(points.first(), Some(&(0, 25)));
+        assert_eq!(points.last(), Some(&(9, 25)));
+    }
+
+    #[test]
+    fn tap_keeps_latest_frame_as_mono() {
+        let tap = WaveformTap::new();
+        let mut mapper = tap.mapper();
+        let mut frame = vec![Channeled::Stereo(1.0, 0.0), Channeled::Stereo(-1.0, -0.5)];
+        mapper.map(&mut frame).expect("should map");
+        tap.with_latest(|latest| assert_eq!(latest, &[0.5, -0.75]));
+    }
+}
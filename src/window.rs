@@ -3,6 +3,7 @@ use crate::framed::FramedMapper;
 use crate::util::{log_timed, VizFloat};
 use anyhow::Result;
 use itertools::Itertools;
+use std::f64::consts::TAU;
 
 pub trait WindowingFunction {
     fn coefficient(idx: VizFloat, count: VizFloat) -> VizFloat;
@@ -31,7 +32,6 @@ pub struct BlackmanNuttall;
 
 impl WindowingFunction for BlackmanNuttall {
     fn coefficient(idx: VizFloat, count: VizFloat) -> VizFloat {
-        const TAU: VizFloat = 6.28318530717958647692528676655900577;
         const A0: VizFloat = 0.3635819;
         const A1: VizFloat = 0.4891775;
         const A2: VizFloat = 0.1365995;
@@ -39,6 +39,11 @@ impl WindowingFunction for BlackmanNuttall {
         const FOURPI: VizFloat = TAU * 2.0;
         const SIXPI: VizFloat = FOURPI + TAU;
 
+        // a window of one sample has nothing to taper (and would divide by zero)
+        if count < 2.0 {
+            return 1.0;
+        }
+
         let count_minus_one = count - 1.0;
         let a1t = A1 * VizFloat::cos((TAU * idx) / count_minus_one);
         let a2t = A2 * VizFloat::cos((FOURPI * idx) / count_minus_one);
@@ -48,6 +53,20 @@ impl WindowingFunction for BlackmanNuttall {
     }
 }
 
+#[derive(Copy, Clone)]
+pub struct Hann;
+
+impl WindowingFunction for Hann {
+    fn coefficient(idx: VizFloat, count: VizFloat) -> VizFloat {
+        // like BlackmanNuttall, a window of one sample is left as is
+        if count < 2.0 {
+            return 1.0;
+        }
+
+        0.5 - (0.5 * VizFloat::cos((TAU * idx) / (count - 1.0)))
+    }
+}
+
 pub struct MemoizedWindowingMapper {
     coefficients: Vec<VizFloat>,
 }